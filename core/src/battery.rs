@@ -0,0 +1,216 @@
+//! Battery voltage and state of charge.
+//!
+//! The ADC measures against VDDA, which follows the LDO and sags with the battery, so the battery
+//! reading is scaled by a reading of the internal reference VREFINT, whose value at 3.3V is
+//! calibrated in the factory. The voltage of a LiPo cell is far from linear in its charge, a lookup
+//! curve maps it to percent.
+//!
+//! The tubes draw enough to make the voltage drop while they are lit, so the state only changes
+//! once the charge is a few percent past a threshold, otherwise the watch would flip between the
+//! time and the empty battery with every wake.
+
+/// VDDA the factory calibration of VREFINT was done at
+pub const CALIBRATION_MV: u32 = 3300;
+
+/// Highest VDDA the STM32 takes, bad VREFINT readings can't claim more
+const MAX_VDDA_MV: u32 = 3600;
+
+/// Full scale of the 12 bit ADC
+const ADC_MAX: u32 = 4095;
+
+/// The battery is measured through a 1:1 divider
+const DIVIDER: u32 = 2;
+
+/// Battery voltage from a reading of the battery pin and one of VREFINT taken right after it.
+pub fn millivolts(reading: u16, vrefint: u16, vrefint_cal: u16) -> u16 {
+    let vdda = (CALIBRATION_MV * vrefint_cal as u32 / (vrefint as u32).max(1)).min(MAX_VDDA_MV);
+    (reading as u32 * DIVIDER * vdda / ADC_MAX) as u16
+}
+
+/// Points of the curve, at 0%, 10% up to 100%.
+pub const POINTS: usize = 11;
+
+/// Voltage of the battery at every 10% of charge, rising.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Curve {
+    millivolts: [u16; POINTS]
+}
+
+/// A LiPo cell discharged at the few mA the watch draws
+pub const LIPO: Curve = Curve { millivolts: [3300, 3610, 3690, 3730, 3770, 3800, 3840, 3890, 3960, 4060, 4200] };
+
+impl Curve {
+    /// `None` unless the voltages rise.
+    pub fn new(millivolts: [u16; POINTS]) -> Option<Curve> {
+        if millivolts.windows(2).all(|pair| pair[0] < pair[1]) {
+            Some(Curve { millivolts })
+        } else {
+            None
+        }
+    }
+
+    pub fn empty(&self) -> u16 {
+        self.millivolts[0]
+    }
+
+    pub fn full(&self) -> u16 {
+        self.millivolts[POINTS - 1]
+    }
+
+    /// The same shape stretched to run from `empty` up to `full`, for a cell that ends elsewhere.
+    pub fn scaled(&self, empty: u16, full: u16) -> Curve {
+        let full = full.max(empty.saturating_add(POINTS as u16));
+        let range = (self.full() - self.empty()) as u32;
+        let mut millivolts = [0; POINTS];
+        for (scaled, &point) in millivolts.iter_mut().zip(self.millivolts.iter()) {
+            *scaled = empty + ((point - self.empty()) as u32 * (full - empty) as u32 / range) as u16;
+        }
+        Curve { millivolts }
+    }
+
+    /// Charge in percent, interpolated between the points.
+    pub fn percent(&self, millivolts: u16) -> u8 {
+        if millivolts <= self.empty() {
+            return 0;
+        }
+        if millivolts >= self.full() {
+            return 100;
+        }
+        let upper = self.millivolts.iter().position(|&point| point > millivolts).unwrap();
+        let (low, high) = (self.millivolts[upper - 1] as u32, self.millivolts[upper] as u32);
+        ((upper as u32 - 1) * 10 + (millivolts as u32 - low) * 10 / (high - low)) as u8
+    }
+}
+
+/// Weight of a new reading in the average is 1 / 2^FILTER_SHIFT
+const FILTER_SHIFT: u32 = 4;
+
+/// Median of the last three readings, which drops single spikes, followed by an exponential
+/// moving average of the battery voltage, the multiplexed display makes single readings jump.
+#[derive(Default)]
+pub struct Filter {
+    recent: [u16; 3],
+    /// In millivolts << FILTER_SHIFT, `None` until the first reading
+    average: Option<u32>
+}
+
+impl Filter {
+    pub const fn new() -> Self {
+        Filter { recent: [0; 3], average: None }
+    }
+
+    /// Adds a reading, returning the filtered voltage.
+    pub fn update(&mut self, millivolts: u16) -> u16 {
+        if self.average.is_none() {
+            self.recent = [millivolts; 3];
+        }
+        self.recent = [self.recent[1], self.recent[2], millivolts];
+        let [a, b, c] = self.recent;
+        let median = a.min(b).max(a.max(b).min(c));
+        let average = match self.average {
+            None => (median as u32) << FILTER_SHIFT,
+            Some(average) => average - (average >> FILTER_SHIFT) + median as u32
+        };
+        self.average = Some(average);
+        (average >> FILTER_SHIFT) as u16
+    }
+
+    /// The filtered voltage, `None` before the first reading.
+    pub fn millivolts(&self) -> Option<u16> {
+        self.average.map(|average| (average >> FILTER_SHIFT) as u16)
+    }
+}
+
+/// Where the power comes from, as far as the charger tells.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Power {
+    Battery,
+    Charging,
+    /// On USB power with a full battery
+    Charged
+}
+
+impl Power {
+    pub fn name(self) -> &'static str {
+        match self {
+            Power::Battery => "battery",
+            Power::Charging => "charging",
+            Power::Charged => "charged"
+        }
+    }
+
+    /// Power by its name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Power> {
+        [Power::Battery, Power::Charging, Power::Charged].iter().copied().find(|power| power.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum State {
+    Ok,
+    /// Below the low threshold, the display saves power where it can
+    Low,
+    /// Below the critical threshold, only the empty battery is shown
+    Critical,
+    Charging,
+    Charged
+}
+
+impl State {
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Ok => "ok",
+            State::Low => "low",
+            State::Critical => "critical",
+            State::Charging => "charging",
+            State::Charged => "charged"
+        }
+    }
+}
+
+/// Percent the charge has to rise above a threshold before the state goes back up
+pub const HYSTERESIS: u8 = 5;
+
+/// Battery state from the charge and the charger, with hysteresis on the thresholds.
+pub struct Monitor {
+    /// Ok, Low or Critical, kept while charging until the battery readings are trusted again
+    level: State,
+    state: State
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    pub const fn new() -> Self {
+        Monitor { level: State::Ok, state: State::Ok }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Updates the state from the filtered charge in percent and the `low` and `critical` thresholds.
+    pub fn update(&mut self, percent: u8, power: Power, low: u8, critical: u8) -> State {
+        self.state = match power {
+            // The charger lifts the voltage, so it says nothing about the charge
+            Power::Charging => State::Charging,
+            Power::Charged => State::Charged,
+            Power::Battery => {
+                let recovered = |threshold: u8| percent >= threshold.saturating_add(HYSTERESIS);
+                self.level = match self.level {
+                    _ if percent < critical => State::Critical,
+                    State::Ok if percent < low => State::Low,
+                    State::Low | State::Critical if recovered(low) => State::Ok,
+                    State::Critical if recovered(critical) => State::Low,
+                    level => level
+                };
+                self.level
+            }
+        };
+        self.state
+    }
+}
@@ -0,0 +1,232 @@
+/// Day of the week, numbered 1 (Monday) to 7 (Sunday) like ISO 8601 and the STM32 RTC.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Weekday {
+    Monday = 1,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [Weekday::Monday, Weekday::Tuesday, Weekday::Wednesday, Weekday::Thursday,
+                               Weekday::Friday, Weekday::Saturday, Weekday::Sunday];
+
+    /// Weekday from its ISO number, 1 (Monday) to 7 (Sunday).
+    pub fn from_number(number: u8) -> Option<Weekday> {
+        if (1..=7).contains(&number) {
+            Some(Self::ALL[number as usize - 1])
+        } else {
+            None
+        }
+    }
+
+    pub fn number(self) -> u8 {
+        self as u8
+    }
+
+    /// Three letter English abbreviation, e.g. `Mon`.
+    pub fn abbreviation(self) -> &'static str {
+        let start = (self as usize - 1) * 3;
+        &"MonTueWedThuFriSatSun"[start..start + 3]
+    }
+}
+
+/// The years a `Date` can hold, all of them have a timestamp that fits a `u32`.
+pub const MIN_YEAR: u16 = 1970;
+pub const MAX_YEAR: u16 = 2105;
+
+/// Days from 1970-01-01 to the last day of `MAX_YEAR`
+const LAST_DAY: u32 = 49672;
+
+/// A date in the proleptic Gregorian calendar, from `MIN_YEAR` up to `MAX_YEAR`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Date {
+    year: u16,
+    month: u8,
+    day: u8
+}
+
+impl Date {
+    /// Returns `None` if the day does not exist, e.g. the 29th of February in a common year, or is out of range.
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Date> {
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year) || month == 0 || month > 12 || day == 0 || day > Self::days_in_month(year, month) {
+            return None;
+        }
+        Some(Date { year, month, day })
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn is_leap_year(year: u16) -> bool {
+        (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+    }
+
+    /// Number of days in `month` (1 to 12) of `year`, 0 for an invalid month.
+    pub fn days_in_month(year: u16, month: u8) -> u8 {
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            _ => 0
+        }
+    }
+
+    /// The day after, the last day of `MAX_YEAR` stays as it is.
+    pub fn next_day(&self) -> Date {
+        if self.day < Self::days_in_month(self.year, self.month) {
+            Date { day: self.day + 1, ..*self }
+        } else if self.month < 12 {
+            Date { year: self.year, month: self.month + 1, day: 1 }
+        } else if self.year < MAX_YEAR {
+            Date { year: self.year + 1, month: 1, day: 1 }
+        } else {
+            *self
+        }
+    }
+
+    /// Days since 1970-01-01. Uses the days-from-civil algorithm by Howard Hinnant.
+    pub fn days_since_epoch(&self) -> i32 {
+        let year = if self.month <= 2 { self.year as i32 - 1 } else { self.year as i32 };
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let month = (self.month as i32 + 9) % 12; // March is 0
+        let day_of_year = (153 * month + 2) / 5 + self.day as i32 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+
+    /// Inverse of `days_since_epoch`, days past the end of `MAX_YEAR` give its last day.
+    pub fn from_days_since_epoch(days: u32) -> Date {
+        let days = days.min(LAST_DAY) + 719468;
+        let era = days / 146097;
+        let day_of_era = days - era * 146097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153; // March is 0
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date { year: year as u16, month: month as u8, day: day as u8 }
+    }
+
+    /// Day of the week, counted on from 1970-01-01 which was a Thursday.
+    pub fn weekday(&self) -> Weekday {
+        Weekday::ALL[(self.days_since_epoch() + 3).rem_euclid(7) as usize]
+    }
+}
+
+impl Default for Date {
+    fn default() -> Self {
+        Date { year: 2000, month: 1, day: 1 }
+    }
+}
+
+/// Time of day in 24 hour format.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct Time {
+    hours: u8,
+    minutes: u8,
+    seconds: u8
+}
+
+impl Time {
+    pub fn new(hours: u8, minutes: u8, seconds: u8) -> Option<Time> {
+        if hours < 24 && minutes < 60 && seconds < 60 {
+            Some(Time { hours, minutes, seconds })
+        } else {
+            None
+        }
+    }
+
+    pub fn hours(&self) -> u8 {
+        self.hours
+    }
+
+    pub fn minutes(&self) -> u8 {
+        self.minutes
+    }
+
+    pub fn seconds(&self) -> u8 {
+        self.seconds
+    }
+
+    /// Hours on a 12-hour clock, 12 for midnight and noon.
+    pub fn hours_12(&self) -> u8 {
+        (self.hours + 11) % 12 + 1
+    }
+
+    pub fn is_pm(&self) -> bool {
+        self.hours >= 12
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct DateTime {
+    pub date: Date,
+    pub time: Time
+}
+
+impl DateTime {
+    pub fn new(date: Date, time: Time) -> Self {
+        DateTime { date, time }
+    }
+
+    /// Seconds since 1970-01-01 00:00:00.
+    pub fn timestamp(&self) -> u32 {
+        self.date.days_since_epoch() as u32 * 86400 + self.time.hours as u32 * 3600 + self.time.minutes as u32 * 60 + self.time.seconds as u32
+    }
+
+    pub fn from_timestamp(timestamp: u32) -> DateTime {
+        let seconds = timestamp % 86400;
+        DateTime {
+            date: Date::from_days_since_epoch(timestamp / 86400),
+            time: Time { hours: (seconds / 3600) as u8, minutes: (seconds / 60 % 60) as u8, seconds: (seconds % 60) as u8 }
+        }
+    }
+
+    /// The moment closest to `self` with the time of day `time`, which can be on the previous or next day.
+    pub fn nearest_with_time(&self, time: Time) -> DateTime {
+        let same_day = DateTime::new(self.date, time).timestamp() as i64;
+        let now = self.timestamp() as i64;
+        let nearest = if same_day - now > 43200 {
+            same_day - 86400
+        } else if now - same_day > 43200 {
+            same_day + 86400
+        } else {
+            same_day
+        };
+        DateTime::from_timestamp(nearest.max(0) as u32)
+    }
+
+    pub fn next_second(&self) -> DateTime {
+        let mut next = *self;
+        next.time.seconds += 1;
+        if next.time.seconds >= 60 {
+            next.time.minutes += 1;
+            next.time.seconds = 0;
+            if next.time.minutes >= 60 {
+                next.time.minutes = 0;
+                next.time.hours += 1;
+                if next.time.hours >= 24 {
+                    next.time.hours = 0;
+                    next.date = next.date.next_day();
+                }
+            }
+        }
+        next
+    }
+}
@@ -0,0 +1,83 @@
+//! Drift calibration of the time source.
+//!
+//! Every time the host sets the time to the second, the difference between the time the watch
+//! kept and the real time, divided by the time since the previous set, is the drift of the
+//! time source in parts per million. That drift is folded into a trim that the time source
+//! applies, so the next period should show less drift.
+
+/// Shortest time between two sets to calibrate from. Sets have a one second resolution,
+/// so this gives a trim accurate to about 12ppm.
+pub const MIN_CALIBRATION_PERIOD: u32 = 24 * 60 * 60;
+
+/// Largest trim that is applied, the RTC smooth calibration covers -487 up to +488ppm.
+pub const MAX_TRIM_PPM: i32 = 480;
+
+/// Differences larger than this are taken as the clock having been set wrong rather than drifting.
+pub const MAX_DRIFT_PPM: i32 = 100_000;
+
+/// Drift in ppm of a clock showing `clock` when the real time is `actual`, when it was last set
+/// at `last_set`. Positive when the clock runs fast, `None` if the period is too short to tell or
+/// the difference is over `MAX_DRIFT_PPM`.
+pub fn drift_ppm(last_set: u32, clock: u32, actual: u32) -> Option<i32> {
+    if actual < last_set || actual - last_set < MIN_CALIBRATION_PERIOD {
+        return None;
+    }
+    let elapsed = (actual - last_set) as u64;
+    let error = clock.abs_diff(actual) as u64;
+    // Round to the nearest ppm, unsigned spares the firmware a signed 64 bit division
+    let ppm = (error * 1_000_000 * 2 + elapsed) / (elapsed * 2);
+    if ppm > MAX_DRIFT_PPM as u64 {
+        return None;
+    }
+    let ppm = ppm as i32;
+    Some(if clock < actual { -ppm } else { ppm })
+}
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct Calibration {
+    /// Correction applied to the time source in ppm, positive speeds it up.
+    trim_ppm: i32,
+    /// Timestamp of the last time the clock was set to the second, 0 if never.
+    last_set: u32
+}
+
+impl Calibration {
+    pub fn new(trim_ppm: i32, last_set: u32) -> Self {
+        Calibration { trim_ppm: trim_ppm.clamp(-MAX_TRIM_PPM, MAX_TRIM_PPM), last_set }
+    }
+
+    pub fn trim_ppm(&self) -> i32 {
+        self.trim_ppm
+    }
+
+    pub fn last_set(&self) -> u32 {
+        self.last_set
+    }
+
+    /// Seconds since the clock was last set, 0 if it never was.
+    pub fn elapsed(&self, now: u32) -> u32 {
+        if self.last_set == 0 {
+            0
+        } else {
+            now.saturating_sub(self.last_set)
+        }
+    }
+
+    /// Call when the time is changed without knowing the exact time, the next set can't calibrate from that.
+    pub fn time_changed(&mut self) {
+        self.last_set = 0;
+    }
+
+    /// Call when the host sets the clock to `actual` while it showed `clock`.
+    /// Returns the new trim if the drift since the last set changed it.
+    pub fn time_set(&mut self, clock: u32, actual: u32) -> Option<i32> {
+        let drift = if self.last_set == 0 { None } else { drift_ppm(self.last_set, clock, actual) };
+        self.last_set = actual;
+        let drift = drift?;
+        if drift == 0 {
+            return None;
+        }
+        self.trim_ppm = (self.trim_ppm - drift).clamp(-MAX_TRIM_PPM, MAX_TRIM_PPM);
+        Some(self.trim_ppm)
+    }
+}
@@ -0,0 +1,147 @@
+//! The MCP73831 charger, seen through its STAT pin.
+//!
+//! STAT is driven low while charging, high once the battery is full and left floating without
+//! USB power. Reading the pin once with the pull-up and once with the pull-down tells all three
+//! apart, a pin that follows both pulls isn't driven. A new level only counts once it is read
+//! `DEBOUNCE_READINGS` times in a row, so plugging in or a loose cable doesn't flip the state back and forth.
+
+use crate::battery::Power;
+
+/// What the STAT pin says.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Stat {
+    /// Driven low, charging
+    Low,
+    /// Driven high, charge complete
+    High,
+    /// Not driven, the charger has no power
+    Floating
+}
+
+impl Stat {
+    /// From the levels read with the pull-up and with the pull-down enabled.
+    pub fn from_levels(with_pull_up: bool, with_pull_down: bool) -> Stat {
+        match (with_pull_up, with_pull_down) {
+            (false, false) => Stat::Low,
+            (true, true) => Stat::High,
+            _ => Stat::Floating
+        }
+    }
+
+    fn power(self) -> Power {
+        match self {
+            Stat::Low => Power::Charging,
+            Stat::High => Power::Charged,
+            Stat::Floating => Power::Battery
+        }
+    }
+}
+
+/// Readings in a row a new level needs before it counts
+pub const DEBOUNCE_READINGS: u8 = 10;
+
+/// One time the watch was on USB power.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Session {
+    /// Uptime in seconds when the charger got power
+    pub start: u32,
+    /// Seconds until the charge completed or the power went, so far while still active
+    pub duration: u32,
+    /// Battery voltage at the end, the latest one while still active
+    pub end_mv: u16,
+    /// The charger got the battery full
+    pub complete: bool,
+    /// Still on USB power
+    pub active: bool
+}
+
+/// Debounced charger state and the latest charge session.
+pub struct Charger {
+    power: Power,
+    /// Level that differs from `power`, and how often it was read in a row
+    candidate: Stat,
+    count: u8,
+    session: Option<Session>
+}
+
+impl Default for Charger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Charger {
+    pub const fn new() -> Self {
+        Charger { power: Power::Battery, candidate: Stat::Floating, count: 0, session: None }
+    }
+
+    pub fn power(&self) -> Power {
+        self.power
+    }
+
+    /// The current session, or the last one once the power is gone. `None` since the reset.
+    pub fn session(&self) -> Option<Session> {
+        self.session
+    }
+
+    /// A new level was read but doesn't count yet, the caller should keep reading.
+    pub fn is_settling(&self) -> bool {
+        self.count > 0
+    }
+
+    /// Takes one reading of the pin at `uptime` in seconds with the battery at `millivolts`,
+    /// returning the power once it changed.
+    pub fn update(&mut self, stat: Stat, uptime: u32, millivolts: u16) -> Option<Power> {
+        if let Some(session) = self.session.as_mut().filter(|session| session.active) {
+            if !session.complete {
+                session.duration = uptime.wrapping_sub(session.start);
+                session.end_mv = millivolts;
+            }
+        }
+
+        if stat.power() == self.power {
+            self.count = 0;
+            return None;
+        }
+        if stat != self.candidate {
+            self.candidate = stat;
+            self.count = 0;
+        }
+        self.count += 1;
+        if self.count < DEBOUNCE_READINGS {
+            return None;
+        }
+        self.count = 0;
+
+        let power = stat.power();
+        match (self.power, power) {
+            (Power::Battery, _) => {
+                self.session = Some(Session {
+                    start: uptime,
+                    duration: 0,
+                    end_mv: millivolts,
+                    complete: power == Power::Charged,
+                    active: true
+                });
+            }
+            (_, Power::Battery) => {
+                if let Some(session) = self.session.as_mut() {
+                    session.active = false;
+                }
+            }
+            (_, Power::Charged) => {
+                if let Some(session) = self.session.as_mut() {
+                    session.complete = true;
+                }
+            }
+            (_, Power::Charging) => {
+                // Topping up again after it was full
+                if let Some(session) = self.session.as_mut() {
+                    session.complete = false;
+                }
+            }
+        }
+        self.power = power;
+        Some(power)
+    }
+}
@@ -0,0 +1,638 @@
+//! Line based command protocol spoken over the USB serial port.
+//!
+//! Every command is a line of words separated by spaces, the command name is not case sensitive.
+//! The reply is zero or more lines of output followed by `OK`, or a single `ERR <code> <reason>`.
+//! Lines end in `\n`, a `\r` before it is ignored so terminals in either mode work.
+//!
+//! `STATUS` answers with a single record meant for scripts, `key=value` pairs separated by spaces:
+//!
+//! ```text
+//! STATUS v=1 time=13:37:00 date=2024-05-01 battery=85 battery_mv=3950 charge=battery uptime=3600 wakes=12 firmware=0.1.0 tx_dropped=0 board=1 image_crc=48879 power=battery used_uah=1200 runtime_h=212
+//! ```
+//!
+//! `v` is `STATUS_VERSION`. Within a version keys are only ever added, so readers should skip keys
+//! they don't know. `battery` is in percent, `charge` is `battery`, `charging` or `charged`, `uptime` is in
+//! seconds since the last reset and `wakes` counts the times movement turned the display on.
+//! `tx_dropped` counts the reply bytes lost because the host didn't read them in time. `board` is the
+//! board revision the firmware was built for and `image_crc` the CRC of the running image, see `firmware`.
+//! `power` is the same as `charge`, older firmware only told charging from charged in `charge` and said
+//! `charging` on battery as well. `used_uah` is the charge used since the charger was unplugged and `runtime_h` the hours
+//! it is estimated to last at that rate, left out until there is a rate, see `energy`.
+//!
+//! `MSG` takes the rest of the line, spaces included, and scrolls it across the tubes, see `text`.
+//!
+//! `EXERCISE` lights every segment in turn for `EXERCISE_S` and `USAGE` lists the seconds each segment
+//! of both tubes has been lit, see `wear`. `SELFTEST` lights every segment and glyph in turn and reports the
+//! number of each step on a line of its own while it runs, see `self_test`.
+//!
+//! `BOOTLOADER` answers `OK` and then restarts into the DFU bootloader of the STM32, the serial port
+//! goes away until the new firmware runs.
+
+use crate::battery::Power;
+use crate::calendar::{Date, DateTime, Time};
+use crate::charger::Session;
+use crate::energy::{self, Usage};
+use crate::nixie_segment::{DotStatus, BRIGHTNESS_LEVELS};
+use crate::sequence::{Sequence, Transition};
+use crate::text::Text;
+use crate::settings::{Key, Settings, Value};
+use crate::wear::{Wear, SEGMENTS};
+
+/// Bumped when a command or its output changes in a way that breaks host tools.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Bumped when the meaning of a `STATUS` key changes.
+pub const STATUS_VERSION: u8 = 1;
+
+/// The RTC only counts the years 2000 up to 2099
+const FIRST_TIMESTAMP: u32 = 946_684_800;
+const LAST_TIMESTAMP: u32 = 4_102_444_799;
+const FIRST_YEAR: u16 = 2000;
+const LAST_YEAR: u16 = 2099;
+
+/// Longest line the parser accepts, without the line end.
+pub const MAX_LINE_LEN: usize = 64;
+
+/// Room for the longest reply, `GET` with every setting at its widest takes a bit under 500 bytes.
+pub const TX_BUFFER_SIZE: usize = 512;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum ErrorCode {
+    UnknownCommand = 1,
+    MissingArgument = 2,
+    BadArgument = 3,
+    UnknownSetting = 4,
+    OutOfRange = 5,
+    LineTooLong = 6
+}
+
+impl ErrorCode {
+    pub fn reason(self) -> &'static str {
+        match self {
+            ErrorCode::UnknownCommand => "unknown command",
+            ErrorCode::MissingArgument => "missing argument",
+            ErrorCode::BadArgument => "bad argument",
+            ErrorCode::UnknownSetting => "unknown setting",
+            ErrorCode::OutOfRange => "out of range",
+            ErrorCode::LineTooLong => "line too long"
+        }
+    }
+}
+
+/// Collects received bytes into lines, whatever size the USB packets happen to have.
+pub struct LineBuffer {
+    data: [u8; MAX_LINE_LEN],
+    len: usize,
+    /// The current line didn't fit and is dropped up to its end
+    overflow: bool
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        LineBuffer { data: [0; MAX_LINE_LEN], len: 0, overflow: false }
+    }
+
+    /// Adds a byte, returning the line once it is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], ErrorCode>> {
+        match byte {
+            b'\n' => {
+                let len = self.len;
+                let overflow = self.overflow;
+                self.len = 0;
+                self.overflow = false;
+                if overflow {
+                    Some(Err(ErrorCode::LineTooLong))
+                } else {
+                    Some(Ok(&self.data[..len]))
+                }
+            }
+            b'\r' => None,
+            _ if self.len < MAX_LINE_LEN => {
+                self.data[self.len] = byte;
+                self.len += 1;
+                None
+            }
+            _ => {
+                self.overflow = true;
+                None
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Command {
+    GetTime,
+    SetTime(u8, u8),
+    /// Set to the second, which also calibrates the drift
+    SyncTime(Time),
+    GetTimestamp,
+    /// Set date and time from a Unix timestamp in local time
+    SyncTimestamp(DateTime),
+    GetDate,
+    SetDate(Date),
+    ShowDate,
+    /// Scroll a text across the display
+    Message(Text),
+    Battery,
+    /// The latest charge session
+    Charge,
+    /// Where the charge went and how long the rest lasts
+    Energy,
+    Calibration,
+    Status,
+    /// Light every segment against cathode poisoning
+    Exercise,
+    /// Seconds every segment has been lit
+    Usage,
+    /// Every segment and glyph in turn, for production testing
+    SelfTest,
+    /// One setting, or all of them
+    Get(Option<Key>),
+    Set(Key, Value),
+    Help,
+    Version,
+    Bootloader
+}
+
+/// Something the host asked for that the clock has to act on.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Request {
+    SetTime(u8, u8),
+    SyncTime(Time),
+    SyncDateTime(DateTime),
+    SetDate(Date),
+    ShowDate,
+    ShowMessage(Text),
+    Exercise,
+    SelfTest,
+    Set(Key, Value),
+    /// Restart into the DFU bootloader, after the reply went out
+    Bootloader
+}
+
+/// What the watch reports back to the host.
+pub struct Status {
+    pub time: Time,
+    pub date: Date,
+    pub battery_status: u8,
+    pub battery_mv: u16,
+    pub power: Power,
+    pub charge_session: Option<Session>,
+    /// Seconds since the time was last set to the second, 0 if unknown
+    pub since_set: u32,
+    /// Seconds since the last reset
+    pub uptime: u32,
+    /// Times movement turned the display on
+    pub wakes: u32,
+    pub energy: Usage,
+    pub wear: Wear,
+    /// Reply bytes that didn't fit in the transmit buffer
+    pub tx_dropped: u32,
+    pub board_revision: u8,
+    /// CRC of the running image, see `firmware::image_crc`
+    pub image_crc: u16,
+    pub settings: Settings,
+    pub firmware_version: &'static str
+}
+
+/// Where replies go, the serial port on the watch.
+pub trait Output {
+    fn write(&mut self, data: &[u8]);
+}
+
+/// The parts of `text` between the `separator`s.
+pub fn split(text: &[u8], separator: u8) -> impl Iterator<Item = &[u8]> {
+    text.split(move |&b| b == separator)
+}
+
+/// Parses a decimal number without sign, `None` if anything is not a digit.
+fn parse_number(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value: u32 = 0;
+    for &d in digits {
+        if !d.is_ascii_digit() {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add((d - b'0') as u32)?;
+    }
+    Some(value)
+}
+
+fn parse_signed(text: &[u8]) -> Option<i32> {
+    match text.split_first() {
+        Some((b'-', digits)) => parse_number(digits).filter(|&n| n <= i32::MAX as u32).map(|n| -(n as i32)),
+        Some((b'+', digits)) => parse_number(digits).filter(|&n| n <= i32::MAX as u32).map(|n| n as i32),
+        _ => parse_number(text).filter(|&n| n <= i32::MAX as u32).map(|n| n as i32)
+    }
+}
+
+/// `HH:MM` or `HH:MM:SS`, the seconds are `None` in the first case.
+fn parse_time(text: &[u8]) -> Option<(u8, u8, Option<u8>)> {
+    let mut parts = split(text, b':');
+    let hours = parse_number(parts.next()?)?;
+    let minutes = parse_number(parts.next()?)?;
+    let seconds = match parts.next() {
+        Some(seconds) => Some(parse_number(seconds)?),
+        None => None
+    };
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds.unwrap_or(0) > 59 {
+        return None;
+    }
+    Some((hours as u8, minutes as u8, seconds.map(|s| s as u8)))
+}
+
+/// `YYYY-MM-DD`, with a year the RTC can store
+fn parse_date(text: &[u8]) -> Result<Date, ErrorCode> {
+    let mut parts = split(text, b'-');
+    let mut next = || parts.next().and_then(parse_number).ok_or(ErrorCode::BadArgument);
+    let (year, month, day) = (next()?, next()?, next()?);
+    if parts.next().is_some() || month > 12 || day > 31 {
+        return Err(ErrorCode::BadArgument);
+    }
+    if year < FIRST_YEAR as u32 || year > LAST_YEAR as u32 {
+        return Err(ErrorCode::OutOfRange);
+    }
+    Date::new(year as u16, month as u8, day as u8).ok_or(ErrorCode::BadArgument)
+}
+
+/// Parses one line, `None` for an empty one.
+pub fn parse(line: &[u8]) -> Option<Result<Command, ErrorCode>> {
+    // The text of a message is the rest of the line, spaces and all
+    let line = line.trim_ascii();
+    let name_len = line.iter().position(u8::is_ascii_whitespace).unwrap_or(line.len());
+    if line[..name_len].eq_ignore_ascii_case(b"MSG") {
+        return Some(match line[name_len..].trim_ascii() {
+            [] => Err(ErrorCode::MissingArgument),
+            text => Text::from_bytes(text).map(Command::Message).ok_or(ErrorCode::OutOfRange)
+        });
+    }
+
+    let mut words = line.split(|b| b.is_ascii_whitespace()).filter(|word| !word.is_empty());
+    let name = words.next()?;
+    let argument = words.next();
+    let value = words.next();
+    if words.next().is_some() {
+        return Some(Err(ErrorCode::BadArgument));
+    }
+    let is = |command: &str| name.eq_ignore_ascii_case(command.as_bytes());
+
+    let command = if is("TIME") {
+        match argument {
+            None => Ok(Command::GetTime),
+            Some(time) => match parse_time(time) {
+                Some((hours, minutes, None)) => Ok(Command::SetTime(hours, minutes)),
+                Some((hours, minutes, Some(seconds))) => Ok(Command::SyncTime(Time::new(hours, minutes, seconds).unwrap())),
+                None => Err(ErrorCode::BadArgument)
+            }
+        }
+    } else if is("EPOCH") {
+        match argument.map(parse_number) {
+            None => Ok(Command::GetTimestamp),
+            Some(Some(timestamp)) if (FIRST_TIMESTAMP..=LAST_TIMESTAMP).contains(&timestamp) => {
+                Ok(Command::SyncTimestamp(DateTime::from_timestamp(timestamp)))
+            }
+            Some(Some(_)) => Err(ErrorCode::OutOfRange),
+            Some(None) => Err(ErrorCode::BadArgument)
+        }
+    } else if is("DATE") {
+        match argument {
+            None => Ok(Command::GetDate),
+            Some(show) if show.eq_ignore_ascii_case(b"SHOW") => Ok(Command::ShowDate),
+            Some(date) => parse_date(date).map(Command::SetDate)
+        }
+    } else if is("GET") {
+        match argument {
+            None => Ok(Command::Get(None)),
+            Some(name) => Key::from_name(name).map(|key| Command::Get(Some(key))).ok_or(ErrorCode::UnknownSetting)
+        }
+    } else if is("SET") {
+        match (argument.map(Key::from_name), value) {
+            (None, _) | (_, None) => Err(ErrorCode::MissingArgument),
+            (Some(None), _) => Err(ErrorCode::UnknownSetting),
+            (Some(Some(Key::StartTime)), Some(value)) => match parse_time(value) {
+                Some((hours, minutes, seconds)) => {
+                    let time = Time::new(hours, minutes, seconds.unwrap_or(0)).unwrap();
+                    Ok(Command::Set(Key::StartTime, Value::Time(time)))
+                }
+                None => Err(ErrorCode::BadArgument)
+            },
+            (Some(Some(Key::Sequence)), Some(value)) => match Sequence::parse(value) {
+                Some(sequence) => Ok(Command::Set(Key::Sequence, Value::Sequence(sequence))),
+                None => Err(ErrorCode::BadArgument)
+            },
+            (Some(Some(key)), Some(value)) => match parse_signed(value) {
+                Some(number) => Ok(Command::Set(key, Value::Number(number))),
+                None => Err(ErrorCode::BadArgument)
+            }
+        }
+    } else {
+        // The commands without an argument, in the order of the match below
+        const NAMES: [&str; 11] = ["BATT", "CHARGE", "ENERGY", "CAL", "STATUS", "EXERCISE", "USAGE", "SELFTEST", "HELP",
+                                   "VERSION", "BOOTLOADER"];
+        let Some(index) = NAMES.iter().position(|command| is(command)) else {
+            return Some(Err(ErrorCode::UnknownCommand));
+        };
+        let command = match index {
+            0 => Command::Battery,
+            1 => Command::Charge,
+            2 => Command::Energy,
+            3 => Command::Calibration,
+            4 => Command::Status,
+            5 => Command::Exercise,
+            6 => Command::Usage,
+            7 => Command::SelfTest,
+            8 => Command::Help,
+            9 => Command::Version,
+            _ => Command::Bootloader
+        };
+        if argument.is_some() {
+            Err(ErrorCode::BadArgument)
+        } else {
+            Ok(command)
+        }
+    };
+    // Only SET takes a second argument
+    if value.is_some() && matches!(command, Ok(command) if !matches!(command, Command::Set(..))) {
+        return Some(Err(ErrorCode::BadArgument));
+    }
+    Some(command)
+}
+
+const HELP: &str = "TIME [HH:MM[:SS]]\n\
+                    EPOCH [SECONDS]\n\
+                    DATE [YYYY-MM-DD|SHOW]\n\
+                    MSG TEXT\n\
+                    BATT\n\
+                    CHARGE\n\
+                    ENERGY\n\
+                    CAL\n\
+                    STATUS\n\
+                    EXERCISE\n\
+                    USAGE\n\
+                    SELFTEST\n\
+                    GET [NAME]\n\
+                    SET NAME VALUE\n\
+                    VERSION\n\
+                    BOOTLOADER\n";
+
+pub fn write_number<O: Output>(out: &mut O, number: i32) {
+    if number < 0 {
+        out.write(b"-");
+    }
+    write_padded(out, number.unsigned_abs(), 1);
+}
+
+/// A label and the number after it.
+fn write_field<O: Output>(out: &mut O, label: &[u8], number: u32) {
+    out.write(label);
+    write_padded(out, number, 1);
+}
+
+/// Decimal number with leading zeros up to `width` digits.
+pub fn write_padded<O: Output>(out: &mut O, number: u32, width: usize) {
+    out.write(padded(number, width, &mut [0; 10]));
+}
+
+/// The digits of `number` with leading zeros up to `width`, written to the end of `digits`.
+pub fn padded(number: u32, width: usize, digits: &mut [u8; 10]) -> &[u8] {
+    let mut start = digits.len();
+    let mut rest = number;
+    while rest > 0 || start > digits.len() - width.max(1) {
+        start -= 1;
+        digits[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+    }
+    &digits[start..]
+}
+
+pub fn write_time<O: Output>(out: &mut O, time: Time) {
+    write_padded(out, time.hours() as u32, 2);
+    out.write(b":");
+    write_padded(out, time.minutes() as u32, 2);
+    out.write(b":");
+    write_padded(out, time.seconds() as u32, 2);
+}
+
+pub fn write_date<O: Output>(out: &mut O, date: Date) {
+    write_padded(out, date.year() as u32, 4);
+    out.write(b"-");
+    write_padded(out, date.month() as u32, 2);
+    out.write(b"-");
+    write_padded(out, date.day() as u32, 2);
+}
+
+/// In the form `SET SEQUENCE` takes, `DEFAULT` if empty.
+pub fn write_sequence<O: Output>(out: &mut O, sequence: &Sequence) {
+    if sequence.is_empty() {
+        out.write(b"DEFAULT");
+    } else if sequence.transition != Transition::Cut {
+        out.write(sequence.transition.name().as_bytes());
+        out.write(b"/");
+    }
+    for (i, frame) in sequence.frames().iter().enumerate() {
+        if i > 0 {
+            out.write(b",");
+        }
+        out.write(&[frame.content.letter()]);
+        match frame.dot {
+            DotStatus::Off => {}
+            DotStatus::Digit1 => out.write(b"1"),
+            DotStatus::Digit2 => out.write(b"2")
+        }
+        write_field(out, b":", frame.duration as u32);
+        if frame.brightness != BRIGHTNESS_LEVELS {
+            write_field(out, b":", frame.brightness as u32);
+        }
+    }
+}
+
+fn write_setting<O: Output>(out: &mut O, settings: &Settings, key: Key) {
+    out.write(key.name().as_bytes());
+    out.write(b" ");
+    match settings.get(key) {
+        Value::Time(time) => write_time(out, time),
+        Value::Number(number) => write_number(out, number),
+        Value::Sequence(sequence) => write_sequence(out, &sequence)
+    }
+    out.write(b"\n");
+}
+
+pub fn write_error<O: Output>(out: &mut O, error: ErrorCode) {
+    out.write(b"ERR ");
+    write_number(out, error as i32);
+    out.write(b" ");
+    out.write(error.reason().as_bytes());
+    out.write(b"\n");
+}
+
+fn runtime_hours(status: &Status) -> Option<u32> {
+    status.energy.runtime_hours(energy::remaining_uah(status.settings.battery_capacity_mah, status.battery_status))
+}
+
+/// Answers a command, returning what the clock has to do for it.
+pub fn execute<O: Output>(command: Command, status: &Status, out: &mut O) -> Option<Request> {
+    let mut request = None;
+    match command {
+        Command::GetTime => {
+            write_time(out, status.time);
+            out.write(b"\n");
+        }
+        Command::SetTime(hours, minutes) => request = Some(Request::SetTime(hours, minutes)),
+        Command::SyncTime(time) => request = Some(Request::SyncTime(time)),
+        Command::GetTimestamp => {
+            write_padded(out, DateTime::new(status.date, status.time).timestamp(), 1);
+            out.write(b"\n");
+        }
+        Command::SyncTimestamp(now) => request = Some(Request::SyncDateTime(now)),
+        Command::GetDate => {
+            write_date(out, status.date);
+            out.write(b" ");
+            out.write(status.date.weekday().abbreviation().as_bytes());
+            out.write(b"\n");
+        }
+        Command::SetDate(date) => request = Some(Request::SetDate(date)),
+        Command::ShowDate => request = Some(Request::ShowDate),
+        Command::Message(text) => request = Some(Request::ShowMessage(text)),
+        Command::Battery => {
+            write_number(out, status.battery_status as i32);
+            out.write(match status.power {
+                Power::Battery => b"% on battery\n",
+                Power::Charging => b"% charging\n",
+                Power::Charged => b"% charged\n"
+            });
+        }
+        Command::Charge => match status.charge_session {
+            None => out.write(b"No charge since the reset\n"),
+            Some(session) => {
+                out.write(match (session.complete, session.active) {
+                    (true, _) => b"Charged in " as &[u8],
+                    (false, true) => b"Charging for ",
+                    (false, false) => b"Unplugged after "
+                });
+                write_padded(out, session.duration, 1);
+                out.write(b"s at ");
+                write_number(out, session.end_mv as i32);
+                write_field(out, b"mV\nStarted at uptime ", session.start);
+                out.write(b"s\n");
+            }
+        },
+        Command::Energy => {
+            let usage = &status.energy;
+            write_field(out, b"On battery for ", usage.seconds);
+            write_field(out, b"s, woke up ", usage.wakes);
+            write_field(out, b" times\nAwake ", usage.awake);
+            write_field(out, b"s, display ", usage.display);
+            write_field(out, b"s, HV ", usage.hv);
+            write_field(out, b"s, USB ", usage.usb);
+            write_field(out, b"s\nUsed ", usage.used_uah);
+            out.write(b"uAh");
+            if let Some(average) = usage.average_ua() {
+                write_field(out, b", ", average);
+                out.write(b"uA on average");
+            }
+            out.write(b"\n");
+            match runtime_hours(status) {
+                Some(hours) => {
+                    write_field(out, b"About ", hours);
+                    out.write(b"h left\n");
+                }
+                None => out.write(b"Runtime unknown\n")
+            }
+        }
+        Command::Calibration => {
+            write_field(out, b"Set ", status.since_set);
+            out.write(b"s ago\nTrim ");
+            write_number(out, status.settings.trim_ppm);
+            out.write(b"ppm\n");
+        }
+        Command::Status => {
+            out.write(b"STATUS v=");
+            write_number(out, STATUS_VERSION as i32);
+            out.write(b" time=");
+            write_time(out, status.time);
+            out.write(b" date=");
+            write_date(out, status.date);
+            out.write(b" battery=");
+            write_number(out, status.battery_status as i32);
+            out.write(b" battery_mv=");
+            write_number(out, status.battery_mv as i32);
+            out.write(b" charge=");
+            out.write(status.power.name().as_bytes());
+            write_field(out, b" uptime=", status.uptime);
+            write_field(out, b" wakes=", status.wakes);
+            out.write(b" firmware=");
+            out.write(status.firmware_version.as_bytes());
+            write_field(out, b" tx_dropped=", status.tx_dropped);
+            out.write(b" board=");
+            write_number(out, status.board_revision as i32);
+            write_field(out, b" image_crc=", status.image_crc as u32);
+            out.write(b" power=");
+            out.write(status.power.name().as_bytes());
+            write_field(out, b" used_uah=", status.energy.used_uah);
+            if let Some(hours) = runtime_hours(status) {
+                write_field(out, b" runtime_h=", hours);
+            }
+            out.write(b"\n");
+        }
+        Command::Exercise => request = Some(Request::Exercise),
+        Command::SelfTest => request = Some(Request::SelfTest),
+        Command::Usage => {
+            for (tube, seconds) in status.wear.seconds.chunks(SEGMENTS).enumerate() {
+                write_number(out, tube as i32 + 1);
+                for (segment, &seconds) in seconds.iter().enumerate() {
+                    out.write(b" ");
+                    // Segments a up to g, then the point as dp
+                    out.write(&b"abcdefgdp"[segment..(segment + 1 + segment / 7)]);
+                    write_field(out, b"=", seconds);
+                }
+                out.write(b"\n");
+            }
+        }
+        Command::Get(Some(key)) => write_setting(out, &status.settings, key),
+        Command::Get(None) => {
+            for key in Key::ALL {
+                write_setting(out, &status.settings, key);
+            }
+        }
+        Command::Set(key, value) => {
+            let mut settings = status.settings;
+            if !settings.set(key, value) {
+                write_error(out, ErrorCode::OutOfRange);
+                return None;
+            }
+            request = Some(Request::Set(key, value));
+        }
+        Command::Help => out.write(HELP.as_bytes()),
+        Command::Version => {
+            out.write(b"nixiewatch ");
+            out.write(status.firmware_version.as_bytes());
+            out.write(b" protocol ");
+            write_number(out, PROTOCOL_VERSION as i32);
+            out.write(b"\n");
+        }
+        Command::Bootloader => request = Some(Request::Bootloader)
+    }
+    out.write(b"OK\n");
+    request
+}
+
+/// Parses and answers one line, replying nothing to an empty one.
+pub fn handle_line<O: Output>(line: Result<&[u8], ErrorCode>, status: &Status, out: &mut O) -> Option<Request> {
+    match line.map(parse) {
+        Ok(None) => None,
+        Ok(Some(Ok(command))) => execute(command, status, out),
+        Ok(Some(Err(error))) | Err(error) => {
+            write_error(out, error);
+            None
+        }
+    }
+}
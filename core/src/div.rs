@@ -0,0 +1,55 @@
+//! Shift and subtract division for the firmware's replacement of the one of `compiler_builtins`.
+//!
+//! The Cortex-M0 has no divide instruction, and the routines of `compiler_builtins` unroll the
+//! division for speed, about 1K of flash more than the 350 bytes the ones here take. The watch divides
+//! a few times a second at most, so one quotient bit per round is plenty. Nothing in here may use `/`
+//! or `%`, on the watch those end up calling these very functions.
+
+/// Quotient and remainder of `n / d`. Rust checks for a zero `d` before it divides, so that never
+/// reaches here, but if it does the result is all ones and `n`.
+pub fn udivmod32(n: u32, d: u32) -> (u32, u32) {
+    let mut quotient = 0;
+    let mut remainder: u32 = 0;
+    for i in (0..32).rev() {
+        // The bit shifted out of the remainder still counts when comparing with the divisor
+        let carry = remainder >> 31;
+        remainder = remainder << 1 | (n >> i) & 1;
+        if carry != 0 || remainder >= d {
+            remainder = remainder.wrapping_sub(d);
+            quotient |= 1 << i;
+        }
+    }
+    (quotient, remainder)
+}
+
+/// Quotient and remainder of `n / d`, all ones and `n` for a zero `d` like `udivmod32`.
+pub fn udivmod64(n: u64, d: u64) -> (u64, u64) {
+    let mut quotient = 0;
+    let mut remainder: u64 = 0;
+    for i in (0..64).rev() {
+        let carry = remainder >> 63;
+        remainder = remainder << 1 | (n >> i) & 1;
+        if carry != 0 || remainder >= d {
+            remainder = remainder.wrapping_sub(d);
+            quotient |= 1 << i;
+        }
+    }
+    (quotient, remainder)
+}
+
+/// Signed division rounding towards zero, the remainder takes the sign of `a`. `i32::MIN / -1` wraps
+/// to `i32::MIN`, a zero `b` gives -1 for a positive `a`, 1 for a negative one and `a` as remainder.
+pub fn idivmod32(a: i32, b: i32) -> (i32, i32) {
+    let (quotient, remainder) = udivmod32(a.unsigned_abs(), b.unsigned_abs());
+    let quotient = if (a < 0) != (b < 0) { (quotient as i32).wrapping_neg() } else { quotient as i32 };
+    let remainder = if a < 0 { (remainder as i32).wrapping_neg() } else { remainder as i32 };
+    (quotient, remainder)
+}
+
+/// The 64 bit `idivmod32`.
+pub fn idivmod64(a: i64, b: i64) -> (i64, i64) {
+    let (quotient, remainder) = udivmod64(a.unsigned_abs(), b.unsigned_abs());
+    let quotient = if (a < 0) != (b < 0) { (quotient as i64).wrapping_neg() } else { quotient as i64 };
+    let remainder = if a < 0 { (remainder as i64).wrapping_neg() } else { remainder as i64 };
+    (quotient, remainder)
+}
@@ -0,0 +1,118 @@
+//! Where the charge of the battery goes.
+//!
+//! The multiplex timer counts its ticks while the watch is awake, and of those the ones with the
+//! display lit and with the high voltage supply on. The rest of the time on battery was spent in
+//! STOP mode. With a current figure for each state from the settings that gives the charge used
+//! and the average current, which estimates how long the charge left in the battery lasts.
+//! Counting starts over whenever the charger is unplugged, so it covers the current charge.
+
+/// Current the watch draws in each state, in µA.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Currents {
+    /// In STOP mode, mostly the motion sensor and the LDO
+    pub sleep: u32,
+    /// Awake with the multiplex timer running
+    pub run: u32,
+    /// On top of running while the display is lit
+    pub display: u32,
+    /// On top of that while the high voltage supply is enabled
+    pub hv: u32
+}
+
+/// Ticks and wakes since the counting started, see `Meter::usage` for the totals.
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+pub struct Usage {
+    /// Seconds on battery
+    pub seconds: u32,
+    /// Seconds of those awake, with the display lit and with the high voltage on
+    pub awake: u32,
+    pub display: u32,
+    pub hv: u32,
+    /// Seconds with USB connected, which powers the watch
+    pub usb: u32,
+    /// Times the watch woke up from STOP mode
+    pub wakes: u32,
+    /// Charge drawn from the battery in µAh
+    pub used_uah: u32
+}
+
+impl Usage {
+    /// Average current in µA, `None` before the first second on battery.
+    pub fn average_ua(&self) -> Option<u32> {
+        if self.seconds == 0 {
+            return None;
+        }
+        Some((self.used_uah as u64 * 3600 / self.seconds as u64) as u32)
+    }
+
+    /// Hours the `remaining_uah` last at the average current so far.
+    pub fn runtime_hours(&self, remaining_uah: u32) -> Option<u32> {
+        self.average_ua().filter(|&average| average > 0).map(|average| remaining_uah / average)
+    }
+}
+
+/// Charge left in µAh in a battery of `capacity_mah` at `percent`.
+pub fn remaining_uah(capacity_mah: u16, percent: u8) -> u32 {
+    capacity_mah as u32 * 10 * percent.min(100) as u32
+}
+
+#[derive(Default)]
+pub struct Meter {
+    /// Uptime in seconds the counting started at
+    start: u32,
+    awake_ticks: u32,
+    display_ticks: u32,
+    hv_ticks: u32,
+    usb_ticks: u32,
+    /// Ticks on USB power, which don't count as time on battery
+    external_ticks: u32,
+    wakes: u32
+}
+
+impl Meter {
+    pub const fn new() -> Self {
+        Meter { start: 0, awake_ticks: 0, display_ticks: 0, hv_ticks: 0, usb_ticks: 0, external_ticks: 0, wakes: 0 }
+    }
+
+    /// Counts one tick of the multiplex timer. `external_power` is set while the charger has USB power.
+    pub fn tick(&mut self, display_on: bool, hv_on: bool, usb_connected: bool, external_power: bool) {
+        self.usb_ticks += usb_connected as u32;
+        if external_power {
+            self.external_ticks += 1;
+            return;
+        }
+        self.awake_ticks += 1;
+        self.display_ticks += display_on as u32;
+        self.hv_ticks += hv_on as u32;
+    }
+
+    pub fn wake(&mut self) {
+        self.wakes = self.wakes.wrapping_add(1);
+    }
+
+    /// The charger was unplugged at `uptime`, count the new charge from there.
+    pub fn restart(&mut self, uptime: u32) {
+        *self = Meter { start: uptime, ..Meter::new() };
+    }
+
+    /// The totals at `uptime` for the timer running at `ticks_per_second`.
+    pub fn usage(&self, uptime: u32, ticks_per_second: u32, currents: &Currents) -> Usage {
+        let seconds = |ticks: u32| ticks / ticks_per_second.max(1);
+        let on_battery = uptime.wrapping_sub(self.start).saturating_sub(seconds(self.external_ticks));
+        let asleep = on_battery.saturating_sub(seconds(self.awake_ticks));
+        // In µA per tick, a tick being 1 / ticks_per_second
+        let awake = self.awake_ticks as u64 * currents.run as u64
+            + self.display_ticks as u64 * currents.display as u64
+            + self.hv_ticks as u64 * currents.hv as u64;
+        let used_uas = awake / ticks_per_second.max(1) as u64 + asleep as u64 * currents.sleep as u64;
+        Usage {
+            seconds: on_battery,
+            awake: seconds(self.awake_ticks),
+            display: seconds(self.display_ticks),
+            hv: seconds(self.hv_ticks),
+            usb: seconds(self.usb_ticks),
+            wakes: self.wakes,
+            used_uah: (used_uas / 3600) as u32
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Telling firmware images apart, so the host tools only flash an image onto the board it was built for.
+//!
+//! Every image embeds an `ImageInfo` record, found by its magic bytes. The watch reports the board
+//! revision and the CRC of the image it runs in `STATUS`, which lets the host check an update before
+//! flashing it and again once the new image runs.
+
+use crate::settings::crc16;
+
+/// Starts the image info record, unlikely to show up anywhere else in an image
+pub const MAGIC: [u8; 8] = *b"NXWATCH\x01";
+
+/// Size of the record in the image.
+pub const RECORD_LEN: usize = MAGIC.len() + 1;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ImageInfo {
+    /// Revision of the PCB the image was built for
+    pub board_revision: u8
+}
+
+impl ImageInfo {
+    /// The record as it is stored in the image.
+    pub const fn record(board_revision: u8) -> [u8; RECORD_LEN] {
+        let mut record = [0; RECORD_LEN];
+        let mut i = 0;
+        while i < MAGIC.len() {
+            record[i] = MAGIC[i];
+            i += 1;
+        }
+        record[MAGIC.len()] = board_revision;
+        record
+    }
+
+    /// Looks for the record in an image, `None` if it is not a watch image.
+    pub fn find(image: &[u8]) -> Option<ImageInfo> {
+        image.windows(RECORD_LEN)
+            .find(|window| window[..MAGIC.len()] == MAGIC)
+            .map(|record| ImageInfo { board_revision: record[MAGIC.len()] })
+    }
+}
+
+/// CRC-16/CCITT-FALSE over the whole image, the way the watch reports it.
+pub fn image_crc(image: &[u8]) -> u16 {
+    crc16(0xFFFF, image)
+}
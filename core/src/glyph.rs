@@ -0,0 +1,76 @@
+//! What a tube shows, as a segment mask.
+//!
+//! Segment a up to g are bit 0 up to 6 and the decimal point is bit 7:
+//!
+//! ```text
+//!  aaa
+//! f   b
+//!  ggg
+//! e   c
+//!  ddd  .
+//! ```
+
+/// Bar graph steps, one segment each
+pub const BAR_LEVELS: u8 = 6;
+
+const DOT: u8 = 0x80;
+
+/// Glyphs of the printable ASCII characters from the space up to `~`. Letters that can't be told
+/// apart from a digit or another letter on seven segments look the same, `M` and `W` are half drawn
+/// and `*` stands in for the degree sign, which isn't ASCII.
+const FONT: [u8; 95] = [
+    0x00, 0x86, 0x22, 0x7E, 0x6D, 0xD2, 0x46, 0x20, 0x29, 0x0B, 0x63, 0x70, 0x10, 0x40, 0x80, 0x52, // space to /
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F, 0x09, 0x0D, 0x61, 0x48, 0x43, 0xD3, // 0 to ?
+    0x5F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71, 0x3D, 0x76, 0x30, 0x1E, 0x75, 0x38, 0x15, 0x37, 0x3F, // @ to O
+    0x73, 0x6B, 0x33, 0x6D, 0x78, 0x3E, 0x3E, 0x2A, 0x76, 0x6E, 0x5B, 0x39, 0x64, 0x0F, 0x23, 0x08, // P to _
+    0x02, 0x5F, 0x7C, 0x58, 0x5E, 0x7B, 0x71, 0x6F, 0x74, 0x10, 0x0C, 0x75, 0x30, 0x14, 0x54, 0x5C, // ` to o
+    0x73, 0x67, 0x50, 0x6D, 0x78, 0x1C, 0x1C, 0x14, 0x76, 0x6E, 0x5B, 0x46, 0x30, 0x70, 0x01        // p to ~
+];
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct Glyph(u8);
+
+impl Glyph {
+    pub const BLANK: Glyph = Glyph(0x00);
+    pub const MINUS: Glyph = Glyph(0x40);
+    pub const DEGREE: Glyph = Glyph(0x63);
+
+    pub const fn from_segments(segments: u8) -> Glyph {
+        Glyph(segments)
+    }
+
+    /// The segment mask, the point included.
+    pub const fn segments(self) -> u8 {
+        self.0
+    }
+
+    /// Digit `value`, blank above 9.
+    pub fn digit(value: u8) -> Glyph {
+        if value < 10 {
+            Glyph::from_ascii(b'0' + value)
+        } else {
+            Glyph::BLANK
+        }
+    }
+
+    /// Bar graph of `level` segments lit clockwise from the top, up to `BAR_LEVELS`.
+    pub fn bar(level: u8) -> Glyph {
+        Glyph((1 << level.min(BAR_LEVELS)) - 1)
+    }
+
+    /// Glyph of an ASCII character, blank for anything not printable.
+    pub fn from_ascii(c: u8) -> Glyph {
+        match c {
+            b' '..=b'~' => Glyph(FONT[(c - b' ') as usize]),
+            _ => Glyph::BLANK
+        }
+    }
+
+    pub const fn with_dot(self) -> Glyph {
+        Glyph(self.0 | DOT)
+    }
+
+    pub const fn has_dot(self) -> bool {
+        self.0 & DOT != 0
+    }
+}
@@ -0,0 +1,25 @@
+//! Hardware independent part of the nixie watch firmware.
+//!
+//! Everything in here only talks to the outside world through `embedded_hal` traits,
+//! so it can be unit tested on the host with mock pins.
+#![no_std]
+
+pub mod battery;
+pub mod calendar;
+pub mod calibration;
+pub mod charger;
+pub mod command;
+pub mod div;
+pub mod energy;
+pub mod firmware;
+pub mod glyph;
+pub mod mem;
+pub mod nixie_segment;
+pub mod power;
+pub mod ring_buffer;
+pub mod self_test;
+pub mod sequence;
+pub mod settings;
+pub mod text;
+pub mod time_source;
+pub mod wear;
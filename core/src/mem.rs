@@ -0,0 +1,41 @@
+//! Byte by byte copies for the firmware's `memcpy`, `memmove` and `memset`.
+//!
+//! The ones of `compiler_builtins` copy word by word with a path for every alignment, which takes
+//! about 2.3K of flash against 80 bytes for these. The copies in the watch are a few hundred bytes at
+//! most, so the speed doesn't matter.
+//!
+//! `copy` is a correct `memmove` for any overlap: it runs forwards when `dest` is below `src` and
+//! backwards otherwise, so every byte is read before the copy overwrites it. `memcpy` and `memcpy4`
+//! only promise more, no overlap and word alignment, which `copy` doesn't rely on. The volatile
+//! accesses keep LLVM from recognising the loops as a copy or fill and turning them back into calls to
+//! the very functions they implement, which would recurse forever.
+
+use core::ptr;
+
+/// Copies `n` bytes from `src` to `dest`, which may overlap.
+///
+/// # Safety
+///
+/// Both have to be valid for `n` bytes.
+pub unsafe fn copy(dest: *mut u8, src: *const u8, n: usize) {
+    if (dest as usize) < (src as usize) {
+        for i in 0..n {
+            ptr::write_volatile(dest.add(i), ptr::read_volatile(src.add(i)));
+        }
+    } else {
+        for i in (0..n).rev() {
+            ptr::write_volatile(dest.add(i), ptr::read_volatile(src.add(i)));
+        }
+    }
+}
+
+/// Sets `n` bytes at `dest` to `value`.
+///
+/// # Safety
+///
+/// `dest` has to be valid for `n` bytes.
+pub unsafe fn fill(dest: *mut u8, n: usize, value: u8) {
+    for i in 0..n {
+        ptr::write_volatile(dest.add(i), value);
+    }
+}
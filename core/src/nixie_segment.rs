@@ -0,0 +1,651 @@
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::battery::State;
+use crate::calendar::{Date, DateTime, Time};
+use crate::glyph::{Glyph, BAR_LEVELS};
+use crate::self_test::{self, Step};
+use crate::sequence::{Content, Frame, Sequence, Transition};
+use crate::text::Text;
+use crate::time_source::TimeSource;
+use crate::wear::{Wear, SEGMENTS};
+
+/// Steps per second the charging animation fills the bar graph with
+const CHARGING_STEPS_PER_SECOND: u32 = 6;
+
+/// Digits per second the exercise cycles through
+const EXERCISE_STEPS_PER_SECOND: u32 = 5;
+
+/// How long a frame takes to take over from the last one with a transition other than a cut
+const TRANSITION_MS: u32 = 500;
+
+/// Digits per second a rolling transition goes through
+const ROLL_STEPS_PER_SECOND: u32 = 20;
+
+/// How long AM or PM shows before the hours on a 12-hour clock
+const AM_PM_MS: u32 = 400;
+
+/// Brightness steps of the display, the highest is full brightness
+pub const BRIGHTNESS_LEVELS: u8 = 8;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum DotStatus {
+    Off,
+    Digit1,
+    Digit2
+}
+
+/// How a 12-hour clock tells the morning from the afternoon.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum AmPm {
+    /// The point of the hours frame only lights in the afternoon
+    Dot,
+    /// The hours frame starts with AM or PM
+    Letter
+}
+
+pub struct NixieDisplay<P: OutputPin> {
+    nixie1: P,
+    nixie2: P,
+    segments: [P; 7],
+    dot: P,
+    enable: P,
+
+    nixie1_value: Glyph,
+    nixie2_value: Glyph,
+    display_counter: u8,
+    dot_status: DotStatus,
+    /// Levels of a multiplex period the lit tube stays on
+    brightness: u8,
+    /// `update` leaves lighting the tube to `light`
+    blanking: bool,
+    /// Multiplex periods every segment was lit, in the order of `Wear::seconds`
+    lit: [u32; 2 * SEGMENTS],
+    /// The high voltage supply is enabled
+    enabled: bool
+}
+
+
+
+impl<P: OutputPin> NixieDisplay<P> {
+    pub fn new(nixie1: P, nixie2: P, segments: [P;7], dot: P, enable: P) -> Self {
+        NixieDisplay {
+            nixie1,
+            nixie2,
+            segments,
+            dot,
+            enable,
+            nixie1_value: Glyph::digit(3),
+            nixie2_value: Glyph::digit(8),
+            display_counter: 0,
+            dot_status: DotStatus::Off,
+            brightness: BRIGHTNESS_LEVELS,
+            blanking: false,
+            lit: [0; 2 * SEGMENTS],
+            enabled: false,
+        }
+    }
+
+    /// gets the bit at position `n`. Bits are numbered from 0 (least significant) to 31 (most significant).
+    fn get_bit_at(input: u32, n: u8) -> bool {
+        if n < 32 {
+        input & (1 << n) != 0
+        } else {
+        false
+        }
+    }
+
+    /// Lights the tube picked by the last `update`, its anode while the segments are still low and then the segments.
+    pub fn light(&mut self) {
+        let tube = (self.display_counter & 1) as usize;
+        let (digit_to_display, dot) = if tube == 0 {
+            self.nixie1.set_high().ok();
+            (self.nixie1_value, self.dot_status == DotStatus::Digit1)
+        } else {
+            self.nixie2.set_high().ok();
+            (self.nixie2_value, self.dot_status == DotStatus::Digit2)
+        };
+
+        let lit = &mut self.lit[tube * SEGMENTS..];
+        for (i, s) in self.segments.iter_mut().enumerate() {
+            if Self::get_bit_at(digit_to_display.segments() as u32, i as u8) {
+                s.set_high().ok();
+                lit[i] += 1;
+              } else {
+                s.set_low().ok();
+              }
+        }
+        if dot {
+            self.dot.set_high().ok();
+            lit[SEGMENTS - 1] += 1;
+        }
+    }
+
+    /// Multiplex periods every segment was lit since the start, see `wear`.
+    pub fn lit_periods(&self) -> &[u32; 2 * SEGMENTS] {
+        &self.lit
+    }
+
+    /// Darkens the display and moves on to the next tube. Without blanking the tube is lit right away, with it
+    /// `light` has to follow once the anodes settled. Below full brightness `blank` darkens it after `on_time`.
+    pub fn update(&mut self) {
+        self.display_counter = self.display_counter.wrapping_add(1);
+        self.blank();
+        if !self.blanking {
+            self.light();
+        }
+    }
+
+    /// Shows `glyph` on tube `digit`, the point follows `dot_status` rather than the glyph.
+    pub fn set_digit(&mut self, digit: u8, glyph: Glyph, dot_status: DotStatus) {
+        if digit == 0 {
+            self.nixie1_value = glyph;
+        } else {
+            self.nixie2_value = glyph;
+        }
+        self.dot_status = dot_status;
+    }
+
+    /// From 1 up to `BRIGHTNESS_LEVELS`.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness.clamp(1, BRIGHTNESS_LEVELS);
+    }
+
+    /// Levels out of `BRIGHTNESS_LEVELS` of the multiplex period the tube lit by `update` stays on.
+    pub fn on_time(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Darkens the tubes for the rest of the multiplex period, the high voltage stays on. The segments go low
+    /// before the anodes, so the anodes never switch with a segment conducting.
+    pub fn blank(&mut self) {
+        for s in &mut self.segments {
+            s.set_low().ok();
+        }
+        self.dot.set_low().ok();
+        self.nixie1.set_low().ok();
+        self.nixie2.set_low().ok();
+    }
+
+    /// With blanking the tubes stay dark after `update` until `light`, the timer sets the dead time in between.
+    pub fn set_blanking(&mut self, blanking: bool) {
+        self.blanking = blanking;
+    }
+
+    pub fn on(&mut self) {
+        self.enable.set_high().ok();
+        self.enabled = true;
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn off(&mut self) {
+        self.blank();
+        self.enable.set_low().ok();
+        self.enabled = false;
+    }
+}
+
+const TIME: Sequence = Sequence::paced([(Content::Hours, DotStatus::Digit1), (Content::Minutes, DotStatus::Digit2),
+                                        (Content::Blank, DotStatus::Off), (Content::Blank, DotStatus::Off)]);
+const TIME_AND_CHARGE: Sequence = Sequence::paced([(Content::Hours, DotStatus::Digit1), (Content::Minutes, DotStatus::Digit2),
+                                                   (Content::Charge, DotStatus::Off), (Content::Blank, DotStatus::Off)]);
+const DATE: Sequence = Sequence::paced([(Content::Day, DotStatus::Digit2), (Content::Month, DotStatus::Digit2),
+                                        (Content::Blank, DotStatus::Off), (Content::Blank, DotStatus::Off)]);
+const CHARGE: Sequence = Sequence::paced([(Content::Charge, DotStatus::Off), (Content::Charge, DotStatus::Off),
+                                          (Content::Charge, DotStatus::Off), (Content::Blank, DotStatus::Off)]);
+const EMPTY: Sequence = Sequence::paced([(Content::Empty, DotStatus::Digit1), (Content::Empty, DotStatus::Digit2),
+                                         (Content::Blank, DotStatus::Off), (Content::Blank, DotStatus::Off)]);
+const CHARGING: Sequence = Sequence::paced([(Content::Charging, DotStatus::Off), (Content::Charging, DotStatus::Off),
+                                            (Content::Charging, DotStatus::Off), (Content::Blank, DotStatus::Off)]);
+
+pub struct NixieClock<P: OutputPin, T: TimeSource> {
+    nixie_display: NixieDisplay<P>,
+    time_source: T,
+    ticks_per_second: u32,
+    /// Ticks a frame paced by the display duration takes
+    frame_ticks: u32,
+    /// Ticks a text stays at each step of its scroll
+    scroll_ticks: u32,
+    /// Shown by `Content::Message`
+    message: Text,
+    /// The sequence on the display, or the next one if `restart` is set
+    sequence: Sequence,
+    /// Shown on a wake up instead of the time and charge, unless empty
+    wake_sequence: Sequence,
+    restart: bool,
+    frame: u8,
+    /// Ticks since the current frame and since the sequence started
+    frame_counter: u32,
+    display_counter: u32,
+    charge_level: u8,
+    battery_state: State,
+    /// Brightness of a full brightness frame, and the most it gets with a low battery
+    brightness: u8,
+    brightness_low: u8,
+    /// Ticks `Content::Exercise` lasts
+    exercise_ticks: u32,
+    /// Segment wear up to the start, the display adds what it lit since
+    wear: Wear,
+    displaying: bool,
+    /// What the current frame shows, and what the last one showed as the current one took over
+    shown: [Glyph; 2],
+    from: [Glyph; 2],
+    /// `None` for the 24-hour clock
+    am_pm: Option<AmPm>
+}
+
+impl<P: OutputPin, T: TimeSource> NixieClock<P, T> {
+    /// `ticks_per_second` is the rate `tick` is called at, which sets the pace of the display sequence.
+    pub fn new(nixie_display: NixieDisplay<P>, time_source: T, ticks_per_second: u32) -> Self {
+        NixieClock{
+            nixie_display,
+            time_source,
+            ticks_per_second,
+            frame_ticks: ticks_per_second,
+            scroll_ticks: ticks_per_second / 4,
+            message: Text::new(),
+            sequence: Sequence::new(),
+            wake_sequence: Sequence::new(),
+            restart: false,
+            frame: 0,
+            frame_counter: 0,
+            display_counter: 0,
+            charge_level: 50,
+            battery_state: State::Ok,
+            brightness: BRIGHTNESS_LEVELS,
+            brightness_low: BRIGHTNESS_LEVELS,
+            exercise_ticks: ticks_per_second * 60,
+            wear: Wear::default(),
+            displaying: false,
+            shown: [Glyph::BLANK; 2],
+            from: [Glyph::BLANK; 2],
+            am_pm: None
+        }
+    }
+
+    /// Sets hours and minutes, the minute starts right away.
+    pub fn set_time(&mut self, hours: u8, minutes: u8)
+    {
+        let mut now = self.time_source.now();
+        if let Some(time) = Time::new(hours, minutes, 0) {
+            now.time = time;
+            self.time_source.set(now);
+        }
+    }
+
+    pub fn get_time(&mut self) -> (u8, u8) {
+        let now = self.time_source.now();
+        (now.time.hours(), now.time.minutes())
+    }
+
+    pub fn now(&mut self) -> DateTime {
+        self.time_source.now()
+    }
+
+    pub fn set_date_time(&mut self, now: DateTime) {
+        self.time_source.set(now);
+    }
+
+    pub fn time_source(&mut self) -> &mut T {
+        &mut self.time_source
+    }
+
+    pub fn set_date(&mut self, date: Date) {
+        let mut now = self.time_source.now();
+        now.date = date;
+        self.time_source.set(now);
+    }
+
+    pub fn get_date(&mut self) -> Date {
+        self.time_source.now().date
+    }
+
+    /// Sets how long the built in sequences keep the display on, in ms.
+    pub fn set_display_duration(&mut self, duration: u32) {
+        self.frame_ticks = (self.ticks_per_second * duration / 4000).max(1);
+    }
+
+    /// Sets how long a scrolling text stays at each step, in ms.
+    pub fn set_scroll_step(&mut self, step: u32) {
+        self.scroll_ticks = (self.ticks_per_second * step / 1000).max(1);
+    }
+
+    /// Scrolls `message` across the display once, it stays around for `Content::Message`.
+    pub fn show_message(&mut self, message: Text) {
+        self.message = message;
+        self.play_content(Content::Message);
+    }
+
+    /// Scales the brightness of every frame, down to `low` while the battery is low.
+    pub fn set_brightness(&mut self, brightness: u8, low: u8) {
+        self.brightness = brightness;
+        self.brightness_low = low;
+    }
+
+    /// Levels out of `BRIGHTNESS_LEVELS` of the multiplex period the lit tube stays on, see `NixieDisplay::blank`.
+    pub fn on_time(&self) -> u8 {
+        self.nixie_display.on_time()
+    }
+
+    /// Darkens the lit tube until the next tick.
+    pub fn blank(&mut self) {
+        self.nixie_display.blank();
+    }
+
+    /// Leaves lighting the tube after a tick to `light`, for a dead time between the tubes against ghosting.
+    pub fn set_blanking(&mut self, blanking: bool) {
+        self.nixie_display.set_blanking(blanking);
+    }
+
+    /// Lights the tube the last tick picked, unless the display went off since.
+    pub fn light(&mut self) {
+        if self.nixie_display.is_on() {
+            self.nixie_display.light();
+        }
+    }
+
+    /// Sets how long the exercise of the segments takes, in seconds.
+    pub fn set_exercise_duration(&mut self, duration: u32) {
+        self.exercise_ticks = (self.ticks_per_second * duration).max(1);
+    }
+
+    /// Shows the hours of a 12-hour clock with the morning or afternoon told by `am_pm`, `None` for the 24-hour clock.
+    pub fn set_twelve_hour(&mut self, am_pm: Option<AmPm>) {
+        self.am_pm = am_pm;
+    }
+
+    /// Cycles through all digits to light every segment, see `wear`.
+    pub fn exercise(&mut self) {
+        self.play_content(Content::Exercise);
+    }
+
+    /// Runs the self test of the display, see `self_test`.
+    pub fn self_test(&mut self) {
+        self.play_content(Content::SelfTest);
+    }
+
+    /// Index of the self test step showing, `None` unless the self test runs.
+    pub fn self_test_step(&self) -> Option<u32> {
+        match self.current_frame() {
+            Some(frame) if self.displaying && !self.restart && frame.content == Content::SelfTest => {
+                Some(self.frame_counter.saturating_sub(1) * self_test::STEPS_PER_SECOND / self.ticks_per_second)
+            }
+            _ => None
+        }
+    }
+
+    /// Sets the wear stored before the start.
+    pub fn set_wear(&mut self, wear: Wear) {
+        self.wear = wear;
+    }
+
+    /// The stored wear with what the display lit since the start.
+    pub fn wear(&self) -> Wear {
+        let mut wear = self.wear;
+        for (seconds, periods) in wear.seconds.iter_mut().zip(self.nixie_display.lit_periods()) {
+            *seconds += periods / self.ticks_per_second;
+        }
+        wear
+    }
+
+    /// Starts `sequence` from its first frame on the next tick.
+    pub fn play(&mut self, sequence: Sequence) {
+        self.sequence = sequence;
+        self.restart = true;
+    }
+
+    /// Plays a single frame of `content` for as long as it takes.
+    fn play_content(&mut self, content: Content) {
+        let mut sequence = Sequence::new();
+        sequence.push(Frame::new(content, DotStatus::Off, 0));
+        self.play(sequence);
+    }
+
+    /// Sets what `wake_up` shows, an empty sequence for the time and charge.
+    pub fn set_wake_sequence(&mut self, sequence: Sequence) {
+        self.wake_sequence = sequence;
+    }
+
+    pub fn show_time(&mut self) {
+        self.play(TIME);
+    }
+
+    pub fn show_date(&mut self) {
+        self.play(DATE);
+    }
+
+    pub fn show_charge(&mut self) {
+        self.play(CHARGE);
+    }
+
+    pub fn show_empty(&mut self) {
+        self.play(EMPTY);
+    }
+
+    pub fn show_time_and_charge(&mut self) {
+        self.play(TIME_AND_CHARGE);
+    }
+
+    pub fn set_charge_level(&mut self, charge_level: u8) {
+        self.charge_level = charge_level;
+    }
+
+    pub fn set_battery_state(&mut self, battery_state: State) {
+        self.battery_state = battery_state;
+    }
+
+    pub fn battery_state(&self) -> State {
+        self.battery_state
+    }
+
+    /// Plays the charging animation for three frames, for when the charger gets power.
+    pub fn show_charging(&mut self) {
+        self.play(CHARGING);
+    }
+
+    /// Shows what a wake up shows in the current battery state, the wake sequence unless the battery is critical.
+    /// While charging the bar graph is animated.
+    pub fn wake_up(&mut self) {
+        if self.battery_state == State::Critical {
+            self.show_empty();
+        } else if self.wake_sequence.is_empty() {
+            self.show_time_and_charge();
+        } else {
+            self.play(self.wake_sequence);
+        }
+    }
+
+    /// Bar graph level of the charging animation, filling up from `level` to full and starting over.
+    fn charging_level(&self, level: u8) -> u8 {
+        let steps = (BAR_LEVELS + 1 - level) as u32;
+        let step = self.display_counter * CHARGING_STEPS_PER_SECOND / self.ticks_per_second;
+        level + (step % steps) as u8
+    }
+
+    /// True while the display is lit or will be lit by the next `tick`.
+    pub fn is_display_on(&mut self) -> bool {
+        self.displaying || self.restart
+    }
+
+    /// True while the high voltage supply is enabled, which may outlast the lit display by a frame.
+    pub fn is_hv_on(&self) -> bool {
+        self.nixie_display.is_on()
+    }
+
+    /// The hours as the clock shows them, on a 12-hour clock with `set_twelve_hour`.
+    fn hours(&self, time: Time) -> u8 {
+        if self.am_pm.is_some() { time.hours_12() } else { time.hours() }
+    }
+
+    /// The scrolling text `content` shows, if it is one.
+    fn text(&mut self, content: Content) -> Option<Text> {
+        let now = self.time_source.now();
+        let mut text = Text::new();
+        match content {
+            Content::TimeText => {
+                text.push_number(self.hours(now.time) as u32, 2);
+                text.push(b'.');
+                text.push_number(now.time.minutes() as u32, 2);
+                if self.am_pm.is_some() {
+                    text.push(b"AP"[now.time.is_pm() as usize]);
+                }
+            }
+            Content::DateText => {
+                text.push_number(now.date.year() as u32, 4);
+                text.push(b'-');
+                text.push_number(now.date.month() as u32, 2);
+                text.push(b'-');
+                text.push_number(now.date.day() as u32, 2);
+            }
+            Content::Message => text = self.message,
+            _ => return None
+        }
+        Some(text)
+    }
+
+    fn frame_ticks(&mut self, frame: Frame) -> u32 {
+        match (frame.duration, self.text(frame.content)) {
+            (0, Some(text)) => text.steps() * self.scroll_ticks,
+            (0, None) if frame.content == Content::Exercise => self.exercise_ticks,
+            (0, None) if frame.content == Content::SelfTest => {
+                self_test::STEPS * self.ticks_per_second / self_test::STEPS_PER_SECOND
+            }
+            (0, None) => self.frame_ticks,
+            (tenths, _) => (tenths as u32 * self.ticks_per_second / 10).max(1)
+        }
+    }
+
+    fn current_frame(&self) -> Option<Frame> {
+        self.sequence.frames().get(self.frame as usize).copied()
+    }
+
+    fn show(&mut self, frame: Frame) {
+        let now = self.time_source.now();
+        let level = (self.charge_level / 16).min(BAR_LEVELS); // 0 to 100 convert to 0 to 6 segments.
+        let digits = |number: u8| [Glyph::digit(number / 10), Glyph::digit(number % 10)];
+        let glyphs = match frame.content {
+            Content::Hours if self.am_pm == Some(AmPm::Letter)
+                && self.frame_counter <= self.ticks_per_second * AM_PM_MS / 1000 => {
+                [Glyph::from_ascii(b"AP"[now.time.is_pm() as usize]), Glyph::from_ascii(b'M')]
+            }
+            Content::Hours => digits(self.hours(now.time)),
+            Content::Minutes => digits(now.time.minutes()),
+            Content::Seconds => digits(now.time.seconds()),
+            Content::Day => digits(now.date.day()),
+            Content::Month => digits(now.date.month()),
+            Content::Charge if self.battery_state != State::Charging => [Glyph::bar(level); 2],
+            Content::Charge | Content::Charging => [Glyph::bar(self.charging_level(level)); 2],
+            Content::Empty => [Glyph::BLANK; 2],
+            Content::Exercise => {
+                // The point moves between the tubes with every digit
+                let step = self.frame_counter * EXERCISE_STEPS_PER_SECOND / self.ticks_per_second;
+                let digit = Glyph::digit((step % 10) as u8);
+                if step & 1 == 0 { [digit.with_dot(), digit] } else { [digit, digit.with_dot()] }
+            }
+            Content::SelfTest => match self.self_test_step().and_then(Step::at) {
+                Some(Step::Hv(false)) | None => {
+                    self.nixie_display.off();
+                    return;
+                }
+                Some(step) => step.glyphs()
+            },
+            Content::Blank => {
+                self.nixie_display.off();
+                return;
+            }
+            Content::TimeText | Content::DateText | Content::Message => {
+                let text = self.text(frame.content).unwrap_or_default();
+                // Scrolls over and over if the frame outlasts the text
+                text.window((self.frame_counter - 1) / self.scroll_ticks % text.steps())
+            }
+        };
+        // The point of a text glyph wins over the one of the frame
+        let dot = if glyphs[0].has_dot() {
+            DotStatus::Digit1
+        } else if glyphs[1].has_dot() {
+            DotStatus::Digit2
+        } else if frame.content == Content::Hours && self.am_pm == Some(AmPm::Dot) {
+            // Only the afternoon lights the point, on the first tube if the frame has none of its own
+            match (now.time.is_pm(), frame.dot) {
+                (false, _) => DotStatus::Off,
+                (true, DotStatus::Off) => DotStatus::Digit1,
+                (true, dot) => dot
+            }
+        } else {
+            frame.dot
+        };
+
+        self.nixie_display.on();
+        let brightness = match self.battery_state {
+            State::Low | State::Critical => self.brightness.min(self.brightness_low),
+            _ => self.brightness
+        };
+        // Rounded up, the dimmest frame stays visible
+        let level = (frame.brightness as u32 * brightness as u32).div_ceil(BRIGHTNESS_LEVELS as u32);
+        self.shown = glyphs;
+        let (glyphs, level) = self.transition(glyphs, level);
+        self.nixie_display.set_brightness(level as u8);
+        self.nixie_display.set_digit(0, glyphs[0], dot);
+        self.nixie_display.set_digit(1, glyphs[1], dot);
+        self.nixie_display.update();
+    }
+
+    /// What the tubes show and how bright while the current frame takes over from the last one.
+    fn transition(&self, new: [Glyph; 2], level: u32) -> ([Glyph; 2], u32) {
+        let ticks = self.ticks_per_second * TRANSITION_MS / 1000;
+        let t = self.frame_counter;
+        if self.frame == 0 || t >= ticks {
+            return (new, level);
+        }
+        let old = self.from;
+        match self.sequence.transition {
+            Transition::Cut => (new, level),
+            // Every other time a tube lights it shows the old frame, each as bright as its share of the fade
+            Transition::Fade if self.display_counter & 2 == 0 => (new, level * t / ticks),
+            Transition::Fade => (old, level * (ticks - t) / ticks),
+            Transition::Wipe => {
+                let taken = (1 << (t * 8 / ticks)) - 1;
+                let wipe = |i: usize| Glyph::from_segments(new[i].segments() & taken | old[i].segments() & !taken);
+                ([wipe(0), wipe(1)], level)
+            }
+            Transition::Roll => {
+                let digit = Glyph::digit((t * ROLL_STEPS_PER_SECOND / self.ticks_per_second % 10) as u8);
+                (if 2 * t < ticks { [digit; 2] } else { [new[0], digit] }, level)
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.time_source.tick();
+
+        if self.restart {
+            self.restart = false;
+            self.displaying = true;
+            self.frame = 0;
+            self.frame_counter = 0;
+            self.display_counter = 0;
+        }
+        if !self.displaying {
+            return;
+        }
+
+        self.display_counter += 1;
+        self.frame_counter += 1;
+        if let Some(frame) = self.current_frame() {
+            if self.frame_counter > self.frame_ticks(frame) {
+                self.frame += 1;
+                self.frame_counter = 1;
+                self.from = if self.nixie_display.is_on() { self.shown } else { [Glyph::BLANK; 2] };
+            }
+        }
+        match self.current_frame() {
+            Some(frame) => self.show(frame),
+            None => {
+                self.displaying = false;
+                self.nixie_display.off();
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! When the watch can sleep, and how deeply.
+//!
+//! The multiplex timer only runs while there is something to do: the display is lit, USB is
+//! connected or the charger is being watched. Once it stopped the core goes to STOP mode, which
+//! turns off all clocks but the RTC, until the motion sensor, the charger or the RTC alarm raises
+//! an interrupt. The clocks have to be restored before any interrupt handler runs, the handler
+//! then pends the multiplex timer, which decides whether to start again.
+
+/// How often the multiplex timer looks at the motion sensor, whatever its rate.
+pub const MOTION_CHECKS_PER_SECOND: u32 = 4;
+
+/// What keeps the watch awake.
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+pub struct Activity {
+    pub display_on: bool,
+    /// The USB peripheral needs its clocks, STOP mode would drop the host
+    pub usb_connected: bool,
+    /// On USB power or a new charger state is being debounced
+    pub charger_busy: bool
+}
+
+impl Activity {
+    fn is_busy(&self) -> bool {
+        self.display_on || self.usb_connected || self.charger_busy
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Mode {
+    /// The multiplex timer runs
+    Running,
+    /// The timer is stopped, waiting for the main loop to enter STOP
+    Idle,
+    /// In STOP mode, until an interrupt restores the clocks
+    Stopped
+}
+
+pub struct PowerManager {
+    mode: Mode,
+    usb_connected: bool,
+    stops: u32
+}
+
+impl Default for PowerManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerManager {
+    /// Starts out running, the multiplex timer is started with the rest of the hardware.
+    pub const fn new() -> Self {
+        PowerManager { mode: Mode::Running, usb_connected: false, stops: 0 }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// How often STOP mode was entered since the reset.
+    pub fn stops(&self) -> u32 {
+        self.stops
+    }
+
+    /// Called by the multiplex timer every tick, returning the mode once it changed so the timer
+    /// can be started or stopped.
+    pub fn update(&mut self, activity: Activity) -> Option<Mode> {
+        self.usb_connected = activity.usb_connected;
+        let mode = if activity.is_busy() { Mode::Running } else { Mode::Idle };
+        if mode == self.mode {
+            return None;
+        }
+        self.mode = mode;
+        Some(mode)
+    }
+
+    /// Called by the main loop with interrupts disabled, true if it should enter STOP mode now.
+    pub fn stop(&mut self) -> bool {
+        if self.mode != Mode::Idle || self.usb_connected {
+            return false;
+        }
+        self.mode = Mode::Stopped;
+        self.stops = self.stops.wrapping_add(1);
+        true
+    }
+
+    /// The clocks are back after STOP mode, the interrupt that woke the watch decides what's next.
+    pub fn wake(&mut self) {
+        if self.mode == Mode::Stopped {
+            self.mode = Mode::Idle;
+        }
+    }
+
+    /// USB got connected or disconnected between two ticks of the timer.
+    pub fn set_usb_connected(&mut self, connected: bool) {
+        self.usb_connected = connected;
+    }
+}
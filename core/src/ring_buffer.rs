@@ -0,0 +1,69 @@
+//! Byte queue between code producing output and a port that takes it whenever it can.
+
+use crate::command::Output;
+
+/// Fixed size FIFO of bytes. Writes that don't fit are cut short and counted, they never block.
+pub struct RingBuffer<const N: usize> {
+    data: [u8; N],
+    start: usize,
+    len: usize,
+    /// Bytes that didn't fit since the buffer was created
+    dropped: u32
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        RingBuffer { data: [0; N], start: 0, len: 0, dropped: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Queues as much of `data` as fits, returning how much that was.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(N - self.len);
+        for &byte in &data[..count] {
+            self.data[(self.start + self.len) % N] = byte;
+            self.len += 1;
+        }
+        self.dropped = self.dropped.saturating_add((data.len() - count) as u32);
+        count
+    }
+
+    /// The oldest queued bytes, as many as are stored in one piece.
+    pub fn front(&self) -> &[u8] {
+        let end = (self.start + self.len).min(N);
+        &self.data[self.start..end]
+    }
+
+    /// Removes `count` bytes from the front once they are sent.
+    pub fn consume(&mut self, count: usize) {
+        let count = count.min(self.len);
+        self.start = (self.start + count) % N;
+        self.len -= count;
+        if self.len == 0 {
+            self.start = 0;
+        }
+    }
+}
+
+impl<const N: usize> Output for RingBuffer<N> {
+    fn write(&mut self, data: &[u8]) {
+        self.push(data);
+    }
+}
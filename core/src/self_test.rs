@@ -0,0 +1,105 @@
+//! Production test of the display, started with the `SELFTEST` command or by shaking the watch.
+//!
+//! The test lights every segment of both tubes on its own, the points included, then walks through
+//! the glyphs of the font and at last turns the high voltage off and back on with all segments lit.
+//! Every step is reported over the serial port by its number as it starts, so a test jig can check what it
+//! sees against it:
+//!
+//! ```text
+//! SELFTEST 1
+//! SELFTEST 2
+//! ...
+//! SELFTEST 112
+//! SELFTEST done
+//! ```
+//!
+//! Steps 1 to 8 light segments a up to g and the point of the first tube, 9 to 16 those of the second.
+//! Steps 17 to 110 show the characters `!` up to `~` on both tubes, 111 has the high voltage off and 112
+//! back on, see `Step::at`. The lines come in between the replies to commands, whenever a step starts.
+
+use crate::command::{write_number, Output};
+use crate::glyph::Glyph;
+use crate::power::MOTION_CHECKS_PER_SECOND;
+use crate::wear::SEGMENTS;
+
+/// Steps per second
+pub const STEPS_PER_SECOND: u32 = 4;
+
+/// The printable characters after the space, which is blank
+const GLYPHS: u32 = (b'~' - b' ') as u32;
+
+pub const STEPS: u32 = 2 * SEGMENTS as u32 + GLYPHS + 2;
+
+/// How long the watch has to keep moving to count as shaken
+pub const SHAKE_MS: u32 = 2000;
+
+/// Checks in a row with movement that add up to `SHAKE_MS`
+const SHAKE_CHECKS: u8 = (SHAKE_MS * MOTION_CHECKS_PER_SECOND / 1000) as u8;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Step {
+    /// One segment on its own, numbered like `Wear::seconds`
+    Segment(usize),
+    /// The same character on both tubes
+    Glyph(u8),
+    /// The high voltage off, then back on with all segments lit
+    Hv(bool)
+}
+
+impl Step {
+    /// Step `index`, counting from 0, `None` once the test is over.
+    pub fn at(index: u32) -> Option<Step> {
+        let segments = 2 * SEGMENTS as u32;
+        match index {
+            _ if index < segments => Some(Step::Segment(index as usize)),
+            _ if index < segments + GLYPHS => Some(Step::Glyph(b'!' + (index - segments) as u8)),
+            _ if index < STEPS => Some(Step::Hv(index == STEPS - 1)),
+            _ => None
+        }
+    }
+
+    /// What the tubes show, the high voltage is off for `Hv(false)`.
+    pub fn glyphs(self) -> [Glyph; 2] {
+        match self {
+            Step::Segment(segment) => {
+                let glyph = Glyph::from_segments(1 << (segment % SEGMENTS));
+                if segment < SEGMENTS { [glyph, Glyph::BLANK] } else { [Glyph::BLANK, glyph] }
+            }
+            Step::Glyph(c) => [Glyph::from_ascii(c); 2],
+            Step::Hv(_) => [Glyph::from_segments(0x7F); 2]
+        }
+    }
+}
+
+/// Reports step `index` as it starts, numbered from 1, or the end of the test for `None`.
+pub fn report<O: Output>(out: &mut O, index: Option<u32>) {
+    out.write(b"SELFTEST ");
+    match index.filter(|&index| index < STEPS) {
+        Some(index) => write_number(out, index as i32 + 1),
+        None => out.write(b"done")
+    }
+    out.write(b"\n");
+}
+
+/// Tells shaking the watch apart from the movement of wearing it.
+#[derive(Default)]
+pub struct Shake {
+    checks: u8
+}
+
+impl Shake {
+    pub const fn new() -> Self {
+        Shake { checks: 0 }
+    }
+
+    /// Takes whether the watch moved since the last check, `MOTION_CHECKS_PER_SECOND` times a second. True
+    /// once it kept moving for `SHAKE_MS`.
+    pub fn update(&mut self, moved: bool) -> bool {
+        self.checks = if moved { self.checks + 1 } else { 0 };
+        if self.checks < SHAKE_CHECKS {
+            return false;
+        }
+        self.checks = 0;
+        true
+    }
+}
@@ -0,0 +1,260 @@
+//! Display sequences, what the tubes show frame by frame after a wake up.
+//!
+//! A frame shows one thing for a while, with the decimal point on one of the tubes and at a
+//! brightness. The sequence shown on a wake up is the `SEQUENCE` setting, written as frames
+//! separated by commas. A frame is a content letter, optionally the tube with the dot, the duration
+//! in tenths of a second and optionally the brightness, so `H1:10,M2:10,D:10,B:10:4` shows the
+//! hours, the minutes, the day and a dimmed bar graph for a second each. A duration of 0 takes a
+//! quarter of `DISPLAY_DURATION`, as the built in sequences do. `DEFAULT` goes back to the time and
+//! the charge.
+//!
+//! A transition name and a slash in front, as in `ROLL/H1:10,M2:10`, changes how one frame gives way to
+//! the next, see `Transition`. Without one the frames cut straight over.
+//!
+//! | Letter | Shows                  |
+//! |--------|------------------------|
+//! | `H`    | hours                  |
+//! | `M`    | minutes                |
+//! | `S`    | seconds                |
+//! | `D`    | day of the month       |
+//! | `N`    | month                  |
+//! | `B`    | battery bar graph      |
+//! | `C`    | charging animation     |
+//! | `E`    | empty battery          |
+//! | `X`    | nothing, the HV is off |
+//! | `T`    | time scrolling by      |
+//! | `Y`    | date scrolling by      |
+//! | `U`    | last message over USB  |
+//! | `A`    | all segments exercised |
+//! | `Z`    | self test              |
+//!
+//! `T`, `Y` and `U` scroll at `SCROLL_STEP_MS`, with a duration of 0 the frame lasts one scroll. `A`
+//! lasts `EXERCISE_S` with a duration of 0, see `wear`, and `Z` all of its steps, see `self_test`.
+
+use crate::command::split;
+use crate::nixie_segment::{DotStatus, BRIGHTNESS_LEVELS};
+
+pub const MAX_FRAMES: usize = 8;
+
+/// Bytes a frame takes in the settings
+pub const FRAME_SIZE: usize = 3;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Content {
+    Hours,
+    Minutes,
+    Seconds,
+    Day,
+    Month,
+    /// The bar graph, animated while charging
+    Charge,
+    Charging,
+    Empty,
+    Blank,
+    /// Texts scrolling by, see `text`
+    TimeText,
+    DateText,
+    Message,
+    /// Every digit with the points, see `wear`
+    Exercise,
+    SelfTest
+}
+
+/// How a sequence goes from one frame to the next, for whatever the frames show.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Transition {
+    Cut,
+    /// The tubes flicker between both frames, the old one fading out and the new one in
+    Fade,
+    /// The segments of the new frame take over one by one, from a to the point
+    Wipe,
+    /// Digits roll by like a slot machine, the first tube lands halfway and the second at the end
+    Roll
+}
+
+impl Transition {
+    const ALL: [Transition; 4] = [Transition::Cut, Transition::Fade, Transition::Wipe, Transition::Roll];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transition::Cut => "CUT",
+            Transition::Fade => "FADE",
+            Transition::Wipe => "WIPE",
+            Transition::Roll => "ROLL"
+        }
+    }
+
+    fn from_name(name: &[u8]) -> Option<Transition> {
+        Self::ALL.iter().copied().find(|transition| transition.name().as_bytes().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Letter of every content, in the order of `Content`
+const LETTERS: &[u8; 14] = b"HMSDNBCEXTYUAZ";
+
+impl Content {
+    const ALL: [Content; 14] = [Content::Hours, Content::Minutes, Content::Seconds, Content::Day, Content::Month,
+                                Content::Charge, Content::Charging, Content::Empty, Content::Blank, Content::TimeText,
+                                Content::DateText, Content::Message, Content::Exercise, Content::SelfTest];
+
+    fn from_letter(letter: u8) -> Option<Content> {
+        let index = LETTERS.iter().position(|&l| l == letter.to_ascii_uppercase())?;
+        Some(Self::ALL[index])
+    }
+
+    pub fn letter(self) -> u8 {
+        LETTERS[self as usize]
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Frame {
+    pub content: Content,
+    pub dot: DotStatus,
+    /// In tenths of a second, 0 for a quarter of the display duration
+    pub duration: u8,
+    /// 1 up to `BRIGHTNESS_LEVELS`
+    pub brightness: u8
+}
+
+impl Frame {
+    pub const fn new(content: Content, dot: DotStatus, duration: u8) -> Self {
+        Frame { content, dot, duration, brightness: BRIGHTNESS_LEVELS }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Sequence {
+    frames: [Frame; MAX_FRAMES],
+    len: u8,
+    pub transition: Transition
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sequence {
+    /// No frames, which for the wake up sequence means the default one.
+    pub const fn new() -> Self {
+        Sequence { frames: [Frame::new(Content::Blank, DotStatus::Off, 0); MAX_FRAMES], len: 0, transition: Transition::Cut }
+    }
+
+    /// Four frames paced by the display duration, as the built in sequences are.
+    pub const fn paced(frames: [(Content, DotStatus); 4]) -> Self {
+        let mut sequence = Sequence::new();
+        let mut i = 0;
+        while i < 4 {
+            sequence.frames[i] = Frame::new(frames[i].0, frames[i].1, 0);
+            i += 1;
+        }
+        sequence.len = 4;
+        sequence
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames[..self.len as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a frame, false if the sequence is full.
+    pub fn push(&mut self, frame: Frame) -> bool {
+        match self.frames.get_mut(self.len as usize) {
+            Some(slot) => {
+                *slot = frame;
+                self.len += 1;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Parses the text form described above, `None` if anything is off.
+    pub fn parse(text: &[u8]) -> Option<Sequence> {
+        let mut sequence = Sequence::new();
+        if text.eq_ignore_ascii_case(b"DEFAULT") {
+            return Some(sequence);
+        }
+        let text = match text.iter().position(|&b| b == b'/') {
+            Some(slash) => {
+                sequence.transition = Transition::from_name(&text[..slash])?;
+                &text[slash + 1..]
+            }
+            None => text
+        };
+        for frame in split(text, b',') {
+            let mut fields = split(frame, b':');
+            let (&letter, dot) = fields.next()?.split_first()?;
+            let dot = match dot {
+                b"" => DotStatus::Off,
+                b"1" => DotStatus::Digit1,
+                b"2" => DotStatus::Digit2,
+                _ => return None
+            };
+            let duration = parse_small(fields.next()?)?;
+            let brightness = match fields.next() {
+                Some(brightness) => parse_small(brightness)?,
+                None => BRIGHTNESS_LEVELS
+            };
+            if fields.next().is_some() || !(1..=BRIGHTNESS_LEVELS).contains(&brightness) {
+                return None;
+            }
+            let frame = Frame { content: Content::from_letter(letter)?, dot, duration, brightness };
+            if !sequence.push(frame) {
+                return None;
+            }
+        }
+        Some(sequence)
+    }
+
+    /// Packs the frames for the settings, returning the bytes used in `data`. The transition goes in the top
+    /// bits of the first byte.
+    pub fn to_bytes(&self, data: &mut [u8]) -> usize {
+        for (frame, bytes) in self.frames().iter().zip(data.chunks_mut(FRAME_SIZE)) {
+            bytes.copy_from_slice(&[frame.content as u8 | (frame.dot as u8) << 4, frame.duration, frame.brightness]);
+        }
+        if let Some(first) = data.first_mut().filter(|_| !self.is_empty()) {
+            *first |= (self.transition as u8) << 6;
+        }
+        self.len as usize * FRAME_SIZE
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Sequence> {
+        let mut sequence = Sequence::new();
+        let frames = data.chunks_exact(FRAME_SIZE);
+        if !frames.remainder().is_empty() {
+            return None;
+        }
+        if let Some(first) = data.first() {
+            sequence.transition = Transition::ALL[*first as usize >> 6];
+        }
+        for bytes in frames {
+            let content = Content::from_letter(*LETTERS.get(bytes[0] as usize & 0xF)?)?;
+            let dot = [DotStatus::Off, DotStatus::Digit1, DotStatus::Digit2].get(bytes[0] as usize >> 4 & 3)?;
+            if !(1..=BRIGHTNESS_LEVELS).contains(&bytes[2]) {
+                return None;
+            }
+            if !sequence.push(Frame { content, dot: *dot, duration: bytes[1], brightness: bytes[2] }) {
+                return None;
+            }
+        }
+        Some(sequence)
+    }
+}
+
+/// A number below 256
+fn parse_small(digits: &[u8]) -> Option<u8> {
+    if digits.is_empty() || digits.len() > 3 || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = digits.iter().fold(0u16, |value, &d| value * 10 + (d - b'0') as u16);
+    if value > u8::MAX as u16 {
+        return None;
+    }
+    Some(value as u8)
+}
@@ -0,0 +1,558 @@
+//! Persistent settings in a single flash page.
+//!
+//! The page starts with a small header holding a magic number and the schema version, followed
+//! by a log of records. Changing a setting appends a new record, so the page is only erased once
+//! it is full, at which point the latest value of every key is rewritten to the fresh page.
+//! Losing power during that rewrite loses the settings and the defaults are used.
+//!
+//! A record is a half word header with the key and the value length, the value padded to a
+//! whole half word, and a CRC16 over key, length and value. Records with a bad CRC, for example
+//! from losing power halfway through a write, are skipped and the previous value is used.
+
+use crate::calendar::Time;
+use crate::calibration::MAX_TRIM_PPM;
+use crate::energy::Currents;
+use crate::nixie_segment::{AmPm, BRIGHTNESS_LEVELS};
+use crate::sequence::{Sequence, FRAME_SIZE, MAX_FRAMES};
+
+/// Bumped when the meaning of an existing key changes. Keys are only ever added,
+/// so a page with an older schema is read as is, a newer one is formatted.
+pub const SCHEMA_VERSION: u16 = 2;
+
+/// Oldest schema still read. Schema 2 numbered the keys without the gap schema 1 had at 4 and 5,
+/// so a page from schema 1 is formatted rather than read under the wrong keys.
+const OLDEST_SCHEMA: u16 = 2;
+
+const MAGIC: u16 = 0x4E57;
+const HEADER_SIZE: usize = 4;
+pub const MAX_VALUE_LEN: usize = 64;
+const ERASED: u16 = 0xFFFF;
+
+/// Room for the latest value of every key while the page is rewritten.
+const COMPACT_BUFFER_SIZE: usize = 256;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum FlashError {
+    Erase,
+    Program
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum SettingsError {
+    Flash(FlashError),
+    /// Value longer than `MAX_VALUE_LEN` or a reserved key
+    Invalid,
+    /// Even an empty page can't hold all values
+    Full
+}
+
+impl From<FlashError> for SettingsError {
+    fn from(error: FlashError) -> Self {
+        SettingsError::Flash(error)
+    }
+}
+
+/// One flash page with the erase and program semantics of the STM32: erasing sets all bits,
+/// programming is done per half word and only on erased half words.
+pub trait Flash {
+    /// Size of the page in bytes.
+    fn size(&self) -> usize;
+
+    fn read(&self, offset: usize, data: &mut [u8]);
+
+    fn erase(&mut self) -> Result<(), FlashError>;
+
+    /// `offset` and the length of `data` are a multiple of two.
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError>;
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(crc: u16, data: &[u8]) -> u16 {
+    let mut crc = crc;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn record_size(len: usize) -> usize {
+    2 + ((len + 1) & !1) + 2
+}
+
+struct Record {
+    key: u8,
+    len: usize,
+    offset: usize,
+    valid: bool
+}
+
+pub struct SettingsStore<F: Flash> {
+    flash: F,
+    /// Offset of the first free half word
+    end: usize
+}
+
+impl<F: Flash> SettingsStore<F> {
+    /// Opens the store, formatting the page if it holds no settings or ones from a schema it can't read.
+    pub fn new(flash: F) -> Self {
+        let mut store = SettingsStore { flash, end: HEADER_SIZE };
+        if store.read_u16(0) == MAGIC && (OLDEST_SCHEMA..=SCHEMA_VERSION).contains(&store.read_u16(2)) {
+            store.end = store.find_end();
+        } else {
+            store.format().ok();
+        }
+        store
+    }
+
+    /// Gives the flash back, for tests that reopen the store.
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// Erases all settings.
+    pub fn format(&mut self) -> Result<(), SettingsError> {
+        self.end = self.flash.size();
+        self.flash.erase()?;
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+        header[2..4].copy_from_slice(&SCHEMA_VERSION.to_le_bytes());
+        self.flash.program(0, &header)?;
+        self.end = HEADER_SIZE;
+        Ok(())
+    }
+
+    pub fn free_space(&self) -> usize {
+        self.flash.size() - self.end
+    }
+
+    /// Copies the latest value of `key` into `value`, returning its length.
+    pub fn read(&self, key: u8, value: &mut [u8]) -> Option<usize> {
+        let record = self.latest(key)?;
+        let len = record.len.min(value.len());
+        self.flash.read(record.offset + 2, &mut value[..len]);
+        Some(record.len)
+    }
+
+    /// Stores `value` for `key`, unless it already holds that value.
+    pub fn write(&mut self, key: u8, value: &[u8]) -> Result<(), SettingsError> {
+        if key == 0 || key == 0xFF || value.len() > MAX_VALUE_LEN {
+            return Err(SettingsError::Invalid);
+        }
+        let mut current = [0u8; MAX_VALUE_LEN];
+        if self.read(key, &mut current) == Some(value.len()) && &current[..value.len()] == value {
+            return Ok(());
+        }
+        if self.free_space() < record_size(value.len()) {
+            self.compact(key)?;
+            if self.free_space() < record_size(value.len()) {
+                return Err(SettingsError::Full);
+            }
+        }
+        self.append(key, value)
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        let mut data = [0u8; 2];
+        self.flash.read(offset, &mut data);
+        u16::from_le_bytes(data)
+    }
+
+    fn record_at(&self, offset: usize) -> Option<Record> {
+        if offset + 2 > self.flash.size() {
+            return None;
+        }
+        let header = self.read_u16(offset);
+        if header == ERASED {
+            return None;
+        }
+        let key = header as u8;
+        let len = (header >> 8) as usize;
+        if len > MAX_VALUE_LEN || offset + record_size(len) > self.flash.size() {
+            return None;
+        }
+
+        let mut value = [0u8; MAX_VALUE_LEN];
+        self.flash.read(offset + 2, &mut value[..len]);
+        let crc = crc16(crc16(0xFFFF, &[key, len as u8]), &value[..len]);
+        let valid = key != 0 && self.read_u16(offset + record_size(len) - 2) == crc;
+        Some(Record { key, len, offset, valid })
+    }
+
+    fn find_end(&self) -> usize {
+        let mut offset = HEADER_SIZE;
+        while let Some(record) = self.record_at(offset) {
+            offset += record_size(record.len);
+        }
+        if offset + 2 <= self.flash.size() && self.read_u16(offset) != ERASED {
+            // Garbage that isn't a record, don't append after it
+            return self.flash.size();
+        }
+        offset
+    }
+
+    fn latest(&self, key: u8) -> Option<Record> {
+        let mut latest = None;
+        let mut offset = HEADER_SIZE;
+        while let Some(record) = self.record_at(offset) {
+            if offset >= self.end {
+                break;
+            }
+            offset += record_size(record.len);
+            if record.valid && record.key == key {
+                latest = Some(record);
+            }
+        }
+        latest
+    }
+
+    fn append(&mut self, key: u8, value: &[u8]) -> Result<(), SettingsError> {
+        let offset = self.end;
+        // Taken up even if programming fails halfway, the record is skipped by its CRC then
+        self.end += record_size(value.len());
+
+        self.flash.program(offset, &[key, value.len() as u8])?;
+        let even = value.len() & !1;
+        self.flash.program(offset + 2, &value[..even])?;
+        if even != value.len() {
+            self.flash.program(offset + 2 + even, &[value[even], 0xFF])?;
+        }
+        let crc = crc16(crc16(0xFFFF, &[key, value.len() as u8]), value);
+        self.flash.program(self.end - 2, &crc.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Rewrites the page with only the latest value of each key, leaving out `skip` which is about to be written.
+    fn compact(&mut self, skip: u8) -> Result<(), SettingsError> {
+        let mut buffer = [0u8; COMPACT_BUFFER_SIZE];
+        let mut used = 0;
+        let mut offset = HEADER_SIZE;
+        while let Some(record) = self.record_at(offset) {
+            offset += record_size(record.len);
+            if !record.valid || record.key == skip {
+                continue;
+            }
+            if self.latest(record.key).map(|latest| latest.offset) != Some(record.offset) {
+                continue;
+            }
+            let size = record_size(record.len);
+            if used + size > buffer.len() {
+                return Err(SettingsError::Full);
+            }
+            self.flash.read(record.offset, &mut buffer[used..used + size]);
+            used += size;
+        }
+
+        self.format()?;
+        self.flash.program(HEADER_SIZE, &buffer[..used])?;
+        self.end = HEADER_SIZE + used;
+        Ok(())
+    }
+}
+
+/// Keys of the settings, from 0x80 up the store holds other records like the `wear`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Key {
+    StartTime = 1,
+    MultiplexRate = 2,
+    DisplayDuration = 3,
+    BatteryEmpty = 4,
+    BatteryFull = 5,
+    BatteryLow = 6,
+    BatteryCritical = 7,
+    TrimPpm = 8,
+    BatteryCapacity = 9,
+    CurrentSleep = 10,
+    CurrentRun = 11,
+    CurrentDisplay = 12,
+    CurrentHv = 13,
+    Sequence = 14,
+    ScrollStep = 15,
+    Brightness = 16,
+    BrightnessLow = 17,
+    Blanking = 18,
+    ExerciseDuration = 19,
+    ExerciseOnCharge = 20,
+    Clock12h = 21
+}
+
+impl Key {
+    pub const ALL: [Key; 21] = [Key::StartTime, Key::MultiplexRate, Key::DisplayDuration, Key::BatteryEmpty,
+                                Key::BatteryFull, Key::BatteryLow, Key::BatteryCritical, Key::TrimPpm,
+                                Key::BatteryCapacity, Key::CurrentSleep, Key::CurrentRun, Key::CurrentDisplay,
+                                Key::CurrentHv, Key::Sequence, Key::ScrollStep, Key::Brightness, Key::BrightnessLow,
+                                Key::Blanking, Key::ExerciseDuration, Key::ExerciseOnCharge, Key::Clock12h];
+
+    /// Name used over USB.
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::StartTime => "START_TIME",
+            Key::MultiplexRate => "MULTIPLEX_RATE",
+            Key::DisplayDuration => "DISPLAY_DURATION",
+            Key::BatteryEmpty => "BATTERY_EMPTY_MV",
+            Key::BatteryFull => "BATTERY_FULL_MV",
+            Key::BatteryLow => "BATTERY_LOW",
+            Key::BatteryCritical => "BATTERY_CRITICAL",
+            Key::TrimPpm => "TRIM_PPM",
+            Key::BatteryCapacity => "BATTERY_CAPACITY_MAH",
+            Key::CurrentSleep => "CURRENT_SLEEP_UA",
+            Key::CurrentRun => "CURRENT_RUN_UA",
+            Key::CurrentDisplay => "CURRENT_DISPLAY_UA",
+            Key::CurrentHv => "CURRENT_HV_UA",
+            Key::Sequence => "SEQUENCE",
+            Key::ScrollStep => "SCROLL_STEP_MS",
+            Key::Brightness => "BRIGHTNESS",
+            Key::BrightnessLow => "BRIGHTNESS_LOW",
+            Key::Blanking => "BLANKING_US",
+            Key::ExerciseDuration => "EXERCISE_S",
+            Key::ExerciseOnCharge => "EXERCISE_ON_CHARGE",
+            Key::Clock12h => "CLOCK_12H"
+        }
+    }
+
+    /// Key by its name, ignoring case.
+    pub fn from_name(name: &[u8]) -> Option<Key> {
+        Self::ALL.iter().copied().find(|key| key.name().as_bytes().eq_ignore_ascii_case(name))
+    }
+
+    /// Lowest and highest number the setting takes, none for the start time and the sequence.
+    fn range(self) -> (i32, i32) {
+        match self {
+            Key::StartTime | Key::Sequence => (1, 0),
+            Key::MultiplexRate => (50, 1000),
+            Key::DisplayDuration => (500, 30000),
+            Key::BatteryEmpty | Key::BatteryFull => (3000, 4500),
+            Key::BatteryLow | Key::BatteryCritical => (0, 100),
+            Key::TrimPpm => (-MAX_TRIM_PPM, MAX_TRIM_PPM),
+            Key::BatteryCapacity => (10, 5000),
+            Key::CurrentSleep | Key::CurrentRun | Key::CurrentDisplay | Key::CurrentHv => (0, 65535),
+            Key::ScrollStep => (50, 2000),
+            Key::Brightness | Key::BrightnessLow => (1, BRIGHTNESS_LEVELS as i32),
+            Key::Blanking => (0, 1000),
+            Key::ExerciseDuration => (1, 600),
+            Key::ExerciseOnCharge => (0, 1),
+            Key::Clock12h => (0, 2)
+        }
+    }
+
+    /// Bytes the value takes in the store, the most for a sequence which takes three per frame.
+    fn size(self) -> usize {
+        match self {
+            Key::StartTime => 3,
+            Key::Sequence => MAX_FRAMES * FRAME_SIZE,
+            Key::BatteryLow | Key::BatteryCritical | Key::Brightness | Key::BrightnessLow | Key::ExerciseOnCharge | Key::Clock12h => 1,
+            Key::TrimPpm => 4,
+            _ => 2
+        }
+    }
+}
+
+/// Value of a setting, the start time and the sequence are the only ones that aren't a number.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Value {
+    Time(Time),
+    Number(i32),
+    Sequence(Sequence)
+}
+
+/// Everything the watch keeps across a power loss.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Settings {
+    /// Time the RTC starts at when it lost power
+    pub start_time: Time,
+    /// Rate of the multiplex timer in Hz, used from the next reset on
+    pub multiplex_rate: u16,
+    /// How long the display stays on after a wake up in ms
+    pub display_duration: u16,
+    /// Battery voltage in mV at 0% and 100%, the LiPo curve is stretched between them
+    pub battery_empty_mv: u16,
+    pub battery_full_mv: u16,
+    /// Charge in percent below which the battery is low
+    pub battery_low: u8,
+    /// Charge in percent below which the battery is shown as empty
+    pub battery_critical: u8,
+    /// Drift correction of the RTC
+    pub trim_ppm: i32,
+    pub battery_capacity_mah: u16,
+    /// Current drawn in µA in STOP mode, awake, and on top of that with the display lit and the high voltage on.
+    /// Estimates for the energy accounting, measure the board for better ones.
+    pub current_sleep_ua: u16,
+    pub current_run_ua: u16,
+    pub current_display_ua: u16,
+    pub current_hv_ua: u16,
+    /// Shown on a wake up, empty for the time and charge, see `sequence`
+    pub sequence: Sequence,
+    /// How long scrolling text stays at each step in ms
+    pub scroll_step_ms: u16,
+    /// Brightness of the display from 1 up to `BRIGHTNESS_LEVELS`, and the most it gets with a low battery
+    pub brightness: u8,
+    pub brightness_low: u8,
+    /// Dead time in µs between one tube going dark and the next lighting up, against ghosting
+    pub blanking_us: u16,
+    /// How long the exercise of the segments takes in s, and whether plugging in the charger starts it
+    pub exercise_s: u16,
+    pub exercise_on_charge: bool,
+    /// 0 for the 24-hour clock, 1 for the 12-hour clock with the point of the hours lit in the afternoon and 2 for it
+    /// with AM or PM shown before the hours
+    pub clock_12h: u8
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            start_time: Time::new(13, 37, 0).unwrap(),
+            multiplex_rate: 200,
+            display_duration: 4000,
+            battery_empty_mv: 3300,
+            battery_full_mv: 4200,
+            battery_low: 10,
+            battery_critical: 3,
+            trim_ppm: 0,
+            battery_capacity_mah: 150,
+            current_sleep_ua: 60,
+            current_run_ua: 4000,
+            current_display_ua: 6000,
+            current_hv_ua: 20000,
+            sequence: Sequence::new(),
+            scroll_step_ms: 300,
+            brightness: BRIGHTNESS_LEVELS,
+            brightness_low: BRIGHTNESS_LEVELS / 2,
+            blanking_us: 100,
+            exercise_s: 60,
+            exercise_on_charge: true,
+            clock_12h: 0
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings, using the default for anything missing or out of range.
+    pub fn load<F: Flash>(store: &SettingsStore<F>) -> Self {
+        let mut settings = Settings::default();
+        for key in Key::ALL {
+            let mut data = [0u8; MAX_FRAMES * FRAME_SIZE];
+            let len = store.read(key as u8, &mut data);
+            if key == Key::Sequence {
+                if let Some(sequence) = len.and_then(|len| data.get(..len)).and_then(Sequence::from_bytes) {
+                    settings.sequence = sequence;
+                }
+                continue;
+            }
+            if len != Some(key.size()) {
+                continue;
+            }
+            let value = if key == Key::StartTime {
+                match Time::new(data[0], data[1], data[2]) {
+                    Some(time) => Value::Time(time),
+                    None => continue
+                }
+            } else {
+                // Little endian in as many bytes as the setting takes, the bytes past it are still zero
+                Value::Number(i32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+            };
+            settings.set(key, value);
+        }
+        settings
+    }
+
+    /// Writes every setting, the store skips the ones that didn't change.
+    pub fn save<F: Flash>(&self, store: &mut SettingsStore<F>) -> Result<(), SettingsError> {
+        for key in Key::ALL {
+            let mut data = [0u8; MAX_FRAMES * FRAME_SIZE];
+            let len = match self.get(key) {
+                Value::Time(time) => {
+                    data[..3].copy_from_slice(&[time.hours(), time.minutes(), time.seconds()]);
+                    key.size()
+                }
+                Value::Number(number) => {
+                    data[..4].copy_from_slice(&number.to_le_bytes());
+                    key.size()
+                }
+                Value::Sequence(sequence) => sequence.to_bytes(&mut data)
+            };
+            store.write(key as u8, &data[..len])?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: Key) -> Value {
+        match key {
+            Key::StartTime => Value::Time(self.start_time),
+            Key::MultiplexRate => Value::Number(self.multiplex_rate as i32),
+            Key::DisplayDuration => Value::Number(self.display_duration as i32),
+            Key::BatteryEmpty => Value::Number(self.battery_empty_mv as i32),
+            Key::BatteryFull => Value::Number(self.battery_full_mv as i32),
+            Key::BatteryLow => Value::Number(self.battery_low as i32),
+            Key::BatteryCritical => Value::Number(self.battery_critical as i32),
+            Key::TrimPpm => Value::Number(self.trim_ppm),
+            Key::BatteryCapacity => Value::Number(self.battery_capacity_mah as i32),
+            Key::CurrentSleep => Value::Number(self.current_sleep_ua as i32),
+            Key::CurrentRun => Value::Number(self.current_run_ua as i32),
+            Key::CurrentDisplay => Value::Number(self.current_display_ua as i32),
+            Key::CurrentHv => Value::Number(self.current_hv_ua as i32),
+            Key::Sequence => Value::Sequence(self.sequence),
+            Key::ScrollStep => Value::Number(self.scroll_step_ms as i32),
+            Key::Brightness => Value::Number(self.brightness as i32),
+            Key::BrightnessLow => Value::Number(self.brightness_low as i32),
+            Key::Blanking => Value::Number(self.blanking_us as i32),
+            Key::ExerciseDuration => Value::Number(self.exercise_s as i32),
+            Key::ExerciseOnCharge => Value::Number(self.exercise_on_charge as i32),
+            Key::Clock12h => Value::Number(self.clock_12h as i32)
+        }
+    }
+
+    /// Changes a setting, returning false if the value has the wrong type or is out of range.
+    pub fn set(&mut self, key: Key, value: Value) -> bool {
+        if let Value::Number(n) = value {
+            let (min, max) = key.range();
+            if !(min..=max).contains(&n) {
+                return false;
+            }
+        }
+        match (key, value) {
+            (Key::StartTime, Value::Time(time)) => self.start_time = time,
+            (Key::MultiplexRate, Value::Number(n)) => self.multiplex_rate = n as u16,
+            (Key::DisplayDuration, Value::Number(n)) => self.display_duration = n as u16,
+            (Key::BatteryEmpty, Value::Number(n)) => self.battery_empty_mv = n as u16,
+            (Key::BatteryFull, Value::Number(n)) => self.battery_full_mv = n as u16,
+            (Key::BatteryLow, Value::Number(n)) => self.battery_low = n as u8,
+            (Key::BatteryCritical, Value::Number(n)) => self.battery_critical = n as u8,
+            (Key::TrimPpm, Value::Number(n)) => self.trim_ppm = n,
+            (Key::BatteryCapacity, Value::Number(n)) => self.battery_capacity_mah = n as u16,
+            (Key::CurrentSleep, Value::Number(n)) => self.current_sleep_ua = n as u16,
+            (Key::CurrentRun, Value::Number(n)) => self.current_run_ua = n as u16,
+            (Key::CurrentDisplay, Value::Number(n)) => self.current_display_ua = n as u16,
+            (Key::CurrentHv, Value::Number(n)) => self.current_hv_ua = n as u16,
+            (Key::Sequence, Value::Sequence(sequence)) => self.sequence = sequence,
+            (Key::ScrollStep, Value::Number(n)) => self.scroll_step_ms = n as u16,
+            (Key::Brightness, Value::Number(n)) => self.brightness = n as u8,
+            (Key::BrightnessLow, Value::Number(n)) => self.brightness_low = n as u8,
+            (Key::Blanking, Value::Number(n)) => self.blanking_us = n as u16,
+            (Key::ExerciseDuration, Value::Number(n)) => self.exercise_s = n as u16,
+            (Key::ExerciseOnCharge, Value::Number(n)) => self.exercise_on_charge = n == 1,
+            (Key::Clock12h, Value::Number(n)) => self.clock_12h = n as u8,
+            _ => return false
+        }
+        true
+    }
+
+    /// How the clock tells the morning from the afternoon, `None` for the 24-hour clock.
+    pub fn am_pm(&self) -> Option<AmPm> {
+        match self.clock_12h {
+            1 => Some(AmPm::Dot),
+            2 => Some(AmPm::Letter),
+            _ => None
+        }
+    }
+
+    pub fn currents(&self) -> Currents {
+        Currents {
+            sleep: self.current_sleep_ua as u32,
+            run: self.current_run_ua as u32,
+            display: self.current_display_ua as u32,
+            hv: self.current_hv_ua as u32
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Text scrolling across the two tubes.
+//!
+//! A text is a row of glyphs, see `glyph` for the font. A `.` after a character lights the point
+//! of that character instead of taking a tube of its own, so `13.37` takes four glyphs. The scroll
+//! starts with the first glyph on the right tube and moves one glyph to the left every step until
+//! the last glyph sits on the left tube.
+
+use crate::command::padded;
+use crate::glyph::Glyph;
+
+pub const MAX_TEXT_LEN: usize = 32;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Text {
+    glyphs: [Glyph; MAX_TEXT_LEN],
+    len: u8
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Text {
+    pub const fn new() -> Self {
+        Text { glyphs: [Glyph::BLANK; MAX_TEXT_LEN], len: 0 }
+    }
+
+    /// The glyphs of `text`, `None` if they don't fit.
+    pub fn from_bytes(text: &[u8]) -> Option<Text> {
+        let mut result = Text::new();
+        for &c in text {
+            if !result.push(c) {
+                return None;
+            }
+        }
+        Some(result)
+    }
+
+    /// Adds a character, or lights the point of the last one for a `.`. False if the text is full.
+    pub fn push(&mut self, c: u8) -> bool {
+        let len = self.len as usize;
+        if c == b'.' && len > 0 && !self.glyphs[len - 1].has_dot() {
+            self.glyphs[len - 1] = self.glyphs[len - 1].with_dot();
+            return true;
+        }
+        match self.glyphs.get_mut(len) {
+            Some(slot) => {
+                *slot = Glyph::from_ascii(c);
+                self.len += 1;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Adds `number` with leading zeros up to `width` digits.
+    pub fn push_number(&mut self, number: u32, width: usize) -> bool {
+        padded(number, width, &mut [0; 10]).iter().all(|&d| self.push(d))
+    }
+
+    pub fn glyphs(&self) -> &[Glyph] {
+        &self.glyphs[..self.len as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Steps the scroll takes.
+    pub fn steps(&self) -> u32 {
+        self.len as u32 + 1
+    }
+
+    /// Glyphs on the left and the right tube at `step` of the scroll, blank outside the text.
+    pub fn window(&self, step: u32) -> [Glyph; 2] {
+        let at = |index: u32| index.checked_sub(1).and_then(|i| self.glyphs().get(i as usize)).copied().unwrap_or_default();
+        [at(step), at(step + 1)]
+    }
+}
@@ -0,0 +1,79 @@
+use crate::calendar::DateTime;
+
+/// Something that keeps the date and time for `NixieClock`, e.g. the hardware RTC.
+pub trait TimeSource {
+    fn now(&mut self) -> DateTime;
+
+    /// Sets the time, the second `now` starts right away.
+    fn set(&mut self, now: DateTime);
+
+    /// Called by `NixieClock::tick` on every multiplex tick. Only sources that count ticks need this.
+    fn tick(&mut self) {}
+
+    /// Speeds the source up by `ppm` parts per million, or slows it down if negative.
+    fn set_trim(&mut self, ppm: i32);
+}
+
+/// Software clock counting multiplex timer ticks. Only as accurate as the timer clock and
+/// only running while the timer is, which makes it mostly useful for tests.
+pub struct TickCounter {
+    ticks_per_second: u32,
+    current_tick: u32,
+    now: DateTime,
+    trim_ppm: i32,
+    /// Fraction of a tick the trim has built up, in millionths of a tick
+    trim_error: i32,
+    ticks_this_second: u32
+}
+
+impl TickCounter {
+    pub fn new(ticks_per_second: u32) -> Self {
+        TickCounter {
+            ticks_per_second,
+            current_tick: 0,
+            now: DateTime::default(),
+            trim_ppm: 0,
+            trim_error: 0,
+            ticks_this_second: ticks_per_second
+        }
+    }
+
+    /// Works out the length of the next second, dropping or adding a tick whenever the trim adds up to a whole one.
+    fn start_second(&mut self) {
+        self.trim_error += self.trim_ppm * self.ticks_per_second as i32;
+        self.ticks_this_second = if self.trim_error >= 1_000_000 {
+            self.trim_error -= 1_000_000;
+            self.ticks_per_second - 1
+        } else if self.trim_error <= -1_000_000 {
+            self.trim_error += 1_000_000;
+            self.ticks_per_second + 1
+        } else {
+            self.ticks_per_second
+        };
+    }
+}
+
+impl TimeSource for TickCounter {
+    fn now(&mut self) -> DateTime {
+        self.now
+    }
+
+    fn set(&mut self, now: DateTime) {
+        self.now = now;
+        self.current_tick = 0;
+    }
+
+    fn tick(&mut self) {
+        self.current_tick = if self.current_tick >= (self.ticks_this_second - 1) {
+            self.now = self.now.next_second();
+            self.start_second();
+            0
+        } else {
+            self.current_tick + 1
+        };
+    }
+
+    fn set_trim(&mut self, ppm: i32) {
+        self.trim_ppm = ppm;
+    }
+}
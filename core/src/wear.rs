@@ -0,0 +1,42 @@
+//! How long every segment has been lit, kept in the settings store.
+//!
+//! Segments of a gas discharge display that stay dark for long wear differently from the ones lit all
+//! the time. The exercise, `Content::Exercise`, cycles through all digits with the points on so every
+//! segment gets lit, on the `EXERCISE` command and when the charger is plugged in if
+//! `EXERCISE_ON_CHARGE` is set.
+
+use crate::settings::{Flash, SettingsError, SettingsStore};
+
+/// Segments a up to g and the point
+pub const SEGMENTS: usize = 8;
+
+/// Key of the record in the settings store, apart from the keys of the settings.
+pub const KEY: u8 = 0x80;
+
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+pub struct Wear {
+    /// Seconds lit of every segment, the first tube then the second, the point last of each
+    pub seconds: [u32; 2 * SEGMENTS]
+}
+
+impl Wear {
+    /// Reads the record, all zero if there is none.
+    pub fn load<F: Flash>(store: &SettingsStore<F>) -> Self {
+        let mut data = [0u8; 2 * SEGMENTS * 4];
+        let mut wear = Wear::default();
+        if store.read(KEY, &mut data) == Some(data.len()) {
+            for (i, seconds) in wear.seconds.iter_mut().enumerate() {
+                *seconds = u32::from_le_bytes([data[4 * i], data[4 * i + 1], data[4 * i + 2], data[4 * i + 3]]);
+            }
+        }
+        wear
+    }
+
+    pub fn save<F: Flash>(&self, store: &mut SettingsStore<F>) -> Result<(), SettingsError> {
+        let mut data = [0u8; 2 * SEGMENTS * 4];
+        for (i, seconds) in self.seconds.iter().enumerate() {
+            data[4 * i..4 * i + 4].copy_from_slice(&seconds.to_le_bytes());
+        }
+        store.write(KEY, &data)
+    }
+}
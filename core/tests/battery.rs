@@ -0,0 +1,140 @@
+use nixiewatch_core::battery::*;
+
+const VREFINT_CAL: u16 = 1526;
+
+/// Readings of a discharge trace as (minutes, battery, VREFINT).
+fn trace(csv: &str) -> Vec<(u32, u16, u16)> {
+    csv.lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<u32> = line.split(',').map(|field| field.parse().unwrap()).collect();
+            (fields[0], fields[1] as u16, fields[2] as u16)
+        })
+        .collect()
+}
+
+/// Runs a trace through the filter and the curve, checking the charge against the elapsed time of
+/// the constant current discharge.
+fn follow(csv: &str, curve: &Curve, tolerance: i32) {
+    let readings = trace(csv);
+    let duration = readings.last().unwrap().0 as i32;
+    let mut filter = Filter::new();
+    let mut lowest = 100;
+    for (minutes, reading, vrefint) in readings {
+        let percent = curve.percent(filter.update(millivolts(reading, vrefint, VREFINT_CAL))) as i32;
+        let expected = 100 - minutes as i32 * 100 / duration;
+        assert!((percent - expected).abs() <= tolerance, "{}% instead of {}% after {} minutes", percent, expected, minutes);
+        // Noise never makes the charge go back up noticeably
+        assert!(percent <= lowest + 1, "{}% after {}% at {} minutes", percent, lowest, minutes);
+        lowest = lowest.min(percent);
+    }
+}
+
+#[test]
+fn vrefint_cancels_the_supply_voltage() {
+    // 3.9V read with VDDA at 3.3V and sagging to 3.0V
+    assert_eq!(millivolts(2420, 1526, VREFINT_CAL), 3900);
+    assert_eq!(millivolts(2662, 1679, VREFINT_CAL), 3899);
+    // A broken VREFINT reading doesn't claim more than the STM32 takes
+    assert_eq!(millivolts(2000, 0, VREFINT_CAL), 3516);
+}
+
+#[test]
+fn curve_interpolates_between_points() {
+    assert_eq!(LIPO.percent(4300), 100);
+    assert_eq!(LIPO.percent(4200), 100);
+    assert_eq!(LIPO.percent(3800), 50);
+    assert_eq!(LIPO.percent(3820), 55);
+    assert_eq!(LIPO.percent(3300), 0);
+    assert_eq!(LIPO.percent(2900), 0);
+}
+
+#[test]
+fn custom_curves() {
+    assert_eq!(Curve::new([3000, 3100, 3200, 3300, 3400, 3500, 3600, 3700, 3800, 3900, 4000]).unwrap().percent(3450), 45);
+    assert_eq!(Curve::new([3000; POINTS]), None);
+
+    let scaled = LIPO.scaled(3400, 4100);
+    assert_eq!((scaled.empty(), scaled.full()), (3400, 4100));
+    assert_eq!(scaled.percent(LIPO.scaled(3400, 4100).empty() + 1), 0);
+    assert_eq!(LIPO.scaled(3300, 4200), LIPO);
+    // A full voltage below the empty one can't divide by zero
+    assert_eq!(LIPO.scaled(4000, 3500).percent(4000), 0);
+}
+
+#[test]
+fn filter_smooths_readings() {
+    let mut filter = Filter::new();
+    assert_eq!(filter.millivolts(), None);
+    assert_eq!(filter.update(3800), 3800);
+    // Multiplexing noise of 100mV either way
+    for reading in [3700, 3900].repeat(10) {
+        assert!((3790..=3810).contains(&filter.update(reading)));
+    }
+    for _ in 0..100 {
+        filter.update(3700);
+    }
+    assert_eq!(filter.millivolts(), Some(3700));
+}
+
+#[test]
+fn filter_ignores_single_spikes() {
+    let mut filter = Filter::new();
+    filter.update(3800);
+    // The tubes lighting up for one reading
+    assert_eq!(filter.update(3500), 3800);
+    assert_eq!(filter.update(3800), 3800);
+}
+
+#[test]
+fn new_cell_discharge() {
+    follow(include_str!("data/discharge_new_cell.csv"), &LIPO, 8);
+}
+
+#[test]
+fn worn_cell_discharge() {
+    // Its voltage sags more under load, the settings move the curve down to match
+    follow(include_str!("data/discharge_worn_cell.csv"), &LIPO.scaled(3260, 4160), 10);
+}
+
+/// States the monitor goes through for a series of charges, with the low threshold at 10% and critical at 3%.
+fn states(power: Power, percents: &[u8]) -> Vec<State> {
+    let mut monitor = Monitor::new();
+    percents.iter().map(|&percent| monitor.update(percent, power, 10, 3)).collect()
+}
+
+#[test]
+fn thresholds_have_hysteresis() {
+    use State::*;
+    assert_eq!(states(Power::Battery, &[50, 11, 9, 11, 14, 15, 9]), [Ok, Ok, Low, Low, Low, Ok, Low]);
+    assert_eq!(states(Power::Battery, &[9, 2, 3, 7, 8, 9, 2, 20]), [Low, Critical, Critical, Critical, Low, Low, Critical, Ok]);
+    // Straight from a full battery to critical, e.g. on the first reading
+    assert_eq!(states(Power::Battery, &[1]), [Critical]);
+}
+
+#[test]
+fn charger_overrides_the_level() {
+    let mut monitor = Monitor::new();
+    assert_eq!(monitor.update(2, Power::Battery, 10, 3), State::Critical);
+    assert_eq!(monitor.update(4, Power::Charging, 10, 3), State::Charging);
+    assert_eq!(monitor.update(100, Power::Charged, 10, 3), State::Charged);
+    // Charging doesn't count as recovered, the charge has to rise past the hysteresis on the battery
+    assert_eq!(monitor.update(6, Power::Battery, 10, 3), State::Critical);
+    assert_eq!(monitor.state(), State::Critical);
+    assert_eq!(State::Charged.name(), "charged");
+}
+
+#[test]
+fn traces_cross_the_thresholds_once() {
+    let mut filter = Filter::new();
+    let mut monitor = Monitor::new();
+    let mut changes = vec![State::Ok];
+    for (_, reading, vrefint) in trace(include_str!("data/discharge_new_cell.csv")) {
+        let percent = LIPO.percent(filter.update(millivolts(reading, vrefint, VREFINT_CAL)));
+        let state = monitor.update(percent, Power::Battery, 10, 5);
+        if Some(&state) != changes.last() {
+            changes.push(state);
+        }
+    }
+    assert_eq!(changes, [State::Ok, State::Low, State::Critical]);
+}
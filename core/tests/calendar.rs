@@ -0,0 +1,127 @@
+use nixiewatch_core::calendar::{Date, Weekday};
+
+#[test]
+fn leap_years() {
+    assert!(Date::is_leap_year(2024));
+    assert!(Date::is_leap_year(2000));
+    assert!(!Date::is_leap_year(1900));
+    assert!(!Date::is_leap_year(2100));
+    assert!(!Date::is_leap_year(2023));
+}
+
+#[test]
+fn rejects_days_that_do_not_exist() {
+    assert_eq!(Date::new(2023, 2, 29), None);
+    assert!(Date::new(2024, 2, 29).is_some());
+    assert_eq!(Date::new(2024, 4, 31), None);
+    assert_eq!(Date::new(2024, 13, 1), None);
+    assert_eq!(Date::new(2024, 0, 1), None);
+    assert_eq!(Date::new(2024, 1, 0), None);
+}
+
+#[test]
+fn dates_stay_within_what_a_timestamp_holds() {
+    use nixiewatch_core::calendar::{DateTime, Time, MAX_YEAR, MIN_YEAR};
+    assert_eq!(Date::new(MIN_YEAR - 1, 12, 31), None);
+    assert_eq!(Date::new(MAX_YEAR + 1, 1, 1), None);
+    assert_eq!(Date::new(u16::MAX, 12, 31), None);
+
+    let last = DateTime::new(Date::new(MAX_YEAR, 12, 31).unwrap(), Time::new(23, 59, 59).unwrap());
+    assert_eq!(last.timestamp(), 4_291_747_199);
+    assert_eq!(last.date.next_day(), last.date);
+    assert_eq!(DateTime::from_timestamp(u32::MAX).date, last.date);
+}
+
+#[test]
+fn next_day_rolls_months_and_years() {
+    let next = |y, m, d| {
+        let date = Date::new(y, m, d).unwrap().next_day();
+        (date.year(), date.month(), date.day())
+    };
+    assert_eq!(next(2024, 1, 31), (2024, 2, 1));
+    assert_eq!(next(2024, 2, 28), (2024, 2, 29));
+    assert_eq!(next(2024, 2, 29), (2024, 3, 1));
+    assert_eq!(next(2023, 2, 28), (2023, 3, 1));
+    assert_eq!(next(2100, 2, 28), (2100, 3, 1));
+    assert_eq!(next(2024, 12, 31), (2025, 1, 1));
+    assert_eq!(next(2024, 6, 14), (2024, 6, 15));
+}
+
+#[test]
+fn weekdays() {
+    assert_eq!(Date::new(2000, 1, 1).unwrap().weekday(), Weekday::Saturday);
+    assert_eq!(Date::new(2021, 3, 1).unwrap().weekday(), Weekday::Monday);
+    assert_eq!(Date::new(2024, 2, 29).unwrap().weekday(), Weekday::Thursday);
+    assert_eq!(Date::new(2026, 10, 18).unwrap().weekday(), Weekday::Sunday);
+}
+
+#[test]
+fn weekday_follows_next_day_for_a_full_cycle() {
+    let mut date = Date::new(2023, 12, 25).unwrap();
+    let mut weekday = date.weekday().number();
+    for _ in 0..800 {
+        date = date.next_day();
+        weekday = weekday % 7 + 1;
+        assert_eq!(date.weekday().number(), weekday, "{:?}", date);
+    }
+}
+
+#[test]
+fn weekday_numbers() {
+    assert_eq!(Weekday::from_number(1), Some(Weekday::Monday));
+    assert_eq!(Weekday::from_number(7), Some(Weekday::Sunday));
+    assert_eq!(Weekday::from_number(0), None);
+    assert_eq!(Weekday::from_number(8), None);
+    assert_eq!(Weekday::Wednesday.abbreviation(), "Wed");
+}
+
+#[test]
+fn timestamps() {
+    use nixiewatch_core::calendar::{DateTime, Time};
+    let date_time = |y, mo, d, h, mi, s| DateTime::new(Date::new(y, mo, d).unwrap(), Time::new(h, mi, s).unwrap());
+
+    assert_eq!(date_time(1970, 1, 1, 0, 0, 0).timestamp(), 0);
+    assert_eq!(date_time(2000, 1, 1, 0, 0, 0).timestamp(), 946_684_800);
+    assert_eq!(date_time(2024, 2, 29, 13, 37, 42).timestamp(), 1_709_213_862);
+    assert_eq!(date_time(2038, 1, 19, 3, 14, 8).timestamp(), 2_147_483_648);
+
+    assert_eq!(DateTime::from_timestamp(1_709_213_862), date_time(2024, 2, 29, 13, 37, 42));
+    assert_eq!(DateTime::from_timestamp(0), date_time(1970, 1, 1, 0, 0, 0));
+}
+
+#[test]
+fn days_since_epoch_round_trips() {
+    let mut date = Date::new(1970, 1, 1).unwrap();
+    for days in 0..=Date::new(2105, 12, 31).unwrap().days_since_epoch() as u32 {
+        assert_eq!(date.days_since_epoch(), days as i32);
+        assert_eq!(Date::from_days_since_epoch(days), date);
+        date = date.next_day();
+    }
+}
+
+#[test]
+fn nearest_with_time_crosses_midnight() {
+    use nixiewatch_core::calendar::{DateTime, Time};
+    let date_time = |y, mo, d, h, mi, s| DateTime::new(Date::new(y, mo, d).unwrap(), Time::new(h, mi, s).unwrap());
+    let now = date_time(2024, 12, 31, 23, 59, 58);
+    assert_eq!(now.nearest_with_time(Time::new(0, 0, 3).unwrap()), date_time(2025, 1, 1, 0, 0, 3));
+    assert_eq!(now.nearest_with_time(Time::new(23, 58, 0).unwrap()), date_time(2024, 12, 31, 23, 58, 0));
+    let now = date_time(2024, 3, 1, 0, 0, 2);
+    assert_eq!(now.nearest_with_time(Time::new(23, 59, 50).unwrap()), date_time(2024, 2, 29, 23, 59, 50));
+    assert_eq!(now.nearest_with_time(Time::new(11, 0, 0).unwrap()), date_time(2024, 3, 1, 11, 0, 0));
+}
+
+#[test]
+fn twelve_hour_clock() {
+    use nixiewatch_core::calendar::Time;
+    let hours = |hours| {
+        let time = Time::new(hours, 0, 0).unwrap();
+        (time.hours_12(), time.is_pm())
+    };
+    assert_eq!(hours(0), (12, false));
+    assert_eq!(hours(1), (1, false));
+    assert_eq!(hours(11), (11, false));
+    assert_eq!(hours(12), (12, true));
+    assert_eq!(hours(13), (1, true));
+    assert_eq!(hours(23), (11, true));
+}
@@ -0,0 +1,109 @@
+use nixiewatch_core::calibration::{drift_ppm, Calibration, MAX_TRIM_PPM, MIN_CALIBRATION_PERIOD};
+use nixiewatch_core::time_source::{TickCounter, TimeSource};
+
+const DAY: u32 = 24 * 60 * 60;
+const START: u32 = 1_700_000_000;
+
+#[test]
+fn drift_needs_a_long_enough_period() {
+    assert_eq!(drift_ppm(START, START + DAY - 1 + 5, START + DAY - 1), None);
+    assert!(drift_ppm(START, START + MIN_CALIBRATION_PERIOD, START + MIN_CALIBRATION_PERIOD).is_some());
+    // set backwards
+    assert_eq!(drift_ppm(START, START, START - 10), None);
+}
+
+#[test]
+fn drift_in_ppm() {
+    // 10 days, 8.64s fast is 10ppm
+    assert_eq!(drift_ppm(START, START + 10 * DAY + 9, START + 10 * DAY), Some(10));
+    assert_eq!(drift_ppm(START, START + 10 * DAY - 9, START + 10 * DAY), Some(-10));
+    // one second a day is 11.57ppm
+    assert_eq!(drift_ppm(START, START + DAY + 1, START + DAY), Some(12));
+    assert_eq!(drift_ppm(START, START + DAY - 1, START + DAY), Some(-12));
+    assert_eq!(drift_ppm(START, START + 2 * DAY, START + 2 * DAY), Some(0));
+}
+
+#[test]
+fn drift_too_large_to_be_real() {
+    // Seconds off over a few minutes
+    assert_eq!(drift_ppm(START, START + 300 + 5, START + 300), None);
+    // Just past the period, the error being a few hours and then as far off as it gets
+    assert_eq!(drift_ppm(START, START + DAY + 3 * 3600, START + DAY), None);
+    assert_eq!(drift_ppm(START, u32::MAX, START + DAY), None);
+    assert_eq!(drift_ppm(START, 0, START + DAY), None);
+    // 100000ppm is the most still taken as drift
+    assert_eq!(drift_ppm(START, START + 10 * DAY + DAY, START + 10 * DAY), Some(100_000));
+    assert_eq!(drift_ppm(START, START + 10 * DAY + DAY + 1, START + 10 * DAY), None);
+}
+
+#[test]
+fn first_set_only_starts_the_period() {
+    let mut calibration = Calibration::default();
+    assert_eq!(calibration.elapsed(START), 0);
+    assert_eq!(calibration.time_set(START + 5000, START), None);
+    assert_eq!(calibration.last_set(), START);
+    assert_eq!(calibration.elapsed(START + 300), 300);
+}
+
+#[test]
+fn trim_accumulates_over_sets() {
+    let mut calibration = Calibration::new(0, START);
+    // running 17 seconds per 10 days fast, 19.7ppm
+    assert_eq!(calibration.time_set(START + 10 * DAY + 17, START + 10 * DAY), Some(-20));
+    assert_eq!(calibration.trim_ppm(), -20);
+    // after trimming, 1 second slow over 10 days
+    let next = START + 20 * DAY;
+    assert_eq!(calibration.time_set(next - 1, next), Some(-19));
+    // a set too soon after doesn't change the trim, but restarts the period
+    assert_eq!(calibration.time_set(next + 100, next + 60), None);
+    assert_eq!(calibration.last_set(), next + 60);
+    assert_eq!(calibration.trim_ppm(), -19);
+}
+
+#[test]
+fn coarse_time_change_restarts_calibration() {
+    let mut calibration = Calibration::new(0, START);
+    calibration.time_changed();
+    assert_eq!(calibration.elapsed(START + DAY), 0);
+    assert_eq!(calibration.time_set(START + 10 * DAY + 17, START + 10 * DAY), None);
+    assert_eq!(calibration.last_set(), START + 10 * DAY);
+}
+
+#[test]
+fn trim_is_clamped() {
+    let mut calibration = Calibration::new(2000, START);
+    assert_eq!(calibration.trim_ppm(), MAX_TRIM_PPM);
+    assert_eq!(calibration.time_set(START + DAY + 3600, START + DAY), Some(-MAX_TRIM_PPM));
+}
+
+#[test]
+fn wrong_date_is_not_drift() {
+    let mut calibration = Calibration::new(10, START);
+    // the clock was a day ahead after two days
+    assert_eq!(calibration.time_set(START + 3 * DAY, START + 2 * DAY), None);
+    assert_eq!(calibration.trim_ppm(), 10);
+    assert_eq!(calibration.last_set(), START + 2 * DAY);
+}
+
+fn seconds_after(counter: &mut TickCounter, ticks: u32) -> u32 {
+    let start = counter.now().timestamp();
+    for _ in 0..ticks {
+        counter.tick();
+    }
+    counter.now().timestamp() - start
+}
+
+#[test]
+fn tick_counter_trim_speeds_up_and_slows_down() {
+    // 1000ppm is a second every 1000 seconds
+    let mut counter = TickCounter::new(10);
+    counter.set_trim(1000);
+    assert_eq!(seconds_after(&mut counter, 10 * 10_000), 10_010);
+
+    let mut counter = TickCounter::new(10);
+    counter.set_trim(-1000);
+    assert_eq!(seconds_after(&mut counter, 10 * 10_000), 9990);
+
+    let mut counter = TickCounter::new(10);
+    assert_eq!(seconds_after(&mut counter, 10 * 10_000), 10_000);
+}
@@ -0,0 +1,71 @@
+use nixiewatch_core::battery::Power;
+use nixiewatch_core::charger::*;
+
+/// Feeds `count` readings of `stat`, one per second from `uptime` on, returning the power changes.
+fn read(charger: &mut Charger, stat: Stat, uptime: &mut u32, count: usize) -> Vec<Power> {
+    let mut changes = Vec::new();
+    for _ in 0..count {
+        changes.extend(charger.update(stat, *uptime, 3900 + *uptime as u16));
+        *uptime += 1;
+    }
+    changes
+}
+
+#[test]
+fn pin_levels() {
+    assert_eq!(Stat::from_levels(false, false), Stat::Low);
+    assert_eq!(Stat::from_levels(true, true), Stat::High);
+    assert_eq!(Stat::from_levels(true, false), Stat::Floating);
+}
+
+#[test]
+fn new_level_needs_a_run_of_readings() {
+    let mut charger = Charger::new();
+    let mut uptime = 0;
+    assert_eq!(read(&mut charger, Stat::Low, &mut uptime, DEBOUNCE_READINGS as usize - 1), []);
+    assert!(charger.is_settling());
+    // A glitch starts the count over
+    read(&mut charger, Stat::Floating, &mut uptime, 1);
+    assert!(!charger.is_settling());
+    assert_eq!(read(&mut charger, Stat::Low, &mut uptime, DEBOUNCE_READINGS as usize - 1), []);
+    assert_eq!(read(&mut charger, Stat::Low, &mut uptime, 1), [Power::Charging]);
+    assert_eq!(charger.power(), Power::Charging);
+    assert!(!charger.is_settling());
+}
+
+#[test]
+fn session_until_complete() {
+    let mut charger = Charger::new();
+    let mut uptime = 100;
+    assert_eq!(charger.session(), None);
+    read(&mut charger, Stat::Low, &mut uptime, DEBOUNCE_READINGS as usize);
+    let session = charger.session().unwrap();
+    assert_eq!((session.start, session.active, session.complete), (109, true, false));
+
+    read(&mut charger, Stat::Low, &mut uptime, 50);
+    assert_eq!(charger.session().unwrap().duration, 50);
+
+    assert_eq!(read(&mut charger, Stat::High, &mut uptime, 20), [Power::Charged]);
+    // The duration stops once full
+    let session = charger.session().unwrap();
+    assert_eq!((session.duration, session.end_mv, session.complete), (60, 3900 + 169, true));
+
+    assert_eq!(read(&mut charger, Stat::Floating, &mut uptime, DEBOUNCE_READINGS as usize), [Power::Battery]);
+    let session = charger.session().unwrap();
+    assert_eq!((session.duration, session.active, session.complete), (60, false, true));
+}
+
+#[test]
+fn unplugged_while_charging() {
+    let mut charger = Charger::new();
+    let mut uptime = 0;
+    read(&mut charger, Stat::Low, &mut uptime, 30);
+    read(&mut charger, Stat::Floating, &mut uptime, DEBOUNCE_READINGS as usize);
+    let session = charger.session().unwrap();
+    assert_eq!((session.active, session.complete, session.duration), (false, false, 30));
+
+    // Plugging in again starts a new session
+    read(&mut charger, Stat::High, &mut uptime, DEBOUNCE_READINGS as usize);
+    let session = charger.session().unwrap();
+    assert_eq!((session.start, session.active, session.complete), (49, true, true));
+}
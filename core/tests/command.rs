@@ -0,0 +1,281 @@
+use nixiewatch_core::battery::Power;
+use nixiewatch_core::calendar::{Date, DateTime, Time};
+use nixiewatch_core::charger::Session;
+use nixiewatch_core::command::*;
+use nixiewatch_core::energy::Usage;
+use nixiewatch_core::ring_buffer::RingBuffer;
+use nixiewatch_core::sequence::Sequence;
+use nixiewatch_core::text::Text;
+use nixiewatch_core::settings::{Key, Settings, Value};
+use nixiewatch_core::wear::Wear;
+
+struct Reply(Vec<u8>);
+
+impl Output for Reply {
+    fn write(&mut self, data: &[u8]) {
+        self.0.extend_from_slice(data);
+    }
+}
+
+fn status() -> Status {
+    Status {
+        time: Time::new(9, 5, 7).unwrap(),
+        date: Date::new(2024, 2, 29).unwrap(),
+        battery_status: 85,
+        battery_mv: 3950,
+        power: Power::Charging,
+        charge_session: None,
+        since_set: 3600,
+        uptime: 86_400,
+        wakes: 12,
+        energy: Usage::default(),
+        wear: Wear::default(),
+        tx_dropped: 0,
+        board_revision: 1,
+        image_crc: 0xBEEF,
+        settings: Settings::default(),
+        firmware_version: "0.1.0"
+    }
+}
+
+/// Answers `line` with `status` instead of the default one.
+fn run_with(status: &Status, line: &str) -> String {
+    let mut reply = Reply(Vec::new());
+    handle_line(Ok(line.as_bytes()), status, &mut reply);
+    String::from_utf8(reply.0).unwrap()
+}
+
+/// Feeds `input` through a line buffer like the watch does, returning the reply and the requests.
+fn run(input: &str) -> (String, Vec<Request>) {
+    let status = status();
+    let mut buffer = LineBuffer::new();
+    let mut reply = Reply(Vec::new());
+    let mut requests = Vec::new();
+    for &byte in input.as_bytes() {
+        if let Some(line) = buffer.push(byte) {
+            requests.extend(handle_line(line, &status, &mut reply));
+        }
+    }
+    (String::from_utf8(reply.0).unwrap(), requests)
+}
+
+#[test]
+fn queries() {
+    assert_eq!(run("TIME\n").0, "09:05:07\nOK\n");
+    assert_eq!(run("date\r\n").0, "2024-02-29 Thu\nOK\n");
+    assert_eq!(run("BATT\n").0, "85% charging\nOK\n");
+    assert_eq!(run("CAL\n").0, "Set 3600s ago\nTrim 0ppm\nOK\n");
+    assert_eq!(run("VERSION\n").0, "nixiewatch 0.1.0 protocol 1\nOK\n");
+    assert!(run("HELP\n").0.ends_with("BOOTLOADER\nOK\n"));
+}
+
+#[test]
+fn battery_and_charge() {
+    let mut status = status();
+    status.power = Power::Battery;
+    assert_eq!(run_with(&status, "BATT"), "85% on battery\nOK\n");
+    assert_eq!(run_with(&status, "CHARGE"), "No charge since the reset\nOK\n");
+
+    let mut session = Session { start: 120, duration: 1800, end_mv: 4010, complete: false, active: true };
+    status.charge_session = Some(session);
+    assert_eq!(run_with(&status, "CHARGE"), "Charging for 1800s at 4010mV\nStarted at uptime 120s\nOK\n");
+    session.active = false;
+    status.charge_session = Some(session);
+    assert!(run_with(&status, "CHARGE").starts_with("Unplugged after 1800s"));
+    session.complete = true;
+    status.charge_session = Some(session);
+    assert!(run_with(&status, "CHARGE").starts_with("Charged in 1800s at 4010mV\n"));
+}
+
+#[test]
+fn energy() {
+    let mut status = status();
+    assert_eq!(run_with(&status, "ENERGY"), "On battery for 0s, woke up 0 times\nAwake 0s, display 0s, HV 0s, USB 0s\n\
+                                             Used 0uAh\nRuntime unknown\nOK\n");
+    status.energy = Usage { seconds: 7200, awake: 60, display: 50, hv: 50, usb: 0, wakes: 40, used_uah: 1000 };
+    // 85% of 150mAh at 500uA
+    assert_eq!(run_with(&status, "ENERGY"), "On battery for 7200s, woke up 40 times\nAwake 60s, display 50s, HV 50s, USB 0s\n\
+                                             Used 1000uAh, 500uA on average\nAbout 255h left\nOK\n");
+    assert!(run_with(&status, "STATUS").ends_with(" used_uah=1000 runtime_h=255\nOK\n"));
+}
+
+#[test]
+fn status_record() {
+    assert_eq!(run("STATUS\n").0, "STATUS v=1 time=09:05:07 date=2024-02-29 battery=85 battery_mv=3950 charge=charging \
+                                   uptime=86400 wakes=12 firmware=0.1.0 tx_dropped=0 board=1 image_crc=48879 power=charging used_uah=0\nOK\n");
+}
+
+#[test]
+fn status_tells_all_power_states_apart() {
+    for (power, name) in [(Power::Battery, "battery"), (Power::Charging, "charging"), (Power::Charged, "charged")] {
+        let reply = run_with(&Status { power, ..status() }, "STATUS");
+        assert!(reply.contains(&format!(" charge={} ", name)), "{}", reply);
+        assert!(reply.contains(&format!(" power={} ", name)), "{}", reply);
+    }
+}
+
+#[test]
+fn setting_time_and_date() {
+    assert_eq!(run("TIME 12:34\n"), ("OK\n".into(), vec![Request::SetTime(12, 34)]));
+    assert_eq!(run("TIME 12:34:56\n").1, vec![Request::SyncTime(Time::new(12, 34, 56).unwrap())]);
+    assert_eq!(run("DATE 2025-12-31\n").1, vec![Request::SetDate(Date::new(2025, 12, 31).unwrap())]);
+    assert_eq!(run("DATE SHOW\n").1, vec![Request::ShowDate]);
+    // The RTC only does 2000 up to 2099
+    assert_eq!(run("DATE 2000-01-01\n").1, vec![Request::SetDate(Date::new(2000, 1, 1).unwrap())]);
+    assert_eq!(run("DATE 2099-12-31\n").1, vec![Request::SetDate(Date::new(2099, 12, 31).unwrap())]);
+    assert_eq!(run("DATE 1999-12-31\n"), ("ERR 5 out of range\n".into(), vec![]));
+    assert_eq!(run("DATE 2100-01-01\n"), ("ERR 5 out of range\n".into(), vec![]));
+    assert_eq!(run("DATE 2150-01-01\n"), ("ERR 5 out of range\n".into(), vec![]));
+}
+
+#[test]
+fn bootloader_replies_before_restarting() {
+    assert_eq!(run("bootloader\n"), ("OK\n".into(), vec![Request::Bootloader]));
+    assert_eq!(run("BOOTLOADER now\n"), ("ERR 3 bad argument\n".into(), vec![]));
+}
+
+#[test]
+fn unix_timestamps() {
+    assert_eq!(run("EPOCH\n").0, "1709197507\nOK\n");
+    let now = DateTime::new(Date::new(2023, 11, 14).unwrap(), Time::new(22, 13, 20).unwrap());
+    assert_eq!(run("EPOCH 1700000000\n"), ("OK\n".into(), vec![Request::SyncDateTime(now)]));
+    // The RTC only does 2000 up to 2099
+    assert_eq!(run("EPOCH 946684799\n").0, "ERR 5 out of range\n");
+    assert_eq!(run("EPOCH 4102444800\n").0, "ERR 5 out of range\n");
+    assert_eq!(run("EPOCH 99999999999\n").0, "ERR 3 bad argument\n");
+    assert_eq!(run("EPOCH -1\n").0, "ERR 3 bad argument\n");
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    for line in ["TIME 24:00", "TIME 12:60", "TIME 12:00:60", "TIME 1a:00", "TIME 12", "TIME 12:00:00:00",
+                 "DATE 2023-02-29", "DATE 2024-13-01", "DATE 20240101", "TIME 12:00 extra", "BATT now"] {
+        assert_eq!(run(&format!("{}\n", line)), ("ERR 3 bad argument\n".into(), vec![]), "{}", line);
+    }
+}
+
+#[test]
+fn unknown_command() {
+    assert_eq!(run("FOO\n").0, "ERR 1 unknown command\n");
+    // The old single character commands
+    assert_eq!(run("?\n").0, "ERR 1 unknown command\n");
+}
+
+#[test]
+fn empty_lines_are_ignored() {
+    assert_eq!(run("\n\r\n  \n"), (String::new(), vec![]));
+}
+
+#[test]
+fn lines_split_over_packets() {
+    let status = status();
+    let mut buffer = LineBuffer::new();
+    let mut reply = Reply(Vec::new());
+    for packet in ["TI", "ME 1", "2:00\n"] {
+        for &byte in packet.as_bytes() {
+            if let Some(line) = buffer.push(byte) {
+                assert_eq!(handle_line(line, &status, &mut reply), Some(Request::SetTime(12, 0)));
+            }
+        }
+    }
+    assert_eq!(reply.0, b"OK\n");
+}
+
+#[test]
+fn long_line_is_dropped() {
+    let long = "X".repeat(MAX_LINE_LEN + 1);
+    assert_eq!(run(&format!("{}\nTIME\n", long)).0, "ERR 6 line too long\n09:05:07\nOK\n");
+}
+
+#[test]
+fn get_settings() {
+    assert_eq!(run("GET display_duration\n").0, "DISPLAY_DURATION 4000\nOK\n");
+    assert_eq!(run("GET START_TIME\n").0, "START_TIME 13:37:00\nOK\n");
+    let all = run("GET\n").0;
+    assert_eq!(all.lines().count(), Key::ALL.len() + 1);
+    assert!(all.contains("TRIM_PPM 0\n"));
+    assert_eq!(run("GET FOO\n").0, "ERR 4 unknown setting\n");
+}
+
+#[test]
+fn set_settings() {
+    assert_eq!(run("SET BATTERY_LOW 20\n"), ("OK\n".into(), vec![Request::Set(Key::BatteryLow, Value::Number(20))]));
+    assert_eq!(run("SET TRIM_PPM -12\n").1, vec![Request::Set(Key::TrimPpm, Value::Number(-12))]);
+    assert_eq!(run("SET START_TIME 08:00\n").1, vec![Request::Set(Key::StartTime, Value::Time(Time::new(8, 0, 0).unwrap()))]);
+    assert_eq!(run("SET BATTERY_LOW 101\n"), ("ERR 5 out of range\n".into(), vec![]));
+    assert_eq!(run("SET BATTERY_LOW\n").0, "ERR 2 missing argument\n");
+    assert_eq!(run("SET FOO 1\n").0, "ERR 4 unknown setting\n");
+    assert_eq!(run("SET BATTERY_LOW ten\n").0, "ERR 3 bad argument\n");
+}
+
+#[test]
+fn sequence_setting() {
+    let sequence = Sequence::parse(b"H1:10,M2:10,X:5").unwrap();
+    assert_eq!(run("SET SEQUENCE h1:10,m2:10,x:5\n").1, vec![Request::Set(Key::Sequence, Value::Sequence(sequence))]);
+    assert_eq!(run("SET SEQUENCE H1:10,Q:10\n").0, "ERR 3 bad argument\n");
+    assert_eq!(run("GET SEQUENCE\n").0, "SEQUENCE DEFAULT\nOK\n");
+
+    let mut reply = Reply(Vec::new());
+    write_sequence(&mut reply, &Sequence::parse(b"D2:0,N:15:4").unwrap());
+    assert_eq!(reply.0, b"D2:0,N:15:4");
+
+    let mut reply = Reply(Vec::new());
+    write_sequence(&mut reply, &Sequence::parse(b"fade/H1:10,M2:10").unwrap());
+    assert_eq!(reply.0, b"FADE/H1:10,M2:10");
+}
+
+#[test]
+fn number_formatting() {
+    let mut reply = Reply(Vec::new());
+    write_number(&mut reply, 0);
+    write_number(&mut reply, -480);
+    write_padded(&mut reply, 7, 3);
+    write_number(&mut reply, i32::MIN);
+    assert_eq!(reply.0, b"0-480007-2147483648");
+}
+
+#[test]
+fn message_takes_the_rest_of_the_line() {
+    let text = Text::from_bytes(b"Hello  world 13.37").unwrap();
+    assert_eq!(run("msg Hello  world 13.37 \n"), ("OK\n".into(), vec![Request::ShowMessage(text)]));
+    assert_eq!(run("MSG\n").0, "ERR 2 missing argument\n");
+    assert_eq!(run(&format!("MSG {}\n", "X".repeat(40))).0, "ERR 5 out of range\n");
+}
+
+#[test]
+fn exercise_and_usage() {
+    assert_eq!(run("exercise\n"), ("OK\n".into(), vec![Request::Exercise]));
+    assert_eq!(run("EXERCISE now\n").0, "ERR 3 bad argument\n");
+
+    let mut status = status();
+    status.wear.seconds[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    status.wear.seconds[15] = 3600;
+    assert_eq!(run_with(&status, "USAGE"), "1 a=1 b=2 c=3 d=4 e=5 f=6 g=7 dp=8\n\
+                                            2 a=0 b=0 c=0 d=0 e=0 f=0 g=0 dp=3600\nOK\n");
+}
+
+#[test]
+fn self_test() {
+    assert_eq!(run("SELFTEST\n"), ("OK\n".into(), vec![Request::SelfTest]));
+    assert_eq!(run("SELFTEST 1\n").0, "ERR 3 bad argument\n");
+}
+
+#[test]
+fn widest_get_reply_fits_the_tx_buffer() {
+    let mut settings = Settings::default();
+    let widest = [(Key::MultiplexRate, 1000), (Key::DisplayDuration, 30000), (Key::BatteryEmpty, 4500),
+                  (Key::BatteryFull, 4500), (Key::BatteryLow, 100), (Key::BatteryCritical, 100), (Key::TrimPpm, -480),
+                  (Key::BatteryCapacity, 5000), (Key::CurrentSleep, 65535), (Key::CurrentRun, 65535),
+                  (Key::CurrentDisplay, 65535), (Key::CurrentHv, 65535), (Key::ScrollStep, 2000), (Key::Blanking, 1000),
+                  (Key::ExerciseDuration, 600)];
+    for (key, value) in widest {
+        assert!(settings.set(key, Value::Number(value)));
+    }
+    let sequence = format!("ROLL/{}", ["T2:255:7"; 8].join(","));
+    assert!(settings.set(Key::Sequence, Value::Sequence(Sequence::parse(sequence.as_bytes()).unwrap())));
+
+    let mut tx = RingBuffer::<TX_BUFFER_SIZE>::new();
+    handle_line(Ok(b"GET"), &Status { settings, ..status() }, &mut tx);
+    assert_eq!(tx.dropped(), 0);
+    assert!(tx.len() > 450);
+}
@@ -0,0 +1,64 @@
+//! Flash page simulator with the erase and program rules of the STM32F0.
+
+use nixiewatch_core::settings::{Flash, FlashError};
+
+pub struct SimulatedFlash {
+    pub data: Vec<u8>,
+    pub erase_count: u32,
+    /// Half words left before a simulated power loss, programming and erasing fail after that
+    pub power_budget: Option<usize>,
+}
+
+impl SimulatedFlash {
+    /// A factory fresh page: erased.
+    pub fn new(size: usize) -> Self {
+        SimulatedFlash { data: vec![0xFF; size], erase_count: 0, power_budget: None }
+    }
+
+    fn spend_power(&mut self) -> Result<(), FlashError> {
+        match self.power_budget {
+            Some(0) => Err(FlashError::Program),
+            Some(ref mut budget) => {
+                *budget -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Flash for SimulatedFlash {
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn read(&self, offset: usize, data: &mut [u8]) {
+        data.copy_from_slice(&self.data[offset..offset + data.len()]);
+    }
+
+    fn erase(&mut self) -> Result<(), FlashError> {
+        if self.power_budget == Some(0) {
+            return Err(FlashError::Erase);
+        }
+        self.erase_count += 1;
+        self.data.iter_mut().for_each(|b| *b = 0xFF);
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        assert_eq!(offset % 2, 0, "unaligned program at {}", offset);
+        assert_eq!(data.len() % 2, 0, "odd program length {}", data.len());
+        for (i, half_word) in data.chunks(2).enumerate() {
+            self.spend_power()?;
+            let at = offset + i * 2;
+            let current = u16::from_le_bytes([self.data[at], self.data[at + 1]]);
+            let value = u16::from_le_bytes([half_word[0], half_word[1]]);
+            // Like PGERR: only erased half words can be programmed, except for writing zero
+            if current != 0xFFFF && value != 0 {
+                panic!("programming {:#06x} over {:#06x} at {}", value, current, at);
+            }
+            self.data[at..at + 2].copy_from_slice(half_word);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! Mock pins recording every level change into a shared log, so tests can assert the
+//! exact sequence of pin writes the display produces.
+#![allow(dead_code)]
+
+pub mod flash;
+
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::digital::v2::OutputPin;
+use nixiewatch_core::glyph::Glyph;
+use nixiewatch_core::nixie_segment::{NixieClock, NixieDisplay};
+use nixiewatch_core::time_source::TickCounter;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PinId {
+    Nixie1,
+    Nixie2,
+    Segment(u8),
+    Dot,
+    Enable,
+}
+
+pub type Log = Rc<RefCell<Vec<(PinId, bool)>>>;
+
+pub struct MockPin {
+    id: PinId,
+    log: Log,
+}
+
+impl OutputPin for MockPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.log.borrow_mut().push((self.id, false));
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.log.borrow_mut().push((self.id, true));
+        Ok(())
+    }
+}
+
+pub fn display() -> (NixieDisplay<MockPin>, Log) {
+    let log: Log = Rc::new(RefCell::new(Vec::new()));
+    let pin = |id| MockPin { id, log: log.clone() };
+    let segments = [
+        pin(PinId::Segment(0)),
+        pin(PinId::Segment(1)),
+        pin(PinId::Segment(2)),
+        pin(PinId::Segment(3)),
+        pin(PinId::Segment(4)),
+        pin(PinId::Segment(5)),
+        pin(PinId::Segment(6)),
+    ];
+    let display = NixieDisplay::new(pin(PinId::Nixie1), pin(PinId::Nixie2), segments, pin(PinId::Dot), pin(PinId::Enable));
+    (display, log)
+}
+
+pub fn clock(ticks_per_second: u32) -> (NixieClock<MockPin, TickCounter>, Log) {
+    let (display, log) = display();
+    (NixieClock::new(display, TickCounter::new(ticks_per_second), ticks_per_second), log)
+}
+
+/// Level of every pin after replaying the log, as seen by the tube.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PinState {
+    pub nixie1: bool,
+    pub nixie2: bool,
+    pub segments: u8,
+    pub dot: bool,
+    pub enable: bool,
+}
+
+impl PinState {
+    pub fn apply(&mut self, (id, level): (PinId, bool)) {
+        match id {
+            PinId::Nixie1 => self.nixie1 = level,
+            PinId::Nixie2 => self.nixie2 = level,
+            PinId::Dot => self.dot = level,
+            PinId::Enable => self.enable = level,
+            PinId::Segment(n) => {
+                if level {
+                    self.segments |= 1 << n;
+                } else {
+                    self.segments &= !(1 << n);
+                }
+            }
+        }
+    }
+
+    /// Replays and clears the log.
+    pub fn replay(&mut self, log: &Log) {
+        for event in log.borrow_mut().drain(..) {
+            self.apply(event);
+        }
+    }
+}
+
+/// Expected log for blanking the display and lighting one digit with the given segment mask.
+pub fn digit_sequence(digit: u8, glyph: Glyph, dot: bool) -> Vec<(PinId, bool)> {
+    let mut expected: Vec<(PinId, bool)> = (0..7).map(|n| (PinId::Segment(n), false)).collect();
+    expected.extend([(PinId::Dot, false), (PinId::Nixie1, false), (PinId::Nixie2, false)]);
+    expected.push((if digit == 0 { PinId::Nixie1 } else { PinId::Nixie2 }, true));
+    for n in 0..7 {
+        expected.push((PinId::Segment(n), glyph.segments() & (1 << n) != 0));
+    }
+    if dot {
+        expected.push((PinId::Dot, true));
+    }
+    expected
+}
@@ -0,0 +1,98 @@
+use nixiewatch_core::div::{idivmod32, idivmod64, udivmod32, udivmod64};
+
+/// Edge values plus a spread of others from a simple generator, the same on every run.
+fn samples() -> Vec<u64> {
+    let mut samples = vec![0, 1, 2, 3, 7, 10, 60, 3600, 86_400, 0x7FFF_FFFF, 0x8000_0000, 0xFFFF_FFFE, 0xFFFF_FFFF,
+                           0x1_0000_0000, 0x7FFF_FFFF_FFFF_FFFF, 0x8000_0000_0000_0000, u64::MAX - 1, u64::MAX];
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    for _ in 0..200 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        // Short ones too, so the quotients aren't all tiny
+        samples.push(state >> (state % 64));
+    }
+    samples
+}
+
+#[test]
+fn unsigned_32_bit() {
+    for n in samples().into_iter().map(|n| n as u32) {
+        for d in samples().into_iter().map(|d| d as u32).filter(|&d| d != 0) {
+            assert_eq!(udivmod32(n, d), (n / d, n % d), "{} / {}", n, d);
+        }
+    }
+}
+
+#[test]
+fn unsigned_64_bit() {
+    for n in samples() {
+        for d in samples().into_iter().filter(|&d| d != 0) {
+            assert_eq!(udivmod64(n, d), (n / d, n % d), "{} / {}", n, d);
+        }
+    }
+}
+
+#[test]
+fn signed_32_bit() {
+    for a in samples().into_iter().map(|a| a as i32) {
+        for b in samples().into_iter().map(|b| b as i32).filter(|&b| b != 0) {
+            assert_eq!(idivmod32(a, b), (a.wrapping_div(b), a.wrapping_rem(b)), "{} / {}", a, b);
+        }
+    }
+}
+
+#[test]
+fn signed_64_bit() {
+    for a in samples().into_iter().map(|a| a as i64) {
+        for b in samples().into_iter().map(|b| b as i64).filter(|&b| b != 0) {
+            assert_eq!(idivmod64(a, b), (a.wrapping_div(b), a.wrapping_rem(b)), "{} / {}", a, b);
+        }
+    }
+}
+
+#[test]
+fn signs_round_towards_zero() {
+    assert_eq!(idivmod32(7, 2), (3, 1));
+    assert_eq!(idivmod32(-7, 2), (-3, -1));
+    assert_eq!(idivmod32(7, -2), (-3, 1));
+    assert_eq!(idivmod32(-7, -2), (3, -1));
+    assert_eq!(idivmod64(-7, 2), (-3, -1));
+    assert_eq!(idivmod64(7, -2), (-3, 1));
+}
+
+#[test]
+fn most_negative() {
+    assert_eq!(idivmod32(i32::MIN, 1), (i32::MIN, 0));
+    assert_eq!(idivmod32(i32::MIN, -1), (i32::MIN, 0));
+    assert_eq!(idivmod32(i32::MIN, i32::MIN), (1, 0));
+    assert_eq!(idivmod32(i32::MAX, i32::MIN), (0, i32::MAX));
+    assert_eq!(idivmod32(i32::MIN, 10), (-214_748_364, -8));
+    assert_eq!(idivmod64(i64::MIN, -1), (i64::MIN, 0));
+    assert_eq!(idivmod64(i64::MIN, 10), (-922_337_203_685_477_580, -8));
+}
+
+#[test]
+fn largest_operands() {
+    assert_eq!(udivmod32(u32::MAX, 1), (u32::MAX, 0));
+    assert_eq!(udivmod32(u32::MAX, u32::MAX), (1, 0));
+    assert_eq!(udivmod32(u32::MAX - 1, u32::MAX), (0, u32::MAX - 1));
+    assert_eq!(udivmod32(u32::MAX, 2), (u32::MAX / 2, 1));
+    assert_eq!(udivmod32(u32::MAX, 0x8000_0001), (1, 0x7FFF_FFFE));
+    assert_eq!(udivmod64(u64::MAX, 1), (u64::MAX, 0));
+    assert_eq!(udivmod64(u64::MAX, u64::MAX), (1, 0));
+    assert_eq!(udivmod64(u64::MAX, u32::MAX as u64), (u64::MAX / u32::MAX as u64, 0));
+    assert_eq!(udivmod64(u64::MAX, 10), (u64::MAX / 10, 5));
+}
+
+#[test]
+fn zero_divisor_gives_all_ones() {
+    // `/` and `%` panic instead, and Rust checks before calling the division routines
+    for n in [0, 1, 12345, u32::MAX] {
+        assert_eq!(udivmod32(n, 0), (u32::MAX, n));
+        assert_eq!(udivmod64(n as u64, 0), (u64::MAX, n as u64));
+    }
+    assert_eq!(idivmod32(7, 0), (-1, 7));
+    assert_eq!(idivmod32(-7, 0), (1, -7));
+    assert_eq!(idivmod64(7, 0), (-1, 7));
+}
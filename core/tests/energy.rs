@@ -0,0 +1,43 @@
+use nixiewatch_core::energy::*;
+
+const CURRENTS: Currents = Currents { sleep: 50, run: 4000, display: 6000, hv: 20000 };
+
+#[test]
+fn charge_adds_up_per_state() {
+    let mut meter = Meter::new();
+    // Two seconds of display at 100 ticks per second, one more awake with the display off
+    for tick in 0..300 {
+        meter.tick(tick < 200, tick < 200, false, false);
+    }
+    meter.wake();
+    let usage = meter.usage(3600, 100, &CURRENTS);
+    assert_eq!((usage.seconds, usage.awake, usage.display, usage.hv, usage.wakes), (3600, 3, 2, 2, 1));
+    // 3s * 4000uA + 2s * 26000uA + 3597s * 50uA
+    assert_eq!(usage.used_uah, (12_000 + 52_000 + 179_850) / 3600);
+    assert_eq!(usage.average_ua(), Some(67));
+}
+
+#[test]
+fn usb_power_doesnt_count() {
+    let mut meter = Meter::new();
+    meter.restart(1000);
+    for _ in 0..600 {
+        meter.tick(false, false, true, true);
+    }
+    let usage = meter.usage(1010, 100, &CURRENTS);
+    assert_eq!((usage.seconds, usage.usb, usage.awake, usage.used_uah), (4, 6, 0, 0));
+
+    // Unplugged, the count starts over
+    meter.restart(1010);
+    assert_eq!(meter.usage(1010, 100, &CURRENTS), Usage::default());
+    assert_eq!(meter.usage(1010, 100, &CURRENTS).runtime_hours(100_000), None);
+}
+
+#[test]
+fn runtime_from_the_average() {
+    let usage = Usage { seconds: 36_000, used_uah: 5000, ..Usage::default() };
+    assert_eq!(usage.average_ua(), Some(500));
+    assert_eq!(remaining_uah(150, 40), 60_000);
+    assert_eq!(usage.runtime_hours(remaining_uah(150, 40)), Some(120));
+    assert_eq!(remaining_uah(150, 200), 150_000);
+}
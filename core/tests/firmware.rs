@@ -0,0 +1,30 @@
+use nixiewatch_core::firmware::*;
+
+fn image(board_revision: u8) -> Vec<u8> {
+    let mut image = vec![0xFF; 100];
+    image.extend_from_slice(&ImageInfo::record(board_revision));
+    image.extend_from_slice(&[0; 50]);
+    image
+}
+
+#[test]
+fn record_is_found_in_the_image() {
+    assert_eq!(ImageInfo::find(&image(3)), Some(ImageInfo { board_revision: 3 }));
+    assert_eq!(ImageInfo::find(&ImageInfo::record(1)), Some(ImageInfo { board_revision: 1 }));
+}
+
+#[test]
+fn other_files_are_not_images() {
+    assert_eq!(ImageInfo::find(&[]), None);
+    assert_eq!(ImageInfo::find(&MAGIC), None);
+    assert_eq!(ImageInfo::find(b"just some text that isn't firmware"), None);
+}
+
+#[test]
+fn image_crc_covers_every_byte() {
+    // The CRC-16/CCITT-FALSE check value
+    assert_eq!(image_crc(b"123456789"), 0x29B1);
+    let mut changed = image(1);
+    changed[0] ^= 1;
+    assert_ne!(image_crc(&image(1)), image_crc(&changed));
+}
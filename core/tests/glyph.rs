@@ -0,0 +1,72 @@
+use nixiewatch_core::glyph::{Glyph, BAR_LEVELS};
+
+/// Segment mask of segments written as letters, `.` for the point.
+fn mask(segments: &str) -> u8 {
+    segments.bytes().fold(0, |mask, segment| match segment {
+        b'a'..=b'g' => mask | 1 << (segment - b'a'),
+        b'.' => mask | 0x80,
+        _ => panic!("no segment {}", segment as char),
+    })
+}
+
+#[test]
+fn font_masks() {
+    let font: [(u8, &str); 95] = [
+        (b' ', ""), (b'!', "bc."), (b'"', "bf"), (b'#', "bcdefg"), (b'$', "acdfg"), (b'%', "beg."),
+        (b'&', "bcg"), (b'\'', "f"), (b'(', "adf"), (b')', "abd"), (b'*', "abfg"), (b'+', "efg"),
+        (b',', "e"), (b'-', "g"), (b'.', "."), (b'/', "beg"), (b'0', "abcdef"), (b'1', "bc"),
+        (b'2', "abdeg"), (b'3', "abcdg"), (b'4', "bcfg"), (b'5', "acdfg"), (b'6', "acdefg"), (b'7', "abc"),
+        (b'8', "abcdefg"), (b'9', "abcdfg"), (b':', "ad"), (b';', "acd"), (b'<', "afg"), (b'=', "dg"),
+        (b'>', "abg"), (b'?', "abeg."), (b'@', "abcdeg"), (b'A', "abcefg"), (b'B', "cdefg"), (b'C', "adef"),
+        (b'D', "bcdeg"), (b'E', "adefg"), (b'F', "aefg"), (b'G', "acdef"), (b'H', "bcefg"), (b'I', "ef"),
+        (b'J', "bcde"), (b'K', "acefg"), (b'L', "def"), (b'M', "ace"), (b'N', "abcef"), (b'O', "abcdef"),
+        (b'P', "abefg"), (b'Q', "abdfg"), (b'R', "abef"), (b'S', "acdfg"), (b'T', "defg"), (b'U', "bcdef"),
+        (b'V', "bcdef"), (b'W', "bdf"), (b'X', "bcefg"), (b'Y', "bcdfg"), (b'Z', "abdeg"), (b'[', "adef"),
+        (b'\\', "cfg"), (b']', "abcd"), (b'^', "abf"), (b'_', "d"), (b'`', "b"), (b'a', "abcdeg"),
+        (b'b', "cdefg"), (b'c', "deg"), (b'd', "bcdeg"), (b'e', "abdefg"), (b'f', "aefg"), (b'g', "abcdfg"),
+        (b'h', "cefg"), (b'i', "e"), (b'j', "cd"), (b'k', "acefg"), (b'l', "ef"), (b'm', "ce"),
+        (b'n', "ceg"), (b'o', "cdeg"), (b'p', "abefg"), (b'q', "abcfg"), (b'r', "eg"), (b's', "acdfg"),
+        (b't', "defg"), (b'u', "cde"), (b'v', "cde"), (b'w', "ce"), (b'x', "bcefg"), (b'y', "bcdfg"),
+        (b'z', "abdeg"), (b'{', "bcg"), (b'|', "ef"), (b'}', "efg"), (b'~', "a")
+    ];
+    for (i, &(c, segments)) in font.iter().enumerate() {
+        assert_eq!(c, b' ' + i as u8);
+        assert_eq!(Glyph::from_ascii(c).segments(), mask(segments), "{}", c as char);
+    }
+}
+
+#[test]
+fn anything_but_printable_ascii_is_blank() {
+    for c in (0..b' ').chain(0x7F..=0xFF) {
+        assert_eq!(Glyph::from_ascii(c), Glyph::BLANK);
+    }
+}
+
+#[test]
+fn digits_and_symbols() {
+    for value in 0..10 {
+        assert_eq!(Glyph::digit(value), Glyph::from_ascii(b'0' + value));
+    }
+    assert_eq!(Glyph::digit(10), Glyph::BLANK);
+    assert_eq!(Glyph::BLANK.segments(), 0);
+    assert_eq!(Glyph::MINUS.segments(), mask("g"));
+    assert_eq!(Glyph::DEGREE.segments(), mask("abfg"));
+    assert_eq!(Glyph::from_ascii(b'-'), Glyph::MINUS);
+    assert_eq!(Glyph::from_ascii(b'*'), Glyph::DEGREE);
+}
+
+#[test]
+fn bar_graph_fills_clockwise() {
+    let bars: Vec<u8> = (0..=BAR_LEVELS + 1).map(|level| Glyph::bar(level).segments()).collect();
+    assert_eq!(bars, [mask(""), mask("a"), mask("ab"), mask("abc"), mask("abcd"), mask("abcde"),
+                      mask("abcdef"), mask("abcdef")]);
+}
+
+#[test]
+fn point_is_kept_apart() {
+    let glyph = Glyph::from_ascii(b'3');
+    assert!(!glyph.has_dot());
+    assert!(glyph.with_dot().has_dot());
+    assert_eq!(glyph.with_dot().segments(), mask("abcdg."));
+    assert_eq!(Glyph::from_segments(mask("abcdg.")), glyph.with_dot());
+}
@@ -0,0 +1,32 @@
+use nixiewatch_core::mem::{copy, fill};
+
+#[test]
+fn copies_between_buffers() {
+    let src = *b"nixie";
+    let mut dest = [0u8; 7];
+    unsafe { copy(dest.as_mut_ptr().add(1), src.as_ptr(), src.len()) };
+    assert_eq!(&dest, b"\0nixie\0");
+    unsafe { copy(dest.as_mut_ptr(), src.as_ptr(), 0) };
+    assert_eq!(&dest, b"\0nixie\0");
+}
+
+#[test]
+fn overlapping_copies_keep_the_source() {
+    let mut data = *b"abcdef--";
+    let base = data.as_mut_ptr();
+    // The destination after the source
+    unsafe { copy(base.add(2), base, 6) };
+    assert_eq!(&data, b"ababcdef");
+    // The destination before the source
+    unsafe { copy(base, base.add(2), 6) };
+    assert_eq!(&data, b"abcdefef");
+}
+
+#[test]
+fn fills() {
+    let mut data = [1u8; 6];
+    unsafe { fill(data.as_mut_ptr().add(1), 4, 0xAA) };
+    assert_eq!(data, [1, 0xAA, 0xAA, 0xAA, 0xAA, 1]);
+    unsafe { fill(data.as_mut_ptr(), 6, 0) };
+    assert_eq!(data, [0; 6]);
+}
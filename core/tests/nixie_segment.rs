@@ -0,0 +1,677 @@
+mod common;
+
+use common::*;
+use nixiewatch_core::battery::State;
+use nixiewatch_core::calendar::Date;
+use nixiewatch_core::glyph::{Glyph, BAR_LEVELS};
+use nixiewatch_core::nixie_segment::{AmPm, DotStatus, BRIGHTNESS_LEVELS};
+use nixiewatch_core::self_test::{self, Step};
+use nixiewatch_core::sequence::Sequence;
+use nixiewatch_core::text::Text;
+use nixiewatch_core::wear::Wear;
+
+/// What the tubes show after a tick: the lit anode (0 or 1), segment mask and dot.
+fn lit(state: &PinState) -> Option<(u8, u8, bool)> {
+    if !state.enable {
+        return None;
+    }
+    match (state.nixie1, state.nixie2) {
+        (true, false) => Some((0, state.segments, state.dot)),
+        (false, true) => Some((1, state.segments, state.dot)),
+        (false, false) => None,
+        (true, true) => panic!("both anodes driven at the same time"),
+    }
+}
+
+/// Segment masks of a digit and a character, as `lit` reports them.
+fn digit(value: u8) -> u8 {
+    Glyph::digit(value).segments()
+}
+
+fn glyph(c: u8) -> u8 {
+    Glyph::from_ascii(c).segments()
+}
+
+#[test]
+fn update_alternates_digits() {
+    let (mut display, log) = display();
+    display.set_digit(0, Glyph::digit(1), DotStatus::Off);
+    display.set_digit(1, Glyph::digit(2), DotStatus::Off);
+
+    display.update();
+    assert_eq!(*log.borrow(), digit_sequence(1, Glyph::digit(2), false));
+    log.borrow_mut().clear();
+
+    display.update();
+    assert_eq!(*log.borrow(), digit_sequence(0, Glyph::digit(1), false));
+    log.borrow_mut().clear();
+
+    display.update();
+    assert_eq!(*log.borrow(), digit_sequence(1, Glyph::digit(2), false));
+}
+
+#[test]
+fn dot_follows_dot_status() {
+    let (mut display, log) = display();
+    display.set_digit(0, Glyph::digit(4), DotStatus::Digit2);
+    display.set_digit(1, Glyph::digit(5), DotStatus::Digit2);
+
+    display.update();
+    assert_eq!(*log.borrow(), digit_sequence(1, Glyph::digit(5), true));
+    log.borrow_mut().clear();
+
+    display.update();
+    assert_eq!(*log.borrow(), digit_sequence(0, Glyph::digit(4), false));
+}
+
+#[test]
+fn off_drives_everything_low() {
+    let (mut display, log) = display();
+    display.on();
+    display.update();
+    display.off();
+
+    let mut state = PinState::default();
+    state.replay(&log);
+    assert_eq!(state, PinState::default());
+}
+
+#[test]
+fn idle_clock_keeps_display_dark() {
+    let (mut clock, log) = clock(4);
+    for _ in 0..20 {
+        clock.tick();
+        assert!(!clock.is_display_on());
+    }
+    let mut state = PinState::default();
+    state.replay(&log);
+    assert_eq!(lit(&state), None);
+}
+
+#[test]
+fn first_tick_enables_high_voltage_then_shows_hours() {
+    let (mut clock, log) = clock(4);
+    clock.set_time(13, 37);
+    clock.show_time();
+    clock.tick();
+
+    let mut expected = vec![(PinId::Enable, true)];
+    expected.extend(digit_sequence(1, Glyph::digit(3), false));
+    assert_eq!(*log.borrow(), expected);
+}
+
+#[test]
+fn time_and_charge_sequence() {
+    let (mut clock, log) = clock(4);
+    clock.set_time(13, 37);
+    clock.set_charge_level(100);
+    clock.show_time_and_charge();
+
+    let hours = digit(1);
+    let hours_low = digit(3);
+    let minutes = digit(3);
+    let minutes_low = digit(7);
+    let charge = Glyph::bar(BAR_LEVELS).segments();
+    let expected = [
+        // hours, dot on the first tube
+        Some((1, hours_low, false)), Some((0, hours, true)), Some((1, hours_low, false)), Some((0, hours, true)),
+        // minutes, dot on the second tube
+        Some((1, minutes_low, true)), Some((0, minutes, false)), Some((1, minutes_low, true)), Some((0, minutes, false)),
+        // bar graph
+        Some((1, charge, false)), Some((0, charge, false)), Some((1, charge, false)), Some((0, charge, false)),
+        // dark for the remaining second
+        None, None, None, None,
+    ];
+
+    let mut state = PinState::default();
+    for (tick, shown) in expected.iter().enumerate() {
+        clock.tick();
+        state.replay(&log);
+        assert_eq!(lit(&state), *shown, "tick {}", tick);
+        assert!(clock.is_display_on(), "tick {}", tick);
+    }
+
+    clock.tick();
+    assert!(!clock.is_display_on());
+    state.replay(&log);
+    assert_eq!(state, PinState::default());
+}
+
+#[test]
+fn time_only_skips_bar_graph() {
+    let (mut clock, log) = clock(2);
+    clock.set_time(9, 5);
+    clock.show_time();
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..8 {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state));
+        assert_eq!(clock.is_hv_on(), state.enable);
+    }
+
+    // The third second lights nothing
+    assert_eq!(shown[4], None);
+    assert_eq!(shown[5], None);
+    assert_eq!(shown[6], None);
+    assert_eq!(shown[0], Some((1, digit(9), false)));
+    assert_eq!(shown[2], Some((1, digit(5), true)));
+}
+
+#[test]
+fn empty_battery_shows_blank_digits_with_dots() {
+    let (mut clock, log) = clock(2);
+    clock.show_empty();
+
+    let mut state = PinState::default();
+    clock.tick();
+    state.replay(&log);
+    assert_eq!(lit(&state), Some((1, 0, false)));
+    clock.tick();
+    state.replay(&log);
+    assert_eq!(lit(&state), Some((0, 0, true)));
+    clock.tick();
+    state.replay(&log);
+    assert_eq!(lit(&state), Some((1, 0, true)));
+}
+
+#[test]
+fn wake_up_follows_the_battery_state() {
+    let (mut clock, log) = clock(2);
+    clock.set_time(9, 5);
+    clock.set_battery_state(State::Low);
+    clock.wake_up();
+    let mut state = PinState::default();
+    clock.tick();
+    state.replay(&log);
+    assert_eq!(lit(&state), Some((1, digit(9), false)));
+
+    for _ in 0..10 {
+        clock.tick();
+    }
+    clock.set_battery_state(State::Critical);
+    clock.wake_up();
+    clock.tick();
+    state.replay(&log);
+    assert_eq!(lit(&state), Some((1, 0, false)));
+}
+
+#[test]
+fn charging_animation_fills_the_bar_graph() {
+    // 6 ticks per second make one animation step per tick
+    let (mut clock, log) = clock(6);
+    clock.set_charge_level(50);
+    clock.show_charging();
+
+    let mut state = PinState::default();
+    let mut glyphs = Vec::new();
+    for _ in 0..18 {
+        clock.tick();
+        state.replay(&log);
+        glyphs.push(lit(&state).unwrap().1);
+    }
+    // From the charge level, 3 segments, up to full and over again, through all three frames
+    let bars: Vec<u8> = (3..=BAR_LEVELS).map(|level| Glyph::bar(level).segments()).collect();
+    let expected: Vec<u8> = bars.iter().cycle().skip(1).take(18).copied().collect();
+    assert_eq!(glyphs, expected);
+}
+
+#[test]
+fn waking_up_while_charging_animates_the_charge() {
+    let (mut clock, log) = clock(6);
+    clock.set_charge_level(100);
+    clock.set_battery_state(State::Charging);
+    clock.wake_up();
+    let mut state = PinState::default();
+    for _ in 0..13 {
+        clock.tick();
+    }
+    state.replay(&log);
+    // Full stays full
+    assert_eq!(lit(&state).map(|(_, segments, _)| segments), Some(Glyph::bar(BAR_LEVELS).segments()));
+}
+
+#[test]
+fn time_rolls_over_at_midnight() {
+    let (mut clock, _log) = clock(4);
+    clock.set_time(23, 59);
+    for _ in 0..(59 * 4) {
+        clock.tick();
+    }
+    assert_eq!(clock.get_time(), (23, 59));
+    for _ in 0..4 {
+        clock.tick();
+    }
+    assert_eq!(clock.get_time(), (0, 0));
+}
+
+#[test]
+fn date_rolls_over_at_midnight() {
+    let (mut clock, _log) = clock(1);
+    clock.set_date(Date::new(2024, 2, 28).unwrap());
+    clock.set_time(23, 59);
+    for _ in 0..60 {
+        clock.tick();
+    }
+    assert_eq!(clock.get_date(), Date::new(2024, 2, 29).unwrap());
+    for _ in 0..(24 * 60 * 60) {
+        clock.tick();
+    }
+    assert_eq!(clock.get_date(), Date::new(2024, 3, 1).unwrap());
+    assert_eq!(clock.get_time(), (0, 0));
+}
+
+#[test]
+fn date_shows_day_then_month() {
+    let (mut clock, log) = clock(2);
+    clock.set_date(Date::new(2024, 11, 5).unwrap());
+    clock.show_date();
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..5 {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state));
+    }
+
+    assert_eq!(shown[0], Some((1, digit(5), true)));
+    assert_eq!(shown[1], Some((0, digit(0), false)));
+    assert_eq!(shown[2], Some((1, digit(1), true)));
+    assert_eq!(shown[3], Some((0, digit(1), false)));
+}
+
+#[test]
+fn display_duration_scales_the_sequence() {
+    let (mut clock, log) = clock(4);
+    clock.set_display_duration(8000);
+    clock.set_time(13, 37);
+    clock.show_time_and_charge();
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..32 {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state).map(|(_, segments, _)| segments));
+        assert!(clock.is_display_on());
+    }
+    assert_eq!(shown[7], Some(digit(1)));
+    assert_eq!(shown[8], Some(digit(7)));
+    assert_eq!(shown[16], Some(Glyph::bar(50 / 16).segments()));
+    assert_eq!(shown[24], None);
+    clock.tick();
+    assert!(!clock.is_display_on());
+}
+
+#[test]
+fn plays_a_custom_sequence() {
+    let (mut clock, log) = clock(10);
+    clock.set_date(Date::new(2024, 11, 5).unwrap());
+    clock.set_time(9, 5);
+    clock.play(Sequence::parse(b"M:2,D2:1,X:1,N:1").unwrap());
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..5 {
+        clock.tick();
+        assert!(clock.is_display_on());
+        state.replay(&log);
+        shown.push(lit(&state));
+    }
+    clock.tick();
+    assert!(!clock.is_display_on());
+
+    // The blank frame doesn't multiplex, the month picks up on the first tube
+    assert_eq!(shown, vec![
+        Some((1, digit(5), false)), Some((0, digit(0), false)),
+        Some((1, digit(5), true)),
+        None,
+        Some((0, digit(1), false)),
+    ]);
+}
+
+#[test]
+fn wake_up_plays_the_wake_sequence() {
+    let (mut clock, log) = clock(10);
+    clock.set_time(9, 5);
+    clock.set_wake_sequence(Sequence::parse(b"S:1").unwrap());
+    clock.wake_up();
+    let mut state = PinState::default();
+    clock.tick();
+    state.replay(&log);
+    assert_eq!(lit(&state), Some((1, digit(0), false)));
+
+    clock.set_wake_sequence(Sequence::new());
+    clock.wake_up();
+    clock.tick();
+    state.replay(&log);
+    // The hours with the dot on the first tube
+    assert_eq!(lit(&state), Some((0, digit(0), true)));
+}
+
+/// Replays the log, asserting every anode switches with all segments and the point low.
+fn assert_no_ghosting(state: &mut PinState, log: &Log) {
+    for event in log.borrow_mut().drain(..) {
+        if matches!(event.0, PinId::Nixie1 | PinId::Nixie2) {
+            assert_eq!((state.segments, state.dot), (0, false), "{:?} switched with segments high", event);
+        }
+        state.apply(event);
+    }
+}
+
+#[test]
+fn anodes_switch_with_the_segments_low() {
+    for blanking in [false, true] {
+        let (mut clock, log) = clock(8);
+        clock.set_blanking(blanking);
+        clock.set_time(18, 58);
+        clock.play(Sequence::parse(b"H1:2,M2:2:3,B:2,X:1,U:0").unwrap());
+        clock.show_message(Text::from_bytes(b"8.8.").unwrap());
+        let mut state = PinState::default();
+        for tick in 0..40 {
+            clock.tick();
+            if blanking {
+                clock.light();
+            }
+            if tick % 3 == 0 {
+                clock.blank();
+            }
+            assert_no_ghosting(&mut state, &log);
+        }
+    }
+}
+
+#[test]
+fn blanking_keeps_the_tubes_dark_until_lit() {
+    let (mut clock, log) = clock(4);
+    clock.set_blanking(true);
+    clock.set_time(13, 37);
+    clock.show_time();
+
+    let mut state = PinState::default();
+    for _ in 0..3 {
+        clock.tick();
+        state.replay(&log);
+        assert_eq!(lit(&state), None);
+        assert_eq!(state.segments, 0);
+        assert!(state.enable);
+        clock.light();
+        state.replay(&log);
+        assert!(lit(&state).is_some());
+    }
+
+    // Once the sequence is over the timer can't light the display again
+    for _ in 0..16 {
+        clock.tick();
+    }
+    assert!(!clock.is_display_on());
+    clock.light();
+    state.replay(&log);
+    assert_eq!(lit(&state), None);
+}
+
+#[test]
+fn dimmed_frames_shorten_the_on_time() {
+    let (mut clock, log) = clock(100);
+    clock.set_time(8, 8);
+    clock.play(Sequence::parse(b"H:10:2").unwrap());
+
+    let mut state = PinState::default();
+    for _ in 0..4 {
+        clock.tick();
+        state.replay(&log);
+        assert!(lit(&state).is_some());
+        assert_eq!(clock.on_time(), 2);
+        // The compare interrupt darkens the tube for the rest of the period
+        clock.blank();
+        state.replay(&log);
+        assert_eq!(lit(&state), None);
+        assert!(state.enable);
+    }
+}
+
+#[test]
+fn brightness_scales_frames_and_drops_on_a_low_battery() {
+    let (mut clock, _log) = clock(100);
+    clock.set_brightness(4, 2);
+    clock.play(Sequence::parse(b"H:10,M:10:2").unwrap());
+    clock.tick();
+    assert_eq!(clock.on_time(), 4);
+
+    clock.set_battery_state(State::Low);
+    clock.tick();
+    assert_eq!(clock.on_time(), 2);
+    // The second frame, rounded up to the dimmest level rather than off
+    for _ in 0..100 {
+        clock.tick();
+    }
+    assert_eq!(clock.on_time(), 1);
+
+    clock.set_battery_state(State::Ok);
+    clock.set_brightness(BRIGHTNESS_LEVELS, 2);
+    clock.tick();
+    assert_eq!(clock.on_time(), 2);
+}
+
+#[test]
+fn message_scrolls_across_the_tubes() {
+    let (mut clock, log) = clock(4);
+    clock.set_scroll_step(500);
+    clock.show_message(Text::from_bytes(b"Hi.").unwrap());
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..6 {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state));
+    }
+    assert_eq!(shown, vec![
+        Some((1, glyph(b'H'), false)), Some((0, 0, false)),
+        Some((1, glyph(b'i'), true)), Some((0, glyph(b'H'), false)),
+        Some((1, 0, false)), Some((0, glyph(b'i'), true)),
+    ]);
+    clock.tick();
+    assert!(!clock.is_display_on());
+}
+
+#[test]
+fn time_text_in_a_sequence() {
+    let (mut clock, log) = clock(4);
+    clock.set_scroll_step(500);
+    clock.set_time(13, 37);
+    clock.play(Sequence::parse(b"T:0").unwrap());
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..10 {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state));
+    }
+    // Two ticks a step, one for each tube
+    assert_eq!(shown[2], Some((1, glyph(b'3'), true)));
+    assert_eq!(shown[3], Some((0, glyph(b'1'), false)));
+    assert_eq!(shown[6], Some((1, glyph(b'7'), false)));
+    assert_eq!(shown[7], Some((0, glyph(b'3'), false)));
+    clock.tick();
+    assert!(!clock.is_display_on());
+}
+
+#[test]
+fn exercise_cycles_every_digit_for_its_duration() {
+    let (mut clock, log) = clock(10);
+    clock.set_exercise_duration(4);
+    clock.exercise();
+
+    let mut state = PinState::default();
+    let mut digits = [0u16; 2];
+    let mut segments = [0u8; 2];
+    for _ in 0..40 {
+        clock.tick();
+        state.replay(&log);
+        let (tube, mask, dot) = lit(&state).unwrap();
+        digits[tube as usize] |= 1 << (0..10).find(|&d| digit(d) == mask).unwrap();
+        segments[tube as usize] |= mask | (dot as u8) << 7;
+    }
+    // Every digit on both tubes, and with them every segment and the point
+    assert_eq!(digits, [0x3FF; 2]);
+    assert_eq!(segments, [0xFF; 2]);
+    clock.tick();
+    assert!(!clock.is_display_on());
+}
+
+#[test]
+fn wear_counts_what_was_lit() {
+    let (mut clock, log) = clock(1);
+    let mut wear = Wear::default();
+    wear.seconds[11] = 100;
+    clock.set_wear(wear);
+    clock.set_time(13, 37);
+    clock.show_time();
+
+    let mut state = PinState::default();
+    while clock.is_display_on() {
+        clock.tick();
+        state.replay(&log);
+        if let Some((tube, segments, dot)) = lit(&state) {
+            for (segment, seconds) in wear.seconds[8 * tube as usize..][..8].iter_mut().enumerate() {
+                *seconds += ((segments | (dot as u8) << 7) >> segment & 1) as u32;
+            }
+        }
+    }
+    assert_eq!(clock.wear(), wear);
+    assert!(wear.seconds[11] > 100);
+}
+
+#[test]
+fn self_test_lights_one_segment_at_a_time_then_every_glyph() {
+    let (mut clock, log) = clock(2 * self_test::STEPS_PER_SECOND);
+    clock.self_test();
+
+    let mut state = PinState::default();
+    let mut seen = vec![[0u8; 2]; self_test::STEPS as usize];
+    let mut lit_in_hv_off = false;
+    while clock.is_display_on() {
+        clock.tick();
+        state.replay(&log);
+        let Some(step) = clock.self_test_step() else { continue };
+        match lit(&state) {
+            Some((tube, segments, dot)) => seen[step as usize][tube as usize] |= segments | (dot as u8) << 7,
+            None => continue
+        }
+        lit_in_hv_off |= Step::at(step) == Some(Step::Hv(false));
+    }
+    for segment in 0..8 {
+        assert_eq!(seen[segment], [1 << segment, 0]);
+        assert_eq!(seen[8 + segment], [0, 1 << segment]);
+    }
+    assert_eq!(seen[16 + (b'0' - b'!') as usize], [glyph(b'0'); 2]);
+    assert_eq!(seen[16 + (b'A' - b'!') as usize], [glyph(b'A'); 2]);
+    assert!(!lit_in_hv_off);
+    assert_eq!(seen[self_test::STEPS as usize - 1], [0x7F; 2]);
+    assert_eq!(clock.self_test_step(), None);
+}
+
+type Lit = Option<(u8, u8, bool)>;
+
+/// What the tubes show and their on time for the first ticks of `sequence` at 08:10, ten ticks a second.
+fn play_at_ten_past_eight(sequence: &[u8], ticks: usize) -> Vec<(Lit, u8)> {
+    let (mut clock, log) = clock(10);
+    clock.set_time(8, 10);
+    clock.play(Sequence::parse(sequence).unwrap());
+    let mut state = PinState::default();
+    (0..ticks).map(|_| {
+        clock.tick();
+        state.replay(&log);
+        (lit(&state), clock.on_time())
+    }).collect()
+}
+
+#[test]
+fn transitions_take_half_a_second() {
+    // The hours 08 give way to the minutes 10 on the second tick, the tubes take turns starting with the second
+    let wipe = |old: u8, new: u8, segments: u8| digit(new) & ((1 << segments) - 1) | digit(old) & !((1 << segments) - 1);
+    let shown = |tubes: &[(u8, u8)]| tubes.iter().map(|&(tube, segments)| Some((tube, segments, false))).collect::<Vec<_>>();
+    let lit_only = |frames: Vec<(Lit, u8)>| frames.into_iter().map(|(lit, _)| lit).collect::<Vec<_>>();
+
+    assert_eq!(lit_only(play_at_ten_past_eight(b"H:1,M:10", 3)), shown(&[(1, digit(8)), (0, digit(1)), (1, digit(0))]));
+    assert_eq!(lit_only(play_at_ten_past_eight(b"WIPE/H:1,M:10", 6)), shown(&[
+        (1, digit(8)), (0, wipe(0, 1, 1)), (1, wipe(8, 0, 3)), (0, wipe(0, 1, 4)), (1, wipe(8, 0, 6)), (0, digit(1))
+    ]));
+    // Both tubes roll, the first lands halfway
+    assert_eq!(lit_only(play_at_ten_past_eight(b"ROLL/H:1,M:10", 7)), shown(&[
+        (1, digit(8)), (0, digit(2)), (1, digit(4)), (0, digit(1)), (1, digit(8)), (0, digit(1)), (1, digit(0))
+    ]));
+    // Each tube shows the old and the new frame in turn, one dimming as the other brightens
+    let fade = play_at_ten_past_eight(b"FADE/H:1,M:10", 6);
+    assert_eq!(fade.iter().map(|&(_, on_time)| on_time).collect::<Vec<_>>(), vec![BRIGHTNESS_LEVELS, 6, 4, 4, 6, BRIGHTNESS_LEVELS]);
+    assert_eq!(lit_only(fade), shown(&[(1, digit(8)), (0, digit(0)), (1, digit(8)), (0, digit(1)), (1, digit(0)), (0, digit(1))]));
+}
+
+#[test]
+fn twelve_hour_clock_lights_the_point_of_the_hours_in_the_afternoon() {
+    let (mut clock, log) = clock(4);
+    clock.set_twelve_hour(Some(AmPm::Dot));
+    let mut state = PinState::default();
+    for (hours, shown) in [(13, Some((0, digit(0), true))), (9, Some((0, digit(0), false))), (0, Some((0, digit(1), false)))] {
+        clock.set_time(hours, 37);
+        clock.show_time_and_charge();
+        clock.tick();
+        clock.tick();
+        state.replay(&log);
+        assert_eq!(lit(&state), shown, "{}:37", hours);
+    }
+}
+
+#[test]
+fn twelve_hour_clock_lights_the_point_even_if_the_hours_frame_has_none() {
+    let (mut clock, log) = clock(4);
+    clock.set_twelve_hour(Some(AmPm::Dot));
+    let mut state = PinState::default();
+    for (hours, shown) in [(13, Some((0, digit(0), true))), (9, Some((0, digit(0), false)))] {
+        clock.set_time(hours, 37);
+        clock.play(Sequence::parse(b"H:10").unwrap());
+        clock.tick();
+        clock.tick();
+        state.replay(&log);
+        assert_eq!(lit(&state), shown, "{}:37", hours);
+    }
+}
+
+#[test]
+fn twelve_hour_clock_shows_am_or_pm_before_the_hours() {
+    let (mut clock, log) = clock(10);
+    clock.set_twelve_hour(Some(AmPm::Letter));
+    clock.set_time(13, 37);
+    clock.show_time_and_charge();
+
+    let mut state = PinState::default();
+    let mut shown = Vec::new();
+    for _ in 0..6 {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state));
+    }
+    assert_eq!(shown, vec![
+        Some((1, glyph(b'M'), false)), Some((0, glyph(b'P'), true)), Some((1, glyph(b'M'), false)), Some((0, glyph(b'P'), true)),
+        Some((1, digit(1), false)), Some((0, digit(0), true)),
+    ]);
+
+    // The scrolling time ends with the letter
+    clock.set_scroll_step(500);
+    clock.play(Sequence::parse(b"T:0").unwrap());
+    let mut shown = Vec::new();
+    while clock.is_display_on() {
+        clock.tick();
+        state.replay(&log);
+        shown.push(lit(&state));
+    }
+    // 01.37P scrolls by in six steps of five ticks
+    assert_eq!(shown.len(), 6 * 5 + 1);
+    assert!(shown.contains(&Some((1, glyph(b'1'), true))));
+    assert!(shown.contains(&Some((1, glyph(b'P'), false))));
+}
@@ -0,0 +1,55 @@
+use nixiewatch_core::power::*;
+
+const IDLE: Activity = Activity { display_on: false, usb_connected: false, charger_busy: false };
+const DISPLAY_ON: Activity = Activity { display_on: true, ..IDLE };
+
+#[test]
+fn stops_once_idle() {
+    let mut power = PowerManager::new();
+    assert_eq!(power.update(DISPLAY_ON), None);
+    assert!(!power.stop());
+
+    assert_eq!(power.update(IDLE), Some(Mode::Idle));
+    assert_eq!(power.update(IDLE), None);
+    assert!(power.stop());
+    assert_eq!(power.mode(), Mode::Stopped);
+    // Only once per STOP
+    assert!(!power.stop());
+
+    power.wake();
+    assert_eq!(power.mode(), Mode::Idle);
+    assert!(power.stop());
+    assert_eq!(power.stops(), 2);
+}
+
+#[test]
+fn waking_up_starts_the_timer() {
+    let mut power = PowerManager::new();
+    power.update(IDLE);
+    power.stop();
+    power.wake();
+    // Motion lit the display
+    assert_eq!(power.update(DISPLAY_ON), Some(Mode::Running));
+    assert!(!power.stop());
+    // An RTC alarm only takes one tick
+    assert_eq!(power.update(IDLE), Some(Mode::Idle));
+    assert!(power.stop());
+}
+
+#[test]
+fn usb_and_charger_keep_it_running() {
+    let mut power = PowerManager::new();
+    assert_eq!(power.update(Activity { charger_busy: true, ..IDLE }), None);
+    assert_eq!(power.update(Activity { usb_connected: true, ..IDLE }), None);
+    assert_eq!(power.mode(), Mode::Running);
+}
+
+#[test]
+fn usb_connecting_between_ticks_blocks_stop() {
+    let mut power = PowerManager::new();
+    power.update(IDLE);
+    power.set_usb_connected(true);
+    assert!(!power.stop());
+    power.set_usb_connected(false);
+    assert!(power.stop());
+}
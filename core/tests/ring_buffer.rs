@@ -0,0 +1,56 @@
+use nixiewatch_core::ring_buffer::RingBuffer;
+
+/// Empties the buffer the way the USB code does, in pieces of at most `packet` bytes.
+fn drain<const N: usize>(buffer: &mut RingBuffer<N>, packet: usize) -> Vec<u8> {
+    let mut sent = Vec::new();
+    while !buffer.is_empty() {
+        let front = buffer.front();
+        let count = front.len().min(packet);
+        sent.extend_from_slice(&front[..count]);
+        buffer.consume(count);
+    }
+    sent
+}
+
+#[test]
+fn bytes_come_out_in_order() {
+    let mut buffer = RingBuffer::<8>::new();
+    assert_eq!(buffer.push(b"hello"), 5);
+    assert_eq!(buffer.len(), 5);
+    assert_eq!(drain(&mut buffer, 2), b"hello");
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn wraps_around() {
+    let mut buffer = RingBuffer::<8>::new();
+    buffer.push(b"abcdef");
+    buffer.consume(4);
+    buffer.push(b"ghijk");
+    // the part up to the end of the storage first
+    assert_eq!(buffer.front(), b"efgh");
+    assert_eq!(drain(&mut buffer, 64), b"efghijk");
+}
+
+#[test]
+fn overflow_is_cut_short_and_counted() {
+    let mut buffer = RingBuffer::<8>::new();
+    assert_eq!(buffer.push(b"0123456789"), 8);
+    assert_eq!(buffer.push(b"x"), 0);
+    assert_eq!(buffer.dropped(), 3);
+    assert_eq!(drain(&mut buffer, 64), b"01234567");
+    // the count stays for the status report
+    buffer.push(b"ok");
+    assert_eq!(buffer.dropped(), 3);
+}
+
+#[test]
+fn partial_sends() {
+    let mut buffer = RingBuffer::<8>::new();
+    buffer.push(b"abc");
+    // nothing taken while the port is busy
+    buffer.consume(0);
+    assert_eq!(buffer.front(), b"abc");
+    buffer.consume(10);
+    assert!(buffer.is_empty());
+}
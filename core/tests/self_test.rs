@@ -0,0 +1,60 @@
+use nixiewatch_core::command::Output;
+use nixiewatch_core::glyph::Glyph;
+use nixiewatch_core::power::MOTION_CHECKS_PER_SECOND;
+use nixiewatch_core::self_test::{report, Shake, Step, SHAKE_MS, STEPS};
+
+struct Reply(Vec<u8>);
+
+impl Output for Reply {
+    fn write(&mut self, data: &[u8]) {
+        self.0.extend_from_slice(data);
+    }
+}
+
+fn reported(index: Option<u32>) -> String {
+    let mut reply = Reply(Vec::new());
+    report(&mut reply, index);
+    String::from_utf8(reply.0).unwrap()
+}
+
+#[test]
+fn steps_go_segments_glyphs_then_high_voltage() {
+    assert_eq!(Step::at(0), Some(Step::Segment(0)));
+    assert_eq!(Step::at(15), Some(Step::Segment(15)));
+    assert_eq!(Step::at(16), Some(Step::Glyph(b'!')));
+    assert_eq!(Step::at(STEPS - 3), Some(Step::Glyph(b'~')));
+    assert_eq!(Step::at(STEPS - 2), Some(Step::Hv(false)));
+    assert_eq!(Step::at(STEPS - 1), Some(Step::Hv(true)));
+    assert_eq!(Step::at(STEPS), None);
+}
+
+#[test]
+fn segments_light_on_their_own_tube() {
+    assert_eq!(Step::Segment(0).glyphs(), [Glyph::from_segments(0x01), Glyph::BLANK]);
+    assert_eq!(Step::Segment(15).glyphs(), [Glyph::BLANK, Glyph::from_segments(0x80)]);
+    assert_eq!(Step::Glyph(b'A').glyphs(), [Glyph::from_ascii(b'A'); 2]);
+}
+
+#[test]
+fn report_lines() {
+    assert_eq!(reported(Some(0)), "SELFTEST 1\n");
+    assert_eq!(reported(Some(STEPS - 1)), "SELFTEST 112\n");
+    assert_eq!(reported(Some(STEPS)), "SELFTEST done\n");
+    assert_eq!(reported(None), "SELFTEST done\n");
+}
+
+#[test]
+fn shaking_needs_movement_in_a_row() {
+    let checks = SHAKE_MS * MOTION_CHECKS_PER_SECOND / 1000;
+    let mut shake = Shake::new();
+    for _ in 1..checks {
+        assert!(!shake.update(true));
+    }
+    assert!(!shake.update(false));
+    for _ in 1..checks {
+        assert!(!shake.update(true));
+    }
+    assert!(shake.update(true));
+    // Starts over after
+    assert!(!shake.update(true));
+}
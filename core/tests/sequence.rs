@@ -0,0 +1,52 @@
+use nixiewatch_core::nixie_segment::{DotStatus, BRIGHTNESS_LEVELS};
+use nixiewatch_core::sequence::{Content, Frame, Sequence, Transition, FRAME_SIZE, MAX_FRAMES};
+
+#[test]
+fn parses_frames() {
+    let sequence = Sequence::parse(b"H1:10,m2:5,B:20:3,X:0").unwrap();
+    assert_eq!(sequence.frames(), &[
+        Frame::new(Content::Hours, DotStatus::Digit1, 10),
+        Frame::new(Content::Minutes, DotStatus::Digit2, 5),
+        Frame { content: Content::Charge, dot: DotStatus::Off, duration: 20, brightness: 3 },
+        Frame::new(Content::Blank, DotStatus::Off, 0),
+    ]);
+    assert_eq!(sequence.frames()[0].brightness, BRIGHTNESS_LEVELS);
+    assert!(Sequence::parse(b"default").unwrap().is_empty());
+    assert_eq!(sequence.transition, Transition::Cut);
+}
+
+#[test]
+fn parses_a_transition() {
+    let sequence = Sequence::parse(b"roll/H1:10,M2:10").unwrap();
+    assert_eq!(sequence.transition, Transition::Roll);
+    assert_eq!(sequence.frames(), Sequence::parse(b"H1:10,M2:10").unwrap().frames());
+    for text in [&b"SPIN/H:10"[..], b"ROLL/", b"/H:10", b"FADE/DEFAULT", b"FADE/WIPE/H:10"] {
+        assert_eq!(Sequence::parse(text), None, "{}", String::from_utf8_lossy(text));
+    }
+}
+
+#[test]
+fn rejects_bad_frames() {
+    for text in [&b""[..], b"H", b"H:", b"Q:10", b"H3:10", b"H:256", b"H:10:0", b"H:10:9", b"H:10:4:1", b"H:10,,M:10"] {
+        assert_eq!(Sequence::parse(text), None, "{}", String::from_utf8_lossy(text));
+    }
+    let too_long = ["S:1"; MAX_FRAMES + 1].join(",");
+    assert_eq!(Sequence::parse(too_long.as_bytes()), None);
+}
+
+#[test]
+fn bytes_round_trip() {
+    let sequence = Sequence::parse(b"D2:10,N2:10,C:30:5,E1:1,S:0,T:0,U:20").unwrap();
+    let mut data = [0u8; MAX_FRAMES * FRAME_SIZE];
+    let len = sequence.to_bytes(&mut data);
+    assert_eq!(len, 7 * FRAME_SIZE);
+    assert_eq!(Sequence::from_bytes(&data[..len]), Some(sequence));
+    assert_eq!(Sequence::from_bytes(&[]), Some(Sequence::new()));
+    let sequence = Sequence::parse(b"WIPE/H2:10,M1:10").unwrap();
+    let len = sequence.to_bytes(&mut data);
+    assert_eq!(Sequence::from_bytes(&data[..len]), Some(sequence));
+    // Partial frame, unknown content and a brightness out of range
+    assert_eq!(Sequence::from_bytes(&data[..4]), None);
+    assert_eq!(Sequence::from_bytes(&[0x0F, 10, 8]), None);
+    assert_eq!(Sequence::from_bytes(&[0x00, 10, 0]), None);
+}
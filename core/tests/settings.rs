@@ -0,0 +1,232 @@
+mod common;
+
+use common::flash::SimulatedFlash;
+use nixiewatch_core::calendar::Time;
+use nixiewatch_core::sequence::Sequence;
+use nixiewatch_core::settings::{crc16, Flash, Key, Settings, SettingsError, SettingsStore, Value, SCHEMA_VERSION};
+
+const PAGE: usize = 1024;
+
+fn read_u32(store: &SettingsStore<SimulatedFlash>, key: u8) -> Option<u32> {
+    let mut value = [0u8; 4];
+    match store.read(key, &mut value) {
+        Some(4) => Some(u32::from_le_bytes(value)),
+        Some(len) => panic!("unexpected length {}", len),
+        None => None,
+    }
+}
+
+#[test]
+fn crc16_check_value() {
+    assert_eq!(crc16(0xFFFF, b"123456789"), 0x29B1);
+}
+
+#[test]
+fn fresh_page_is_formatted_and_empty() {
+    let store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    assert_eq!(read_u32(&store, 1), None);
+    let flash = store.release();
+    assert_eq!(flash.erase_count, 1);
+    assert_eq!(&flash.data[0..2], &0x4E57u16.to_le_bytes());
+    assert_eq!(&flash.data[2..4], &SCHEMA_VERSION.to_le_bytes());
+}
+
+#[test]
+fn values_survive_reopening() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    store.write(1, &42u32.to_le_bytes()).unwrap();
+    store.write(2, b"odd").unwrap();
+    store.write(1, &43u32.to_le_bytes()).unwrap();
+
+    let store = SettingsStore::new(store.release());
+    assert_eq!(read_u32(&store, 1), Some(43));
+    let mut value = [0u8; 8];
+    assert_eq!(store.read(2, &mut value), Some(3));
+    assert_eq!(&value[..3], b"odd");
+    assert_eq!(store.release().erase_count, 1);
+}
+
+#[test]
+fn unchanged_value_is_not_rewritten() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    store.write(1, &42u32.to_le_bytes()).unwrap();
+    let free = store.free_space();
+    store.write(1, &42u32.to_le_bytes()).unwrap();
+    assert_eq!(store.free_space(), free);
+}
+
+#[test]
+fn invalid_writes_are_rejected() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    assert_eq!(store.write(0, &[1]), Err(SettingsError::Invalid));
+    assert_eq!(store.write(0xFF, &[1]), Err(SettingsError::Invalid));
+    assert_eq!(store.write(1, &[0u8; 65]), Err(SettingsError::Invalid));
+}
+
+#[test]
+fn wear_levelling_only_erases_when_full() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    store.write(2, &7u32.to_le_bytes()).unwrap();
+    // a 4 byte record takes 8 bytes, so 127 fit after the header
+    for i in 0..1000u32 {
+        store.write(1, &i.to_le_bytes()).unwrap();
+    }
+    assert_eq!(read_u32(&store, 1), Some(999));
+    assert_eq!(read_u32(&store, 2), Some(7));
+
+    let flash = store.release();
+    assert!(flash.erase_count >= 8 && flash.erase_count <= 10, "{} erases", flash.erase_count);
+    let store = SettingsStore::new(flash);
+    assert_eq!(read_u32(&store, 1), Some(999));
+    assert_eq!(read_u32(&store, 2), Some(7));
+}
+
+#[test]
+fn torn_write_keeps_previous_value() {
+    for budget in 0..4 {
+        let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+        store.write(1, &1u32.to_le_bytes()).unwrap();
+
+        let mut flash = store.release();
+        flash.power_budget = Some(budget);
+        let mut store = SettingsStore::new(flash);
+        assert!(store.write(1, &2u32.to_le_bytes()).is_err());
+
+        // power comes back
+        let mut flash = store.release();
+        flash.power_budget = None;
+        let mut store = SettingsStore::new(flash);
+        assert_eq!(read_u32(&store, 1), Some(1), "budget {}", budget);
+
+        // and the store carries on after the broken record
+        store.write(1, &3u32.to_le_bytes()).unwrap();
+        let store = SettingsStore::new(store.release());
+        assert_eq!(read_u32(&store, 1), Some(3), "budget {}", budget);
+    }
+}
+
+#[test]
+fn corrupted_record_is_skipped() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    store.write(1, &1u32.to_le_bytes()).unwrap();
+    store.write(1, &2u32.to_le_bytes()).unwrap();
+    let mut flash = store.release();
+    // clear a bit in the value of the second record, which programming can do
+    flash.data[4 + 8 + 2] &= 0xFD;
+    let store = SettingsStore::new(flash);
+    assert_eq!(read_u32(&store, 1), Some(1));
+}
+
+#[test]
+fn newer_schema_is_formatted() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    store.write(1, &1u32.to_le_bytes()).unwrap();
+    let mut flash = store.release();
+    flash.data[2..4].copy_from_slice(&(SCHEMA_VERSION + 1).to_le_bytes());
+    let store = SettingsStore::new(flash);
+    assert_eq!(read_u32(&store, 1), None);
+}
+
+#[test]
+fn schema_with_the_old_key_numbers_is_formatted() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    store.write(1, &1u32.to_le_bytes()).unwrap();
+    let mut flash = store.release();
+    flash.data[2..4].copy_from_slice(&1u16.to_le_bytes());
+    let store = SettingsStore::new(flash);
+    assert_eq!(read_u32(&store, 1), None);
+    let flash = store.release();
+    assert_eq!(&flash.data[2..4], &SCHEMA_VERSION.to_le_bytes());
+}
+
+#[test]
+fn garbage_page_is_formatted() {
+    let mut flash = SimulatedFlash::new(PAGE);
+    flash.data.iter_mut().for_each(|b| *b = 0x00);
+    let mut store = SettingsStore::new(flash);
+    store.write(1, &5u32.to_le_bytes()).unwrap();
+    assert_eq!(read_u32(&store, 1), Some(5));
+}
+
+#[test]
+fn settings_default_when_missing() {
+    let store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    assert_eq!(Settings::load(&store), Settings::default());
+    assert_eq!(Settings::default().start_time, Time::new(13, 37, 0).unwrap());
+}
+
+#[test]
+fn settings_round_trip() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    let settings = Settings {
+        start_time: Time::new(8, 0, 0).unwrap(),
+        multiplex_rate: 250,
+        display_duration: 6000,
+        battery_empty_mv: 3400,
+        battery_full_mv: 4150,
+        battery_low: 15,
+        battery_critical: 5,
+        trim_ppm: -42,
+        battery_capacity_mah: 220,
+        current_sleep_ua: 45,
+        current_run_ua: 3500,
+        current_display_ua: 5500,
+        current_hv_ua: 40000,
+        sequence: Sequence::parse(b"H1:10,M2:10,B:5:3").unwrap(),
+        scroll_step_ms: 150,
+        brightness: 6,
+        brightness_low: 3,
+        blanking_us: 250,
+        exercise_s: 120,
+        exercise_on_charge: false,
+        clock_12h: 2,
+    };
+    settings.save(&mut store).unwrap();
+    let store = SettingsStore::new(store.release());
+    assert_eq!(Settings::load(&store), settings);
+}
+
+#[test]
+fn out_of_range_setting_falls_back_to_default() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    // multiplex rate
+    store.write(2, &5u16.to_le_bytes()).unwrap();
+    // battery low as a u16 instead of a u8
+    store.write(6, &5u16.to_le_bytes()).unwrap();
+    assert_eq!(Settings::load(&store), Settings::default());
+}
+
+#[test]
+fn set_validates_the_value() {
+    let mut settings = Settings::default();
+    assert!(settings.set(Key::MultiplexRate, Value::Number(400)));
+    assert_eq!(settings.get(Key::MultiplexRate), Value::Number(400));
+    assert!(!settings.set(Key::MultiplexRate, Value::Number(20)));
+    assert!(!settings.set(Key::TrimPpm, Value::Number(1000)));
+    assert!(!settings.set(Key::StartTime, Value::Number(0)));
+    assert!(!settings.set(Key::BatteryLow, Value::Time(Time::default())));
+    assert!(!settings.set(Key::Brightness, Value::Number(0)));
+    assert!(!settings.set(Key::BrightnessLow, Value::Number(9)));
+    assert!(!settings.set(Key::ExerciseDuration, Value::Number(0)));
+    assert!(!settings.set(Key::ExerciseOnCharge, Value::Number(2)));
+    assert!(!settings.set(Key::Clock12h, Value::Number(3)));
+    assert_eq!(settings.multiplex_rate, 400);
+}
+
+#[test]
+fn keys_by_name() {
+    for key in Key::ALL {
+        assert_eq!(Key::from_name(key.name().as_bytes()), Some(key));
+    }
+    assert_eq!(Key::from_name(b"battery_low"), Some(Key::BatteryLow));
+    assert_eq!(Key::from_name(b"BATTERY"), None);
+}
+
+#[test]
+fn simulator_rejects_overwrites() {
+    let mut flash = SimulatedFlash::new(8);
+    flash.program(0, &[0x12, 0x34]).unwrap();
+    flash.program(0, &[0x00, 0x00]).unwrap();
+    let result = std::panic::catch_unwind(move || flash.program(2, &[0x12, 0x34]).and_then(|_| flash.program(2, &[0x56, 0x78])));
+    assert!(result.is_err());
+}
@@ -0,0 +1,36 @@
+use nixiewatch_core::glyph::Glyph;
+use nixiewatch_core::text::{Text, MAX_TEXT_LEN};
+
+fn glyph(c: u8) -> Glyph {
+    Glyph::from_ascii(c)
+}
+
+#[test]
+fn points_join_the_character_before() {
+    let text = Text::from_bytes(b"13.37").unwrap();
+    assert_eq!(text.glyphs(), &[glyph(b'1'), glyph(b'3').with_dot(), glyph(b'3'), glyph(b'7')]);
+    // A point at the start or after another one takes a tube of its own
+    let text = Text::from_bytes(b".1..").unwrap();
+    assert_eq!(text.glyphs(), &[glyph(b'.'), glyph(b'1').with_dot(), glyph(b'.')]);
+}
+
+#[test]
+fn scroll_enters_on_the_right() {
+    let text = Text::from_bytes(b"HI").unwrap();
+    assert_eq!(text.steps(), 3);
+    let h = glyph(b'H');
+    let i = glyph(b'I');
+    assert_eq!(text.window(0), [Glyph::BLANK, h]);
+    assert_eq!(text.window(1), [h, i]);
+    assert_eq!(text.window(2), [i, Glyph::BLANK]);
+    assert_eq!(text.window(3), [Glyph::BLANK; 2]);
+}
+
+#[test]
+fn long_text_is_refused() {
+    assert!(Text::from_bytes(&[b'A'; MAX_TEXT_LEN]).is_some());
+    assert_eq!(Text::from_bytes(&[b'A'; MAX_TEXT_LEN + 1]), None);
+    let mut text = Text::new();
+    assert!(text.push_number(7, 3));
+    assert_eq!(text.glyphs(), &[glyph(b'0'), glyph(b'0'), glyph(b'7')]);
+}
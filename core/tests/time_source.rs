@@ -0,0 +1,107 @@
+mod common;
+
+use common::*;
+use nixiewatch_core::calendar::{Date, DateTime, Time};
+use nixiewatch_core::glyph::Glyph;
+use nixiewatch_core::nixie_segment::NixieClock;
+use nixiewatch_core::time_source::{TickCounter, TimeSource};
+
+fn date_time(year: u16, month: u8, day: u8, hours: u8, minutes: u8, seconds: u8) -> DateTime {
+    DateTime::new(Date::new(year, month, day).unwrap(), Time::new(hours, minutes, seconds).unwrap())
+}
+
+/// Stands in for the hardware RTC: the time only changes when the test says so.
+struct FixedTime(DateTime);
+
+impl TimeSource for FixedTime {
+    fn now(&mut self) -> DateTime {
+        self.0
+    }
+
+    fn set(&mut self, now: DateTime) {
+        self.0 = now;
+    }
+
+    fn set_trim(&mut self, _ppm: i32) {}
+}
+
+#[test]
+fn time_rejects_out_of_range_fields() {
+    assert!(Time::new(23, 59, 59).is_some());
+    assert_eq!(Time::new(24, 0, 0), None);
+    assert_eq!(Time::new(0, 60, 0), None);
+    assert_eq!(Time::new(0, 0, 60), None);
+}
+
+#[test]
+fn next_second_carries_into_the_date() {
+    assert_eq!(date_time(2024, 12, 31, 23, 59, 59).next_second(), date_time(2025, 1, 1, 0, 0, 0));
+    assert_eq!(date_time(2024, 3, 1, 9, 59, 59).next_second(), date_time(2024, 3, 1, 10, 0, 0));
+    assert_eq!(date_time(2024, 3, 1, 9, 5, 7).next_second(), date_time(2024, 3, 1, 9, 5, 8));
+}
+
+#[test]
+fn tick_counter_advances_once_per_second() {
+    let mut counter = TickCounter::new(200);
+    counter.set(date_time(2024, 5, 1, 12, 0, 0));
+    for _ in 0..199 {
+        counter.tick();
+    }
+    assert_eq!(counter.now(), date_time(2024, 5, 1, 12, 0, 0));
+    counter.tick();
+    assert_eq!(counter.now(), date_time(2024, 5, 1, 12, 0, 1));
+}
+
+#[test]
+fn setting_the_tick_counter_restarts_the_second() {
+    let mut counter = TickCounter::new(200);
+    for _ in 0..150 {
+        counter.tick();
+    }
+    counter.set(date_time(2024, 5, 1, 12, 0, 0));
+    for _ in 0..199 {
+        counter.tick();
+    }
+    assert_eq!(counter.now(), date_time(2024, 5, 1, 12, 0, 0));
+    counter.tick();
+    assert_eq!(counter.now(), date_time(2024, 5, 1, 12, 0, 1));
+}
+
+#[test]
+fn clock_shows_the_time_of_its_source() {
+    let (display, log) = display();
+    let mut clock = NixieClock::new(display, FixedTime(date_time(2024, 5, 1, 21, 42, 0)), 2);
+    clock.show_time();
+
+    let mut state = PinState::default();
+    let mut segments = Vec::new();
+    for _ in 0..4 {
+        clock.tick();
+        state.replay(&log);
+        segments.push(Glyph::from_segments(state.segments));
+    }
+    assert_eq!(segments, [Glyph::digit(1), Glyph::digit(2), Glyph::digit(2), Glyph::digit(4)]);
+}
+
+#[test]
+fn set_time_keeps_date_and_clears_seconds() {
+    let (display, _log) = display();
+    let mut clock = NixieClock::new(display, FixedTime(date_time(2024, 5, 1, 21, 42, 17)), 2);
+    clock.set_time(8, 15);
+    clock.set_date(Date::new(2025, 6, 2).unwrap());
+    assert_eq!(clock.now(), date_time(2025, 6, 2, 8, 15, 0));
+
+    // out of range values are ignored
+    clock.set_time(24, 0);
+    assert_eq!(clock.get_time(), (8, 15));
+}
+
+#[test]
+fn clock_does_not_tick_a_hardware_source() {
+    let (display, _log) = display();
+    let mut clock = NixieClock::new(display, FixedTime(date_time(2024, 5, 1, 21, 42, 17)), 2);
+    for _ in 0..10 {
+        clock.tick();
+    }
+    assert_eq!(clock.get_time(), (21, 42));
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::flash::SimulatedFlash;
+use nixiewatch_core::settings::{Settings, SettingsStore};
+use nixiewatch_core::wear::Wear;
+
+const PAGE: usize = 1024;
+
+fn wear() -> Wear {
+    let mut wear = Wear::default();
+    for (i, seconds) in wear.seconds.iter_mut().enumerate() {
+        *seconds = 1000 * i as u32 + 7;
+    }
+    wear
+}
+
+#[test]
+fn zero_when_missing() {
+    let store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    assert_eq!(Wear::load(&store), Wear::default());
+}
+
+#[test]
+fn round_trip() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    wear().save(&mut store).unwrap();
+    let store = SettingsStore::new(store.release());
+    assert_eq!(Wear::load(&store), wear());
+}
+
+#[test]
+fn survives_the_settings_and_compaction() {
+    let mut store = SettingsStore::new(SimulatedFlash::new(PAGE));
+    let mut settings = Settings::default();
+    wear().save(&mut store).unwrap();
+    // Enough changes to fill the page a few times over
+    for i in 0..300 {
+        settings.display_duration = 1000 + i;
+        settings.save(&mut store).unwrap();
+    }
+    assert_eq!(Wear::load(&store), wear());
+    assert_eq!(Settings::load(&store), settings);
+    assert!(store.release().erase_count > 1);
+}
@@ -0,0 +1,21 @@
+//! Command line companion for the watch, see `nixiewatch_host::cli::USAGE`.
+
+use std::io;
+use std::process;
+
+use nixiewatch_host::{cli, port, watch::Watch};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = cli::parse_args(&args).and_then(|args| {
+        let port = port::open(args.port.as_deref()).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        cli::run(&args.command, &mut Watch::new(port), &mut io::stdout())
+    });
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,37 @@
+//! Sets the watch to the time of this PC.
+//!
+//! Usage: nixiewatch-sync [--port PATH] [--utc | --offset +HH:MM]
+
+use std::process;
+use std::time::SystemTime;
+
+use nixiewatch_host::{cli::parse_offset, port, sync, watch::Watch};
+
+const USAGE: &str = "usage: nixiewatch-sync [--port PATH] [--utc | --offset +HH:MM]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let mut path = None;
+    let mut offset = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => path = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--utc" => offset = Some(0),
+            "--offset" => offset = Some(args.next().as_deref().and_then(parse_offset).unwrap_or_else(|| fail(USAGE))),
+            _ => fail(USAGE)
+        }
+    }
+    let offset = offset.unwrap_or_else(|| sync::local_offset(SystemTime::now()));
+
+    let port = port::open(path.as_deref()).unwrap_or_else(|error| fail(&error));
+    let mut watch = Watch::new(port);
+    match sync::sync(&mut watch, offset) {
+        Ok(report) => println!("Watch was {}s off, set with {:.1}ms latency", report.error, report.latency.as_secs_f64() * 1000.0),
+        Err(error) => fail(&error.to_string())
+    }
+}
@@ -0,0 +1,305 @@
+//! The subcommands of `nixiewatch-cli`, written against any `Watch` so they can run on a pseudo terminal in tests.
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nixiewatch_core::self_test::Step;
+use nixiewatch_core::wear::SEGMENTS;
+
+use crate::status::Status;
+use crate::sync;
+use crate::update;
+use crate::watch::{self, Watch};
+
+pub const USAGE: &str = "usage: nixiewatch-cli [--port PATH] COMMAND
+
+commands:
+  sync [--utc | --offset +HH:MM]   set the watch to the time of this PC
+  battery                          show the battery charge and the last charge session
+  energy                           show where the charge went and how long the rest lasts
+  settings                         show all settings
+  settings get NAME                show one setting
+  settings set NAME VALUE          change a setting
+  status                           show time, date, battery and calibration
+  message TEXT...                  scroll a text across the display
+  exercise                         light every segment against cathode poisoning
+  usage                            show how long every segment has been lit
+  selftest                         light every segment and glyph in turn, printing each step
+  diagnostics                      check the connection and the watch
+  update IMAGE.bin                 write new firmware over USB, needs dfu-util";
+
+#[derive(Debug)]
+pub enum Error {
+    Usage,
+    Watch(watch::Error),
+    /// Some of the diagnostics failed
+    Diagnostics(usize),
+    Update(String)
+}
+
+impl From<watch::Error> for Error {
+    fn from(error: watch::Error) -> Self {
+        Error::Watch(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Watch(watch::Error::Io(error))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage => write!(f, "{}", USAGE),
+            Error::Watch(error) => write!(f, "{}", error),
+            Error::Diagnostics(failed) => write!(f, "{} checks failed", failed),
+            Error::Update(error) => write!(f, "update failed: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    /// With the UTC offset in seconds, `None` for the local time zone
+    Sync(Option<i32>),
+    Battery,
+    Energy,
+    Settings,
+    Get(String),
+    Set(String, String),
+    Status,
+    Message(String),
+    Exercise,
+    Usage,
+    SelfTest,
+    Diagnostics,
+    /// With the path of the image
+    Update(String)
+}
+
+/// The options before the command and the command itself.
+#[derive(PartialEq, Debug)]
+pub struct Args {
+    pub port: Option<String>,
+    pub command: Command
+}
+
+/// `+HH:MM` or `-HH:MM` in seconds.
+pub fn parse_offset(text: &str) -> Option<i32> {
+    let (sign, rest) = match text.as_bytes().first()? {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => (1, text)
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses the arguments without the program name.
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Args, Error> {
+    let mut args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+    let mut port = None;
+    if args.first() == Some(&"--port") {
+        if args.len() < 2 {
+            return Err(Error::Usage);
+        }
+        port = Some(args[1].to_string());
+        args.drain(..2);
+    }
+
+    let command = match args.as_slice() {
+        ["sync"] => Command::Sync(None),
+        ["sync", "--utc"] => Command::Sync(Some(0)),
+        ["sync", "--offset", offset] => Command::Sync(Some(parse_offset(offset).ok_or(Error::Usage)?)),
+        ["battery"] => Command::Battery,
+        ["energy"] => Command::Energy,
+        ["settings"] => Command::Settings,
+        ["settings", "get", name] => Command::Get(name.to_string()),
+        ["settings", "set", name, value] => Command::Set(name.to_string(), value.to_string()),
+        ["status"] => Command::Status,
+        ["message", text @ ..] if !text.is_empty() => Command::Message(text.join(" ")),
+        ["exercise"] => Command::Exercise,
+        ["usage"] => Command::Usage,
+        ["selftest"] => Command::SelfTest,
+        ["diagnostics"] => Command::Diagnostics,
+        ["update", image] => Command::Update(image.to_string()),
+        _ => return Err(Error::Usage)
+    };
+    Ok(Args { port, command })
+}
+
+fn print_lines<O: Write>(out: &mut O, lines: &[String]) -> Result<(), Error> {
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// What the tubes show in a step of the self test.
+fn describe(step: Step) -> String {
+    const SEGMENT_NAMES: [&str; SEGMENTS] = ["a", "b", "c", "d", "e", "f", "g", "dp"];
+    match step {
+        Step::Segment(segment) => format!("tube {} segment {}", segment / SEGMENTS + 1, SEGMENT_NAMES[segment % SEGMENTS]),
+        Step::Glyph(c) => format!("glyph {}", c as char),
+        Step::Hv(false) => "high voltage off".to_string(),
+        Step::Hv(true) => "high voltage on, all segments lit".to_string()
+    }
+}
+
+/// Starts the self test and prints every step as the watch gets to it, until it is done.
+fn self_test<T: Read + Write, O: Write>(watch: &mut Watch<T>, out: &mut O) -> Result<(), Error> {
+    watch.command("SELFTEST")?;
+    loop {
+        let line = watch.read_line()?;
+        let step = match line.strip_prefix("SELFTEST ") {
+            Some("done") => return Ok(()),
+            Some(number) => number.parse::<u32>().ok().and_then(|number| Step::at(number.checked_sub(1)?)),
+            None => None
+        };
+        match step {
+            Some(step) => writeln!(out, "{:>3} {}", &line["SELFTEST ".len()..], describe(step))?,
+            None => return Err(watch::Error::Protocol(line).into())
+        }
+    }
+}
+
+/// Runs one command, printing its result to `out`.
+pub fn run<T: Read + Write, O: Write>(command: &Command, watch: &mut Watch<T>, out: &mut O) -> Result<(), Error> {
+    match command {
+        Command::Sync(offset) => {
+            let offset = offset.unwrap_or_else(|| sync::local_offset(SystemTime::now()));
+            let report = sync::sync(watch, offset)?;
+            writeln!(out, "Watch was {}s off, set with {:.1}ms latency", report.error, report.latency.as_secs_f64() * 1000.0)?;
+        }
+        Command::Battery => {
+            print_lines(out, &watch.command("BATT")?)?;
+            print_lines(out, &watch.command("CHARGE")?)?;
+        }
+        Command::Energy => print_lines(out, &watch.command("ENERGY")?)?,
+        Command::Settings => print_lines(out, &watch.command("GET")?)?,
+        Command::Get(name) => print_lines(out, &watch.command(&format!("GET {}", name))?)?,
+        Command::Set(name, value) => {
+            watch.command(&format!("SET {} {}", name, value))?;
+            print_lines(out, &watch.command(&format!("GET {}", name))?)?;
+        }
+        Command::Message(text) => {
+            watch.command(&format!("MSG {}", text))?;
+        }
+        Command::Exercise => {
+            watch.command("EXERCISE")?;
+        }
+        Command::Usage => print_lines(out, &watch.command("USAGE")?)?,
+        Command::SelfTest => self_test(watch, out)?,
+        Command::Status => {
+            let status = Status::read(watch)?;
+            writeln!(out, "Firmware  {}", status.firmware)?;
+            writeln!(out, "Time      {:04}-{:02}-{:02} {:02}:{:02}:{:02}", status.date.year(), status.date.month(), status.date.day(),
+                     status.time.hours(), status.time.minutes(), status.time.seconds())?;
+            let power = match status.power {
+                Some(power) => power.name(),
+                None if status.charged => "charged",
+                None => "charging"
+            };
+            writeln!(out, "Battery   {}% ({}mV), {}", status.battery, status.battery_mv, power)?;
+            writeln!(out, "Uptime    {}h {}m", status.uptime / 3600, status.uptime / 60 % 60)?;
+            writeln!(out, "Wakes     {}", status.wakes)?;
+            match (status.used_uah, status.runtime_hours) {
+                (Some(used), Some(hours)) => writeln!(out, "Energy    {}uAh used, about {}h left", used, hours)?,
+                (Some(used), None) => writeln!(out, "Energy    {}uAh used", used)?,
+                _ => {}
+            }
+            print_lines(out, &watch.command("CAL")?)?;
+        }
+        Command::Diagnostics => diagnostics(watch, out)?,
+        Command::Update(path) => {
+            let image = update::Image::read(path).map_err(Error::Update)?;
+            update::enter_bootloader(watch, &image)?;
+            writeln!(out, "Writing {} bytes for board revision {}", image.data.len(), image.info.board_revision)?;
+            update::flash(path).map_err(Error::Update)?;
+            let status = update::verify(&image).map_err(Error::Update)?;
+            writeln!(out, "Watch runs firmware {}, CRC {:04x} matches", status.firmware, image.crc)?;
+        }
+    }
+    Ok(())
+}
+
+/// Largest difference to the PC clock that passes, the watch only resolves whole seconds.
+const MAX_CLOCK_ERROR: i64 = 2;
+
+fn check<O: Write>(out: &mut O, name: &str, result: Result<String, String>) -> Result<bool, Error> {
+    match &result {
+        Ok(detail) => writeln!(out, "ok    {}: {}", name, detail)?,
+        Err(detail) => writeln!(out, "FAIL  {}: {}", name, detail)?
+    }
+    Ok(result.is_ok())
+}
+
+fn diagnostics<T: Read + Write, O: Write>(watch: &mut Watch<T>, out: &mut O) -> Result<(), Error> {
+    let mut failed = 0;
+
+    let version = watch.query("VERSION").map_err(|error| error.to_string());
+    failed += !check(out, "version", version)? as usize;
+
+    let status = match Status::read(watch) {
+        Ok(status) if status.tx_dropped == 0 => Ok(format!("record version {}", status.version)),
+        Ok(status) => Err(format!("{} reply bytes dropped, the host didn't read in time", status.tx_dropped)),
+        Err(error) => Err(error.to_string())
+    };
+    failed += !check(out, "status", status)? as usize;
+
+    let mut round_trips = Vec::new();
+    for _ in 0..sync::SAMPLES {
+        let start = Instant::now();
+        watch.query("EPOCH")?;
+        round_trips.push(start.elapsed());
+    }
+    let slowest = round_trips.iter().max().copied().unwrap_or_default();
+    let latency = if slowest < Duration::from_millis(100) {
+        Ok(format!("{:.1}ms round trip at most", slowest.as_secs_f64() * 1000.0))
+    } else {
+        Err(format!("{:.1}ms round trip", slowest.as_secs_f64() * 1000.0))
+    };
+    failed += !check(out, "latency", latency)? as usize;
+
+    let offset = sync::local_offset(SystemTime::now());
+    let clock = watch.query("EPOCH")?.parse::<i64>().map(|watch_time| {
+        let pc_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64 + offset as i64;
+        watch_time - pc_time
+    });
+    let clock = match clock {
+        Ok(error) if error.abs() <= MAX_CLOCK_ERROR => Ok(format!("{}s off", error)),
+        Ok(error) => Err(format!("{}s off, run sync", error)),
+        Err(_) => Err("unreadable time".to_string())
+    };
+    failed += !check(out, "clock", clock)? as usize;
+
+    let settings = match watch.command("GET") {
+        Ok(lines) => Ok(format!("{} readable", lines.len())),
+        Err(error) => Err(error.to_string())
+    };
+    failed += !check(out, "settings", settings)? as usize;
+
+    let battery = watch.query("BATT").map_err(|error| error.to_string());
+    failed += !check(out, "battery", battery)? as usize;
+
+    let errors = match watch.command("NIXIEWATCH-DIAGNOSTICS") {
+        Err(watch::Error::Command(1, _)) => Ok("unknown commands are rejected".to_string()),
+        other => Err(format!("unknown command answered with {:?}", other))
+    };
+    failed += !check(out, "errors", errors)? as usize;
+
+    if failed > 0 {
+        return Err(Error::Diagnostics(failed));
+    }
+    Ok(())
+}
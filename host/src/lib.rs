@@ -0,0 +1,11 @@
+//! Host side of the nixie watch: talking to it over its USB serial port.
+//!
+//! Everything goes through `Watch`, which only needs `Read` and `Write`, so the tools can be
+//! tested against a stand-in for the serial port.
+
+pub mod cli;
+pub mod port;
+pub mod status;
+pub mod sync;
+pub mod update;
+pub mod watch;
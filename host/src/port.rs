@@ -0,0 +1,33 @@
+//! Finding and opening the serial port of the watch.
+
+use std::time::Duration;
+
+use serialport::{SerialPort, SerialPortType};
+
+/// USB IDs the firmware enumerates with.
+pub const VID: u16 = 0x16c0;
+pub const PID: u16 = 0x27dd;
+
+/// How long to wait for the watch to answer.
+pub const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Path of the first serial port with the watch's USB IDs.
+pub fn find() -> Option<String> {
+    let ports = serialport::available_ports().ok()?;
+    ports.into_iter()
+        .find(|port| matches!(&port.port_type, SerialPortType::UsbPort(usb) if usb.vid == VID && usb.pid == PID))
+        .map(|port| port.port_name)
+}
+
+/// Opens the port at `path`, or the first watch found if `None`.
+pub fn open(path: Option<&str>) -> Result<Box<dyn SerialPort>, String> {
+    let path = match path {
+        Some(path) => path.to_string(),
+        None => find().ok_or_else(|| format!("no watch found with USB ID {:04x}:{:04x}", VID, PID))?
+    };
+    // The baud rate means nothing to a CDC port
+    serialport::new(&path, 115_200)
+        .timeout(TIMEOUT)
+        .open()
+        .map_err(|error| format!("{}: {}", path, error))
+}
@@ -0,0 +1,117 @@
+//! The `STATUS` record, see `nixiewatch_core::command` for its format.
+
+use std::io::{Read, Write};
+
+use nixiewatch_core::battery::Power;
+use nixiewatch_core::calendar::{Date, Time};
+
+use crate::watch::{Error, Watch};
+
+/// Newest record version this understands.
+pub const STATUS_VERSION: u32 = 1;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Status {
+    pub version: u32,
+    pub time: Time,
+    pub date: Date,
+    /// In percent
+    pub battery: u8,
+    pub battery_mv: u16,
+    /// Charging is over
+    pub charged: bool,
+    /// Seconds since the last reset
+    pub uptime: u32,
+    pub wakes: u32,
+    pub firmware: String,
+    /// Reply bytes the watch dropped, 0 from firmware that doesn't count them
+    pub tx_dropped: u32,
+    /// Board revision the firmware was built for, `None` from firmware that can't be updated over USB
+    pub board: Option<u8>,
+    /// CRC of the running image
+    pub image_crc: Option<u16>,
+    /// `None` from firmware that only tells charging from charged
+    pub power: Option<Power>,
+    /// Charge used since the charger was unplugged, `None` from firmware that doesn't count it
+    pub used_uah: Option<u32>,
+    /// Estimated hours left, `None` until the watch knows its average current
+    pub runtime_hours: Option<u32>
+}
+
+fn parse_fields<const N: usize>(text: &str, separator: char) -> Option<[u32; N]> {
+    let mut fields = [0; N];
+    let mut parts = text.split(separator);
+    for field in fields.iter_mut() {
+        *field = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(fields)
+}
+
+impl Status {
+    /// Parses the record line, skipping keys this version doesn't know.
+    pub fn parse(line: &str) -> Result<Status, String> {
+        let mut words = line.split(' ');
+        if words.next() != Some("STATUS") {
+            return Err(format!("not a status record: {}", line));
+        }
+
+        let (mut version, mut time, mut date, mut battery, mut battery_mv) = (None, None, None, None, None);
+        let (mut charged, mut uptime, mut wakes, mut firmware) = (None, None, None, None);
+        let (mut tx_dropped, mut board, mut image_crc, mut power) = (0, None, None, None);
+        let (mut used_uah, mut runtime_hours) = (None, None);
+        for word in words {
+            let (key, value) = word.split_once('=').ok_or_else(|| format!("bad field {}", word))?;
+            let bad = || format!("bad value for {}: {}", key, value);
+            match key {
+                "v" => version = Some(value.parse().map_err(|_| bad())?),
+                "time" => time = parse_fields(value, ':').and_then(|[h, m, s]| Time::new(h as u8, m as u8, s as u8)),
+                "date" => date = parse_fields(value, '-').and_then(|[y, m, d]| Date::new(y as u16, m as u8, d as u8)),
+                "battery" => battery = Some(value.parse().map_err(|_| bad())?),
+                "battery_mv" => battery_mv = Some(value.parse().map_err(|_| bad())?),
+                "charge" => charged = Some(value == "charged"),
+                "uptime" => uptime = Some(value.parse().map_err(|_| bad())?),
+                "wakes" => wakes = Some(value.parse().map_err(|_| bad())?),
+                "firmware" => firmware = Some(value.to_string()),
+                "tx_dropped" => tx_dropped = value.parse().map_err(|_| bad())?,
+                "board" => board = Some(value.parse().map_err(|_| bad())?),
+                "image_crc" => image_crc = Some(value.parse().map_err(|_| bad())?),
+                "power" => power = Some(Power::from_name(value).ok_or_else(bad)?),
+                "used_uah" => used_uah = Some(value.parse().map_err(|_| bad())?),
+                "runtime_h" => runtime_hours = Some(value.parse().map_err(|_| bad())?),
+                _ => {}
+            }
+        }
+
+        let version = version.ok_or("missing version")?;
+        if version > STATUS_VERSION {
+            return Err(format!("status version {} is newer than this tool, update it", version));
+        }
+        let missing = |key: &str| format!("missing {}", key);
+        Ok(Status {
+            version,
+            time: time.ok_or_else(|| missing("time"))?,
+            date: date.ok_or_else(|| missing("date"))?,
+            battery: battery.ok_or_else(|| missing("battery"))?,
+            battery_mv: battery_mv.ok_or_else(|| missing("battery_mv"))?,
+            charged: charged.ok_or_else(|| missing("charge"))?,
+            uptime: uptime.ok_or_else(|| missing("uptime"))?,
+            wakes: wakes.ok_or_else(|| missing("wakes"))?,
+            firmware: firmware.ok_or_else(|| missing("firmware"))?,
+            tx_dropped,
+            board,
+            image_crc,
+            power,
+            used_uah,
+            runtime_hours
+        })
+    }
+
+    /// Asks the watch for its status.
+    pub fn read<T: Read + Write>(watch: &mut Watch<T>) -> Result<Status, Error> {
+        let line = watch.query("STATUS")?;
+        Status::parse(&line).map_err(Error::Protocol)
+    }
+}
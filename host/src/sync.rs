@@ -0,0 +1,84 @@
+//! Setting the watch from the PC clock.
+//!
+//! The watch starts the second it is sent as soon as the command arrives, so the command is sent
+//! one transfer time before the PC clock reaches a whole second. The transfer time is taken as
+//! half the quickest of a few round trips.
+
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::watch::{Error, Watch};
+
+/// Round trips to measure the latency from.
+pub const SAMPLES: usize = 8;
+
+/// Least time left to get ready for sending at the right moment.
+const MARGIN: Duration = Duration::from_millis(20);
+
+#[derive(PartialEq, Debug)]
+pub struct Plan {
+    /// How long to wait before sending
+    pub wait: Duration,
+    /// Timestamp to send, in local time
+    pub timestamp: u32
+}
+
+/// Works out when to send what, `now` being the time since the Unix epoch and `utc_offset` in seconds.
+pub fn plan(now: Duration, latency: Duration, utc_offset: i32) -> Plan {
+    let target = (now + latency + MARGIN).as_secs() + 1;
+    Plan {
+        wait: Duration::from_secs(target) - latency - now,
+        timestamp: (target as i64 + utc_offset as i64) as u32
+    }
+}
+
+/// One way latency to the watch, half the quickest of `samples` round trips.
+pub fn measure_latency<T: Read + Write>(watch: &mut Watch<T>, samples: usize) -> Result<Duration, Error> {
+    let mut quickest = Duration::MAX;
+    for _ in 0..samples {
+        let start = Instant::now();
+        watch.query("EPOCH")?;
+        quickest = quickest.min(start.elapsed());
+    }
+    Ok(quickest / 2)
+}
+
+/// Offset of the local time zone to UTC in seconds at `now`.
+#[cfg(unix)]
+pub fn local_offset(now: SystemTime) -> i32 {
+    let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&seconds, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i32
+}
+
+#[cfg(not(unix))]
+pub fn local_offset(_now: SystemTime) -> i32 {
+    0
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Report {
+    pub latency: Duration,
+    /// How far the watch was ahead before the sync, in seconds
+    pub error: i64,
+    pub timestamp: u32
+}
+
+/// Sets the watch to the PC clock plus `utc_offset` seconds.
+pub fn sync<T: Read + Write>(watch: &mut Watch<T>, utc_offset: i32) -> Result<Report, Error> {
+    let latency = measure_latency(watch, SAMPLES)?;
+
+    let reply = watch.query("EPOCH")?;
+    let before: i64 = reply.parse().map_err(|_| Error::Protocol(reply))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let error = before - (now.as_secs() as i64 + utc_offset as i64);
+
+    let plan = plan(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(), latency, utc_offset);
+    thread::sleep(plan.wait);
+    watch.command(&format!("EPOCH {}", plan.timestamp))?;
+    Ok(Report { latency, error, timestamp: plan.timestamp })
+}
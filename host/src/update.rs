@@ -0,0 +1,114 @@
+//! Firmware updates over USB, through the DFU bootloader built into the STM32.
+//!
+//! The watch restarts into the bootloader on `BOOTLOADER`, `dfu-util` then writes the image and starts it.
+//! Only the pages the image covers are erased, so the settings in the last page survive an update.
+//! Once the watch is back its `image_crc` has to match the file, otherwise the update failed.
+
+use std::io::{Read, Write};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nixiewatch_core::firmware::{image_crc, ImageInfo};
+
+use crate::port;
+use crate::status::Status;
+use crate::watch::{self, Watch};
+
+/// USB ID of the STM32 bootloader.
+pub const DFU_ID: &str = "0483:df11";
+
+pub const FLASH_START: u32 = 0x0800_0000;
+
+/// The flash without the settings page.
+pub const MAX_IMAGE_SIZE: usize = 31 * 1024;
+
+/// Time the bootloader takes to show up on the USB after the watch left.
+const DFU_STARTUP: Duration = Duration::from_secs(2);
+
+/// Time the new firmware gets to show up as a serial port again.
+const RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A firmware image as written to the flash, from `cargo objcopy --release -- -O binary`.
+#[derive(Debug)]
+pub struct Image {
+    pub data: Vec<u8>,
+    pub info: ImageInfo,
+    /// What the watch will report as `image_crc` once it runs the image
+    pub crc: u16
+}
+
+impl Image {
+    pub fn parse(data: Vec<u8>) -> Result<Image, String> {
+        if data.starts_with(b"\x7fELF") {
+            return Err("this is an ELF file, convert it to a raw binary first".to_string());
+        }
+        if data.len() > MAX_IMAGE_SIZE {
+            return Err(format!("{} bytes don't fit in the {} bytes of flash", data.len(), MAX_IMAGE_SIZE));
+        }
+        let info = ImageInfo::find(&data).ok_or("not a nixiewatch image")?;
+        let crc = image_crc(&data);
+        Ok(Image { data, info, crc })
+    }
+
+    pub fn read(path: &str) -> Result<Image, String> {
+        let data = std::fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        Image::parse(data).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    /// Refuses images built for another board than the one in `status`.
+    pub fn check(&self, status: &Status) -> Result<(), String> {
+        match status.board {
+            Some(board) if board == self.info.board_revision => Ok(()),
+            Some(board) => Err(format!("the image is for board revision {}, the watch is revision {}",
+                                       self.info.board_revision, board)),
+            None => Err("the watch doesn't report its board revision, update it with a probe".to_string())
+        }
+    }
+}
+
+/// Checks the image against the watch and restarts the watch into the bootloader.
+pub fn enter_bootloader<T: Read + Write>(watch: &mut Watch<T>, image: &Image) -> Result<(), watch::Error> {
+    let status = Status::read(watch)?;
+    image.check(&status).map_err(watch::Error::Protocol)?;
+    watch.command("BOOTLOADER")?;
+    Ok(())
+}
+
+/// The `dfu-util` arguments writing `path` and starting it afterwards.
+pub fn dfu_util_args(path: &str) -> Vec<String> {
+    ["-d", DFU_ID, "-a", "0", "-s", &format!("{:#010x}:leave", FLASH_START), "-D", path]
+        .iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Writes the image at `path` with `dfu-util`, once the bootloader is up.
+pub fn flash(path: &str) -> Result<(), String> {
+    thread::sleep(DFU_STARTUP);
+    let status = Command::new("dfu-util").args(dfu_util_args(path)).status()
+        .map_err(|error| format!("can't run dfu-util: {}", error))?;
+    if !status.success() {
+        return Err(format!("dfu-util failed with {}", status));
+    }
+    Ok(())
+}
+
+/// Waits for the watch to come back and checks it runs `image`.
+pub fn verify(image: &Image) -> Result<Status, String> {
+    let start = Instant::now();
+    let path = loop {
+        if let Some(path) = port::find() {
+            break path;
+        }
+        if start.elapsed() > RESTART_TIMEOUT {
+            return Err("the watch didn't come back after the update".to_string());
+        }
+        thread::sleep(Duration::from_millis(200));
+    };
+    let mut watch = Watch::new(port::open(Some(&path))?);
+    let status = Status::read(&mut watch).map_err(|error| error.to_string())?;
+    match status.image_crc {
+        Some(crc) if crc == image.crc => Ok(status),
+        Some(crc) => Err(format!("the watch runs an image with CRC {:04x}, not {:04x}", crc, image.crc)),
+        None => Err("the watch doesn't report its image CRC, the update failed".to_string())
+    }
+}
@@ -0,0 +1,110 @@
+//! Commands and replies of the line based protocol, see `nixiewatch_core::command`.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The watch answered `ERR <code> <reason>`
+    Command(u8, String),
+    /// Something that isn't part of the protocol
+    Protocol(String)
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Command(code, reason) => write!(f, "watch replied error {}: {}", code, reason),
+            Error::Protocol(line) => write!(f, "unexpected reply: {}", line)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Starts of the lines the watch sends on its own, whenever it likes, rather than in reply to a command
+const UNSOLICITED: [&str; 1] = ["SELFTEST "];
+
+pub struct Watch<T: Read + Write> {
+    port: T,
+    /// Received bytes that aren't a whole line yet
+    received: Vec<u8>
+}
+
+impl<T: Read + Write> Watch<T> {
+    pub fn new(port: T) -> Self {
+        Watch { port, received: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+
+    /// Sends a command without waiting for the reply.
+    pub fn send(&mut self, command: &str) -> Result<(), Error> {
+        self.port.write_all(command.as_bytes())?;
+        self.port.write_all(b"\n")?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next line, a reply or one the watch sent on its own like the steps of `SELFTEST`.
+    pub fn read_line(&mut self) -> Result<String, Error> {
+        loop {
+            if let Some(end) = self.received.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.received.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string());
+            }
+            let mut buffer = [0u8; 64];
+            match self.port.read(&mut buffer)? {
+                0 => return Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+                count => self.received.extend_from_slice(&buffer[..count])
+            }
+        }
+    }
+
+    /// Reads the reply to a command up to its `OK`, returning the lines before it. Lines the watch sent
+    /// on its own in between, like the steps of `SELFTEST`, are skipped.
+    pub fn reply(&mut self) -> Result<Vec<String>, Error> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if UNSOLICITED.iter().any(|prefix| line.starts_with(prefix)) {
+                continue;
+            }
+            if line == "OK" {
+                return Ok(lines);
+            }
+            if let Some(error) = line.strip_prefix("ERR ") {
+                let (code, reason) = error.split_once(' ').unwrap_or((error, ""));
+                let code = code.parse().map_err(|_| Error::Protocol(line.clone()))?;
+                return Err(Error::Command(code, reason.to_string()));
+            }
+            lines.push(line);
+        }
+    }
+
+    /// Sends a command and waits for its reply.
+    pub fn command(&mut self, command: &str) -> Result<Vec<String>, Error> {
+        self.send(command)?;
+        self.reply()
+    }
+
+    /// A command answering with a single line.
+    pub fn query(&mut self, command: &str) -> Result<String, Error> {
+        let mut lines = self.command(command)?;
+        match lines.len() {
+            1 => Ok(lines.remove(0)),
+            _ => Err(Error::Protocol(lines.join("\n")))
+        }
+    }
+}
@@ -0,0 +1,133 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::{pseudo_terminal, FakeWatch};
+use nixiewatch_core::calendar::DateTime;
+use nixiewatch_core::command::Request;
+use nixiewatch_core::settings::{Key, Value};
+use nixiewatch_core::text::Text;
+use nixiewatch_host::cli::{parse_args, parse_offset, run, Args, Command, Error};
+use nixiewatch_host::sync::local_offset;
+use nixiewatch_host::watch::Watch;
+
+/// Runs `args` against a fake watch behind a pseudo terminal, returning the output and what the watch was asked to do.
+fn cli(watch: FakeWatch, args: &[&str]) -> (Result<String, Error>, Vec<Request>) {
+    let (port, handle) = pseudo_terminal(watch);
+    let mut watch = Watch::new(port);
+    let mut out = Vec::new();
+    let result = run(&parse_args(args).unwrap().command, &mut watch, &mut out);
+    drop(watch);
+    let requests = handle.join().unwrap().requests;
+    (result.map(|_| String::from_utf8(out).unwrap()), requests)
+}
+
+/// A fake watch showing the local time of the PC.
+fn watch_on_time() -> FakeWatch {
+    let now = SystemTime::now();
+    let seconds = now.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 + local_offset(now) as i64;
+    FakeWatch::new(DateTime::from_timestamp(seconds as u32))
+}
+
+#[test]
+fn arguments() {
+    assert_eq!(parse_args(&["--port", "/dev/ttyACM1", "battery"]).unwrap(), Args { port: Some("/dev/ttyACM1".into()), command: Command::Battery });
+    assert_eq!(parse_args(&["sync", "--offset", "-05:30"]).unwrap().command, Command::Sync(Some(-19800)));
+    assert_eq!(parse_args(&["settings", "set", "BATTERY_LOW", "5"]).unwrap().command, Command::Set("BATTERY_LOW".into(), "5".into()));
+    for args in [&[][..], &["--port"], &["battery", "now"], &["settings", "set", "BATTERY_LOW"], &["sync", "--offset", "x"]] {
+        assert!(matches!(parse_args(args), Err(Error::Usage)), "{:?}", args);
+    }
+    assert_eq!(parse_args(&["update", "nixiewatch.bin"]).unwrap().command, Command::Update("nixiewatch.bin".into()));
+    assert_eq!(parse_offset("+2"), Some(7200));
+    assert_eq!(parse_offset("15:00"), None);
+}
+
+#[test]
+fn battery() {
+    assert_eq!(cli(FakeWatch::default(), &["battery"]).0.unwrap(), "85% charging\nNo charge since the reset\n");
+}
+
+#[test]
+fn energy() {
+    let out = cli(FakeWatch::default(), &["energy"]).0.unwrap();
+    assert_eq!(out, "On battery for 3600s, woke up 5 times\nAwake 20s, display 16s, HV 16s, USB 0s\n\
+                     Used 600uAh, 600uA on average\nAbout 212h left\n");
+}
+
+#[test]
+fn status() {
+    let out = cli(FakeWatch::default(), &["status"]).0.unwrap();
+    assert_eq!(out, "Firmware  0.1.0\nTime      2024-05-01 12:00:00\nBattery   85% (3950mV), charging\nUptime    1h 0m\n\
+                     Wakes     3\nEnergy    600uAh used, about 212h left\nSet 0s ago\nTrim 0ppm\n");
+}
+
+#[test]
+fn settings() {
+    let (out, _) = cli(FakeWatch::default(), &["settings"]);
+    assert_eq!(out.unwrap().lines().count(), Key::ALL.len());
+    let (out, requests) = cli(FakeWatch::default(), &["settings", "set", "battery_low", "20"]);
+    assert_eq!(requests, vec![Request::Set(Key::BatteryLow, Value::Number(20))]);
+    // the fake doesn't apply the change, the real watch reads back 20
+    assert_eq!(out.unwrap(), "BATTERY_LOW 10\n");
+
+    let (result, requests) = cli(FakeWatch::default(), &["settings", "set", "battery_low", "200"]);
+    assert!(matches!(result, Err(Error::Watch(_))));
+    assert!(requests.is_empty());
+}
+
+#[test]
+fn message() {
+    assert!(matches!(parse_args(&["message"]), Err(Error::Usage)));
+    let (out, requests) = cli(FakeWatch::default(), &["message", "hello", "world"]);
+    assert_eq!(out.unwrap(), "");
+    assert_eq!(requests, vec![Request::ShowMessage(Text::from_bytes(b"hello world").unwrap())]);
+}
+
+#[test]
+fn exercise_and_usage() {
+    let (out, requests) = cli(FakeWatch::default(), &["exercise"]);
+    assert_eq!(out.unwrap(), "");
+    assert_eq!(requests, vec![Request::Exercise]);
+    let out = cli(FakeWatch::default(), &["usage"]).0.unwrap();
+    assert_eq!(out, "1 a=3600 b=3600 c=3600 d=3600 e=3600 f=3600 g=3600 dp=3600\n\
+                     2 a=1800 b=1800 c=1800 d=1800 e=1800 f=1800 g=1800 dp=1800\n");
+}
+
+#[test]
+fn self_test_prints_every_step() {
+    let (out, requests) = cli(FakeWatch::default(), &["selftest"]);
+    let out = out.unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 112);
+    assert_eq!(lines[0], "  1 tube 1 segment a");
+    assert_eq!(lines[15], " 16 tube 2 segment dp");
+    assert_eq!(lines[16], " 17 glyph !");
+    assert_eq!(lines[110], "111 high voltage off");
+    assert_eq!(lines[111], "112 high voltage on, all segments lit");
+    assert_eq!(requests, vec![Request::SelfTest]);
+}
+
+#[test]
+fn sync() {
+    let (out, requests) = cli(FakeWatch::default(), &["sync", "--utc"]);
+    assert!(out.unwrap().starts_with("Watch was -"));
+    assert!(matches!(requests.as_slice(), [Request::SyncDateTime(_)]));
+}
+
+#[test]
+fn diagnostics() {
+    let out = cli(watch_on_time(), &["diagnostics"]).0.unwrap();
+    assert_eq!(out.lines().count(), 7);
+    assert!(out.lines().all(|line| line.starts_with("ok")), "{}", out);
+
+    assert!(matches!(cli(FakeWatch::default(), &["diagnostics"]).0, Err(Error::Diagnostics(1))));
+}
+
+#[test]
+fn update_refuses_what_isnt_an_image() {
+    let (result, requests) = cli(FakeWatch::default(), &["update", "Cargo.toml"]);
+    assert!(matches!(result, Err(Error::Update(error)) if error.ends_with("not a nixiewatch image")));
+    assert!(requests.is_empty());
+}
@@ -0,0 +1,131 @@
+//! A stand-in for the watch on the other end of the serial port, answering with the
+//! command handling of the firmware itself. It can also sit behind a pseudo terminal,
+//! which the tools open just like the real CDC port.
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread::{self, JoinHandle};
+
+use nixiewatch_core::battery::Power;
+use nixiewatch_core::calendar::{Date, DateTime, Time};
+use nixiewatch_core::command::{handle_line, LineBuffer, Output, Request, Status};
+use nixiewatch_core::energy::Usage;
+use nixiewatch_core::self_test::{self, STEPS};
+use nixiewatch_core::settings::Settings;
+use nixiewatch_core::wear::Wear;
+
+struct Reply<'a>(&'a mut VecDeque<u8>);
+
+impl Output for Reply<'_> {
+    fn write(&mut self, data: &[u8]) {
+        self.0.extend(data);
+    }
+}
+
+pub struct FakeWatch {
+    pub status: Status,
+    /// Everything the watch was asked to do
+    pub requests: Vec<Request>,
+    line: LineBuffer,
+    output: VecDeque<u8>
+}
+
+impl FakeWatch {
+    pub fn new(now: DateTime) -> Self {
+        FakeWatch {
+            status: Status {
+                time: now.time,
+                date: now.date,
+                battery_status: 85,
+                battery_mv: 3950,
+                power: Power::Charging,
+                charge_session: None,
+                since_set: 0,
+                uptime: 3600,
+                wakes: 3,
+                energy: Usage { seconds: 3600, awake: 20, display: 16, hv: 16, usb: 0, wakes: 5, used_uah: 600 },
+                wear: Wear { seconds: [3600, 3600, 3600, 3600, 3600, 3600, 3600, 3600, 1800, 1800, 1800, 1800, 1800, 1800, 1800, 1800] },
+                tx_dropped: 0,
+                board_revision: 1,
+                image_crc: 0xBEEF,
+                settings: Settings::default(),
+                firmware_version: "0.1.0"
+            },
+            requests: Vec::new(),
+            line: LineBuffer::new(),
+            output: VecDeque::new()
+        }
+    }
+
+    /// Sends the report of a self test step on its own, the way the watch does while the test runs.
+    pub fn report_self_test(&mut self, index: Option<u32>) {
+        self_test::report(&mut Reply(&mut self.output), index);
+    }
+}
+
+impl Default for FakeWatch {
+    fn default() -> Self {
+        FakeWatch::new(DateTime::new(Date::new(2024, 5, 1).unwrap(), Time::new(12, 0, 0).unwrap()))
+    }
+}
+
+impl Read for FakeWatch {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buffer.len().min(self.output.len());
+        for (byte, out) in buffer.iter_mut().zip(self.output.drain(..count)) {
+            *byte = out;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for FakeWatch {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        for &byte in data {
+            if let Some(line) = self.line.push(byte) {
+                let request = handle_line(line, &self.status, &mut Reply(&mut self.output));
+                if request == Some(Request::SelfTest) {
+                    // All at once, the watch reports each step as it gets to it
+                    for index in (0..STEPS).map(Some).chain([None]) {
+                        self_test::report(&mut Reply(&mut self.output), index);
+                    }
+                }
+                self.requests.extend(request);
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serves `watch` on the master side of a pseudo terminal, returning the other side and a handle
+/// giving the fake watch back once the terminal is closed.
+#[cfg(unix)]
+pub fn pseudo_terminal(mut watch: FakeWatch) -> (serialport::TTYPort, JoinHandle<FakeWatch>) {
+    let (mut master, slave) = serialport::TTYPort::pair().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buffer = [0u8; 64];
+        loop {
+            match master.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(count) => {
+                    watch.write_all(&buffer[..count]).unwrap();
+                    while let Ok(count) = watch.read(&mut buffer) {
+                        master.write_all(&buffer[..count]).unwrap();
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::TimedOut => {}
+                Err(_) => break
+            }
+        }
+        watch
+    });
+    (slave, handle)
+}
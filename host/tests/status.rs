@@ -0,0 +1,76 @@
+mod common;
+
+use common::FakeWatch;
+use nixiewatch_core::battery::Power;
+use nixiewatch_core::calendar::{Date, Time};
+use nixiewatch_host::status::Status;
+use nixiewatch_host::watch::Watch;
+
+const RECORD: &str = "STATUS v=1 time=13:37:05 date=2024-05-01 battery=85 battery_mv=3950 charge=charged uptime=3600 wakes=12 \
+                      firmware=0.1.0 tx_dropped=5 board=1 image_crc=48879 power=charged used_uah=600 runtime_h=212";
+
+#[test]
+fn parses_the_record() {
+    assert_eq!(Status::parse(RECORD).unwrap(), Status {
+        version: 1,
+        time: Time::new(13, 37, 5).unwrap(),
+        date: Date::new(2024, 5, 1).unwrap(),
+        battery: 85,
+        battery_mv: 3950,
+        charged: true,
+        uptime: 3600,
+        wakes: 12,
+        firmware: "0.1.0".into(),
+        tx_dropped: 5,
+        board: Some(1),
+        image_crc: Some(0xBEEF),
+        power: Some(Power::Charged),
+        used_uah: Some(600),
+        runtime_hours: Some(212)
+    });
+}
+
+#[test]
+fn parses_every_power_state() {
+    for (name, power) in [("battery", Power::Battery), ("charging", Power::Charging), ("charged", Power::Charged)] {
+        let record = RECORD.replace("charge=charged", &format!("charge={}", name)).replace("power=charged", &format!("power={}", name));
+        let status = Status::parse(&record).unwrap();
+        assert_eq!((status.charged, status.power), (power == Power::Charged, Some(power)), "{}", name);
+    }
+    // Older firmware said charging on battery too
+    assert!(!Status::parse(&RECORD.replace("charge=charged", "charge=charging")).unwrap().charged);
+}
+
+#[test]
+fn keys_added_later_are_optional() {
+    let status = Status::parse(&RECORD.replace(" tx_dropped=5 board=1 image_crc=48879 power=charged used_uah=600 runtime_h=212", "")).unwrap();
+    assert_eq!((status.tx_dropped, status.board, status.image_crc, status.power), (0, None, None, None));
+    assert_eq!((status.used_uah, status.runtime_hours), (None, None));
+}
+
+#[test]
+fn unknown_keys_are_skipped() {
+    assert!(Status::parse(&format!("{} added_later=7", RECORD)).is_ok());
+}
+
+#[test]
+fn newer_versions_and_missing_keys_are_rejected() {
+    assert!(Status::parse(&RECORD.replace("v=1", "v=2")).unwrap_err().contains("newer"));
+    assert_eq!(Status::parse(&RECORD.replace(" wakes=12", "")).unwrap_err(), "missing wakes");
+    assert_eq!(Status::parse(&RECORD.replace("13:37:05", "25:00:00")).unwrap_err(), "missing time");
+    assert!(Status::parse("12:00").is_err());
+}
+
+#[test]
+fn self_test_steps_in_between_are_not_the_reply() {
+    let mut fake = FakeWatch::default();
+    fake.report_self_test(Some(0));
+    let mut watch = Watch::new(fake);
+    let status = Status::read(&mut watch).unwrap();
+    assert_eq!((status.battery, status.wakes), (85, 3));
+
+    let mut fake = watch.into_inner();
+    fake.report_self_test(None);
+    let mut watch = Watch::new(fake);
+    assert_eq!(watch.command("GET BATTERY_LOW").unwrap(), vec!["BATTERY_LOW 10"]);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::FakeWatch;
+use nixiewatch_core::calendar::DateTime;
+use nixiewatch_core::command::Request;
+use nixiewatch_host::sync::{plan, sync, Plan};
+use nixiewatch_host::watch::{Error, Watch};
+
+const NOW: u64 = 1_700_000_000;
+
+#[test]
+fn sends_one_latency_before_the_next_second() {
+    let now = Duration::from_secs(NOW) + Duration::from_millis(300);
+    assert_eq!(plan(now, Duration::from_millis(2), 0), Plan { wait: Duration::from_millis(698), timestamp: NOW as u32 + 1 });
+    // with the UTC offset added
+    assert_eq!(plan(now, Duration::from_millis(2), 3600).timestamp, NOW as u32 + 3601);
+}
+
+#[test]
+fn too_close_to_the_second_waits_for_the_next() {
+    let now = Duration::from_secs(NOW) + Duration::from_millis(990);
+    let plan = plan(now, Duration::from_millis(5), 0);
+    assert_eq!(plan.timestamp, NOW as u32 + 2);
+    assert_eq!(plan.wait, Duration::from_millis(1005));
+}
+
+#[test]
+fn sync_sets_the_watch_to_the_pc_clock() {
+    let mut watch = Watch::new(FakeWatch::default());
+    let report = sync(&mut watch, -7200).unwrap();
+    let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 - 7200;
+
+    let fake = watch.into_inner();
+    assert_eq!(fake.requests, vec![Request::SyncDateTime(DateTime::from_timestamp(report.timestamp))]);
+    assert!((after - report.timestamp as i64).abs() <= 1);
+    // the fake watch is stuck in 2024
+    assert!(report.error < 0);
+}
+
+#[test]
+fn command_errors_are_reported() {
+    let mut watch = Watch::new(FakeWatch::default());
+    match watch.command("SET FOO 1") {
+        Err(Error::Command(4, reason)) => assert_eq!(reason, "unknown setting"),
+        other => panic!("{:?}", other)
+    }
+    // and the next command still works
+    assert_eq!(watch.query("TIME").unwrap(), "12:00:00");
+}
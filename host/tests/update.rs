@@ -0,0 +1,46 @@
+mod common;
+
+use common::FakeWatch;
+use nixiewatch_core::command::Request;
+use nixiewatch_core::firmware::{image_crc, ImageInfo};
+use nixiewatch_host::update::{dfu_util_args, enter_bootloader, Image, MAX_IMAGE_SIZE};
+use nixiewatch_host::watch::{Error, Watch};
+
+fn image_data(board_revision: u8) -> Vec<u8> {
+    let mut data = vec![0x55; 1000];
+    data[200..209].copy_from_slice(&ImageInfo::record(board_revision));
+    data
+}
+
+#[test]
+fn images_are_recognised() {
+    let image = Image::parse(image_data(1)).unwrap();
+    assert_eq!(image.info.board_revision, 1);
+    assert_eq!(image.crc, image_crc(&image.data));
+
+    assert_eq!(Image::parse(vec![0x55; 1000]).unwrap_err(), "not a nixiewatch image");
+    assert!(Image::parse(b"\x7fELF rest of the file".to_vec()).unwrap_err().contains("ELF"));
+    let mut too_big = image_data(1);
+    too_big.resize(MAX_IMAGE_SIZE + 1, 0xFF);
+    assert!(Image::parse(too_big).unwrap_err().contains("don't fit"));
+}
+
+#[test]
+fn watch_restarts_into_the_bootloader() {
+    let mut watch = Watch::new(FakeWatch::default());
+    enter_bootloader(&mut watch, &Image::parse(image_data(1)).unwrap()).unwrap();
+    assert_eq!(watch.into_inner().requests, vec![Request::Bootloader]);
+}
+
+#[test]
+fn images_for_another_board_are_refused() {
+    let mut watch = Watch::new(FakeWatch::default());
+    let result = enter_bootloader(&mut watch, &Image::parse(image_data(2)).unwrap());
+    assert!(matches!(result, Err(Error::Protocol(error)) if error.contains("revision 2, the watch is revision 1")));
+    assert!(watch.into_inner().requests.is_empty());
+}
+
+#[test]
+fn dfu_util_starts_the_image_after_writing_it() {
+    assert_eq!(dfu_util_args("nixiewatch.bin"), ["-d", "0483:df11", "-a", "0", "-s", "0x08000000:leave", "-D", "nixiewatch.bin"]);
+}
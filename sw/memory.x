@@ -1,9 +1,11 @@
 MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
-  /* TODO Adjust these memory regions to match your device memory layout */
-  /* These values correspond to the LM3S6965, one of the few devices QEMU can emulate */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 32K
+  /* STM32F042K6, the last 1K flash page is kept free for the settings */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 31K
+  SETTINGS : ORIGIN = 0x08007C00, LENGTH = 1K
   RAM : ORIGIN = 0x20000000, LENGTH = 4K
 }
 
+_image_start = ORIGIN(FLASH);
+_settings_start = ORIGIN(SETTINGS);
@@ -0,0 +1,37 @@
+//! The STAT pin of the MCP73831 on PA1, see `nixiewatch_core::charger` for what it means.
+
+use cortex_m::asm;
+use stm32f0xx_hal::{gpio::{gpioa::PA1, Input, PullUp}, pac::{EXTI, GPIOA}, prelude::*};
+
+use nixiewatch_core::charger::Stat;
+
+/// Cycles the pin takes to follow the weak pull, generous for the few pF on it
+const SETTLE_CYCLES: u32 = 100;
+
+pub struct StatPin {
+    pin: PA1<Input<PullUp>>,
+}
+
+impl StatPin {
+    /// Takes the pin with its pull-up, which also lets a falling edge wake the watch once charging starts.
+    pub fn new(pin: PA1<Input<PullUp>>) -> Self {
+        StatPin { pin }
+    }
+
+    /// Reads the pin with the pull-up and then with the pull-down, a pin that follows both isn't driven.
+    pub fn read(&mut self) -> Stat {
+        let with_pull_up = self.pin.is_high().unwrap();
+        // The HAL can only change the pull by consuming the pin, PA1 and its EXTI line are only touched from here
+        let (gpioa, exti) = unsafe { (&*GPIOA::ptr(), &*EXTI::ptr()) };
+        // Pulling the pin down is no reason to wake up
+        exti.imr.modify(|_, w| w.mr1().clear_bit());
+        gpioa.pupdr.modify(|_, w| w.pupdr1().pull_down());
+        asm::delay(SETTLE_CYCLES);
+        let with_pull_down = self.pin.is_high().unwrap();
+        gpioa.pupdr.modify(|_, w| w.pupdr1().pull_up());
+        asm::delay(SETTLE_CYCLES);
+        exti.pr.write(|w| w.pif1().set_bit());
+        exti.imr.modify(|_, w| w.mr1().set_bit());
+        Stat::from_levels(with_pull_up, with_pull_down)
+    }
+}
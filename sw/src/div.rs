@@ -0,0 +1,49 @@
+//! Division in place of the one of `compiler_builtins`, see `nixiewatch_core::div` for why.
+//!
+//! The linker takes these over the ones of `compiler_builtins`, which stay out of the image. Rust
+//! checks for a zero divisor before it divides, so `__aeabi_idiv0` is never needed.
+
+use nixiewatch_core::div::{idivmod32, idivmod64, udivmod32, udivmod64};
+
+#[no_mangle]
+pub extern "C" fn __aeabi_uidiv(n: u32, d: u32) -> u32 {
+    udivmod32(n, d).0
+}
+
+/// Quotient in r0 and remainder in r1
+#[no_mangle]
+pub extern "C" fn __aeabi_uidivmod(n: u32, d: u32) -> u64 {
+    let (quotient, remainder) = udivmod32(n, d);
+    (remainder as u64) << 32 | quotient as u64
+}
+
+#[no_mangle]
+pub extern "C" fn __aeabi_idiv(a: i32, b: i32) -> i32 {
+    idivmod32(a, b).0
+}
+
+#[no_mangle]
+pub extern "C" fn __aeabi_idivmod(a: i32, b: i32) -> u64 {
+    let (quotient, remainder) = idivmod32(a, b);
+    (remainder as u32 as u64) << 32 | quotient as u32 as u64
+}
+
+/// Called by `__aeabi_uldivmod`
+#[no_mangle]
+pub unsafe extern "C" fn __udivmoddi4(n: u64, d: u64, remainder: *mut u64) -> u64 {
+    let (quotient, rest) = udivmod64(n, d);
+    if !remainder.is_null() {
+        *remainder = rest;
+    }
+    quotient
+}
+
+/// Called by `__aeabi_ldivmod`
+#[no_mangle]
+pub unsafe extern "C" fn __divmoddi4(a: i64, b: i64, remainder: *mut i64) -> i64 {
+    let (quotient, rest) = idivmod64(a, b);
+    if !remainder.is_null() {
+        *remainder = rest;
+    }
+    quotient
+}
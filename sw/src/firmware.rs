@@ -0,0 +1,76 @@
+//! The running image: its info record for the host tools, its CRC and the way into the DFU bootloader.
+
+use core::{ptr, slice};
+
+use stm32f0xx_hal::pac::{RCC, SYSCFG};
+use cortex_m::peripheral::{NVIC, SYST};
+
+use nixiewatch_core::firmware::{image_crc, ImageInfo, RECORD_LEN};
+
+/// Revision of the PCB in `hw`, bump it when a board change needs different firmware
+pub const BOARD_REVISION: u8 = 1;
+
+/// Lets the host tools check an image is meant for this board before flashing it
+#[used]
+static IMAGE_INFO: [u8; RECORD_LEN] = ImageInfo::record(BOARD_REVISION);
+
+/// Start of the STM32F042 system memory, the bootloader's vector table
+const SYSTEM_MEMORY: u32 = 0x1FFF_C400;
+
+extern "C" {
+    /// Start of the FLASH region from `memory.x`
+    static _image_start: u8;
+    /// The initial values of `.data`, the last part of the image, from cortex-m-rt
+    static __sidata: u32;
+    static mut __sdata: u32;
+    static mut __edata: u32;
+}
+
+/// The board revision from the info record, read so the linker keeps the record.
+pub fn board_revision() -> u8 {
+    unsafe { ptr::read_volatile(&IMAGE_INFO[RECORD_LEN - 1]) }
+}
+
+/// CRC of the image in flash, what the host computes over the binary it flashed. Takes around 150ms.
+pub fn crc() -> u16 {
+    unsafe {
+        let start = &_image_start as *const u8;
+        let data_size = ptr::addr_of!(__edata) as usize - ptr::addr_of!(__sdata) as usize;
+        let end = &__sidata as *const u32 as usize + data_size;
+        image_crc(slice::from_raw_parts(start, end - start as usize))
+    }
+}
+
+/// Restarts into the DFU bootloader in system memory, leaving the chip close enough to a reset for it.
+///
+/// Must be called from thread mode, from an interrupt handler the bootloader's own interrupts would stay blocked.
+pub fn enter_dfu() -> ! {
+    cortex_m::interrupt::disable();
+    unsafe {
+        let rcc = &*RCC::ptr();
+        // The bootloader sets up its own clocks starting from the HSI
+        rcc.cr.modify(|_, w| w.hsion().set_bit());
+        while rcc.cr.read().hsirdy().bit_is_clear() {}
+        rcc.cfgr.modify(|_, w| w.sw().hsi());
+        while !rcc.cfgr.read().sws().is_hsi() {}
+
+        // Resetting the peripherals also drops the USB pull-up, so the host sees the watch leave
+        rcc.apb1rstr.write(|w| w.bits(!0));
+        rcc.apb1rstr.reset();
+        rcc.apb2rstr.write(|w| w.bits(!0));
+        rcc.apb2rstr.reset();
+
+        let syst = &*SYST::PTR;
+        syst.csr.write(0);
+        let nvic = &*NVIC::PTR;
+        nvic.icer[0].write(!0);
+        nvic.icpr[0].write(!0);
+
+        // Map the system memory at address 0 like booting with BOOT0 high does
+        rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+        (*SYSCFG::ptr()).cfgr1.modify(|_, w| w.mem_mode().system_flash());
+
+        cortex_m::interrupt::enable();
+        cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32)
+    }
+}
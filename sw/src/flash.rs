@@ -0,0 +1,98 @@
+//! The last flash page, reserved in `memory.x`, as backing store for the settings.
+//!
+//! The CPU stalls while the flash is erased or programmed since the code runs from the same flash,
+//! an erase takes around 20ms.
+
+use core::ptr;
+
+use stm32f0xx_hal::pac::FLASH;
+
+use nixiewatch_core::settings::{Flash, FlashError};
+
+const PAGE_SIZE: usize = 1024;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+extern "C" {
+    /// Start of the SETTINGS region from `memory.x`
+    static _settings_start: u16;
+}
+
+pub struct SettingsFlash {
+    flash: FLASH,
+}
+
+impl SettingsFlash {
+    /// Takes the flash interface, after the RCC has set up the wait states with it.
+    pub fn new(flash: FLASH) -> Self {
+        SettingsFlash { flash }
+    }
+
+    fn start(&self) -> usize {
+        unsafe { &_settings_start as *const u16 as usize }
+    }
+
+    fn unlock(&mut self) {
+        if self.flash.cr.read().lock().bit_is_set() {
+            self.flash.keyr.write(|w| w.fkeyr().bits(KEY1));
+            self.flash.keyr.write(|w| w.fkeyr().bits(KEY2));
+        }
+    }
+
+    fn lock(&mut self) {
+        self.flash.cr.modify(|_, w| w.lock().set_bit());
+    }
+
+    /// Waits for the operation to finish and clears its status flags.
+    fn wait(&mut self) -> bool {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+        let sr = self.flash.sr.read();
+        let ok = sr.pgerr().bit_is_clear() && sr.wrprt().bit_is_clear();
+        // The flags are cleared by writing a one
+        self.flash.sr.write(|w| w.eop().set_bit().pgerr().set_bit().wrprt().set_bit());
+        ok
+    }
+}
+
+impl Flash for SettingsFlash {
+    fn size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn read(&self, offset: usize, data: &mut [u8]) {
+        let start = self.start() + offset;
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = unsafe { ptr::read_volatile((start + i) as *const u8) };
+        }
+    }
+
+    fn erase(&mut self) -> Result<(), FlashError> {
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.per().set_bit());
+        self.flash.ar.write(|w| w.far().bits(self.start() as u32));
+        self.flash.cr.modify(|_, w| w.strt().set_bit());
+        let ok = self.wait();
+        self.flash.cr.modify(|_, w| w.per().clear_bit());
+        self.lock();
+        if ok { Ok(()) } else { Err(FlashError::Erase) }
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.pg().set_bit());
+        let mut ok = true;
+        let start = self.start() + offset;
+        for (i, half_word) in data.chunks(2).enumerate() {
+            let value = u16::from_le_bytes([half_word[0], half_word[1]]);
+            unsafe { ptr::write_volatile((start + i * 2) as *mut u16, value) };
+            if !self.wait() {
+                ok = false;
+                break;
+            }
+        }
+        self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        self.lock();
+        if ok { Ok(()) } else { Err(FlashError::Program) }
+    }
+}
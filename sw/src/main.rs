@@ -10,7 +10,7 @@ use panic_halt as _;
 use stm32f0xx_hal as hal;
 
 use crate::hal::{
-  pac::{interrupt, Interrupt, Peripherals, TIM14, I2C1, EXTI},
+  pac::{interrupt, Interrupt, Peripherals, TIM14, I2C1, EXTI, NVIC},
   {i2c::I2c, delay::Delay, prelude::*},
   time::Hertz,
   adc::*,
@@ -22,59 +22,130 @@ use crate::hal::{
 use cortex_m::{asm::wfi, interrupt::Mutex, peripheral::Peripherals as c_m_Peripherals};
 use cortex_m_rt::entry;
 
-use core::{cell::RefCell};
+use core::cell::{Cell, RefCell};
+use core::mem::MaybeUninit;
 
-mod nixie_segment;
-use nixie_segment::*;
+use nixiewatch_core::nixie_segment::*;
+use nixiewatch_core::battery::{self, Filter, Monitor, Power};
+use nixiewatch_core::charger::{Charger, Session};
+use nixiewatch_core::calendar::{Date, DateTime, Time};
+use nixiewatch_core::calibration::Calibration;
+use nixiewatch_core::time_source::TimeSource;
+use nixiewatch_core::settings::{Settings, SettingsStore};
+use nixiewatch_core::command::{Request, Status};
+use nixiewatch_core::energy::Meter;
+use nixiewatch_core::power::{Activity, Mode, PowerManager, MOTION_CHECKS_PER_SECOND};
+use nixiewatch_core::self_test::{self, Shake};
+use nixiewatch_core::text::Text;
+use nixiewatch_core::wear::Wear;
+
+mod charger;
+use charger::StatPin;
+
+mod div;
+
+mod firmware;
+
+mod flash;
+use flash::SettingsFlash;
+
+mod mem;
+
+mod power;
+
+mod rtc;
+use rtc::*;
 
 mod usb_serial;
 use usb_serial::*;
 
-use core::sync::atomic::{AtomicU8, AtomicBool, Ordering};
-use core::ops::DerefMut;
+use core::sync::atomic::{AtomicU8, AtomicU16, AtomicU32, AtomicBool, Ordering};
 
 use mpu6050::*;
 
 static HOURS: AtomicU8 = AtomicU8::new(13);
 static MINUTES: AtomicU8 = AtomicU8::new(37);
 static BATTERY_STATUS: AtomicU8 = AtomicU8::new(0);
-static BATTERY_CHARGE_DONE: AtomicBool = AtomicBool::new(false);
-static TIME_SET: AtomicBool = AtomicBool::new(true);
+static BATTERY_MV: AtomicU16 = AtomicU16::new(0);
+static UPTIME: AtomicU32 = AtomicU32::new(0);
+static WAKES: AtomicU32 = AtomicU32::new(0);
+static SECONDS: AtomicU8 = AtomicU8::new(0);
+static TIME_SET: AtomicBool = AtomicBool::new(false);
+static TIME_SYNC: AtomicBool = AtomicBool::new(false);
+static TIMESTAMP: AtomicU32 = AtomicU32::new(0);
+static TIMESTAMP_SYNC: AtomicBool = AtomicBool::new(false);
+static SINCE_SET: AtomicU32 = AtomicU32::new(0);
+static YEAR: AtomicU16 = AtomicU16::new(2000);
+static MONTH: AtomicU8 = AtomicU8::new(1);
+static DAY: AtomicU8 = AtomicU8::new(1);
+static DATE_SET: AtomicBool = AtomicBool::new(false);
+static SHOW_DATE: AtomicBool = AtomicBool::new(false);
+static EXERCISE: AtomicBool = AtomicBool::new(false);
+static SELF_TEST: AtomicBool = AtomicBool::new(false);
+static MESSAGE: Mutex<Cell<Option<Text>>> = Mutex::new(Cell::new(None));
+static SETTINGS_CHANGED: AtomicBool = AtomicBool::new(false);
+// How long every segment has been lit, brought up to date by the multiplex timer every few ticks
+static WEAR: Mutex<Cell<Wear>> = Mutex::new(Cell::new(Wear { seconds: [0; 16] }));
 
 static MOVEMENT_DETECTED: AtomicBool = AtomicBool::new(false);
+static USB_CONNECTED: AtomicBool = AtomicBool::new(false);
+/// Set by USB, the main loop leaves for the bootloader once the reply went out
+static ENTER_BOOTLOADER: AtomicBool = AtomicBool::new(false);
+static IMAGE_CRC: AtomicU16 = AtomicU16::new(0);
+
+/// Multiplex rate of the display from the settings, TIM14 only runs while the display is lit or USB is connected.
+static TICKS_PER_SECOND: AtomicU32 = AtomicU32::new(200);
+
+/// Clock of the core and the timers
+const SYSCLK_MHZ: u32 = 12;
+
+/// Factory calibration of VREFINT at 3.3V
+const VREFINT_CAL: *const u16 = 0x1FFF_F7BA as *const u16;
+
+/// RTC backup register keeping the time of the last calibration through a reset, the trim is in the settings
+const BACKUP_LAST_SET: usize = 0;
+
+pub type OPIN = Pin<Output<PushPull>>;
+
+/// Everything only TIM14 uses, handed over in one go as every handover costs flash
+struct Multiplex {
+  timer: Timer<TIM14>,
+  /// The clock itself lives in `NIXIE_CLOCK`, an `Option` of it would have to be initialized from flash
+  nixie_clock: &'static mut NixieClock<OPIN, Rtc>,
+  charge_status: StatPin,
+  adc: Adc,
+  battery_voltage: gpioa::PA0<Analog>,
+  calibration: Calibration,
+  settings_store: SettingsStore<SettingsFlash>,
+}
+
+static MULTIPLEX: Mutex<RefCell<Option<Multiplex>>> = Mutex::new(RefCell::new(None));
+/// Shared by TIM14 and USB, the store itself is only written from TIM14
+static SETTINGS: Mutex<RefCell<Option<Settings>>> = Mutex::new(RefCell::new(None));
 
-static NIXIE_DISPLAY: Mutex<RefCell<Option<NixieClock>>> = Mutex::new(RefCell::new(None));
-static GINT: Mutex<RefCell<Option<Timer<TIM14>>>> = Mutex::new(RefCell::new(None));
+/// Updated by TIM14 and USB, the main loop enters STOP mode through it
+static POWER: Mutex<RefCell<PowerManager>> = Mutex::new(RefCell::new(PowerManager::new()));
 
-static USB_SERIAL: Mutex<RefCell<Option<UsbSerial>>> = Mutex::new(RefCell::new(None));
+/// Counted by TIM14 and the main loop, reported over USB
+static ENERGY: Mutex<RefCell<Meter>> = Mutex::new(RefCell::new(Meter::new()));
 
-static CHARGE_STATUS: Mutex<RefCell<Option<gpioa::PA1<Input<Floating>>>>> = Mutex::new(RefCell::new(None));
+static USB_SERIAL: Mutex<RefCell<UsbSerial>> = Mutex::new(RefCell::new(UsbSerial::new()));
 
-static BATTERY_VOLTAGE: Mutex<RefCell<Option<gpioa::PA0<Analog>>>> = Mutex::new(RefCell::new(None));
-static ADC: Mutex<RefCell<Option<Adc<>>>> = Mutex::new(RefCell::new(None));
+/// The charger as TIM14 last saw it, for USB
+static CHARGER: Mutex<Cell<(Power, Option<Session>)>> = Mutex::new(Cell::new((Power::Battery, None)));
 
 static MPU: Mutex<RefCell<Option<mpu6050::Mpu6050<I2c<I2C1,
 gpiob::PB6<Alternate<AF1>>,
 gpiob::PB7<Alternate<AF1>>>>
 >>> = Mutex::new(RefCell::new(None));
 
-// Make external interrupt registers globally available
-static EINT: Mutex<RefCell<Option<EXTI>>> = Mutex::new(RefCell::new(None));
-
 // Interrupt from IMU that movement was detected
 #[interrupt]
 fn EXTI4_15() {
-  static mut EXINT: Option<EXTI> = None;
   static mut XMPU: Option<mpu6050::Mpu6050<I2c<I2C1,
   gpiob::PB6<Alternate<AF1>>,
   gpiob::PB7<Alternate<AF1>>>>> = None;
 
-  let exti = EXINT.get_or_insert_with(|| {
-    cortex_m::interrupt::free(|cs| {
-      EINT.borrow(cs).replace(None).unwrap()
-    })
-  });
-
   let mpu = XMPU.get_or_insert_with(|| {
     cortex_m::interrupt::free(|cs| {
       MPU.borrow(cs).replace(None).unwrap()
@@ -83,108 +154,365 @@ fn EXTI4_15() {
 
   if mpu.get_motion_detected().unwrap() {
     MOVEMENT_DETECTED.store(true, Ordering::Relaxed);
+    // Wake up TIM14 in case it is stopped
+    NVIC::pend(Interrupt::TIM14);
   }
 
-  exti.pr.write(|w| w.pif4().set_bit());
+  unsafe { (*EXTI::ptr()).pr.write(|w| w.pif4().set_bit()) };
+
+}
+
+// The charger started charging, wake TIM14 to follow it
+#[interrupt]
+fn EXTI0_1() {
+  // Only line 1 is used, TIM14 reads the pin
+  unsafe { (*EXTI::ptr()).pr.write(|w| w.pif1().set_bit()) };
+  NVIC::pend(Interrupt::TIM14);
+}
 
+// The minute alarm of the RTC, wakes TIM14 to check on the battery
+#[interrupt]
+fn RTC() {
+  unsafe {
+    (*hal::pac::RTC::ptr()).isr.modify(|_, w| w.alraf().clear_bit());
+    (*EXTI::ptr()).pr.write(|w| w.pif17().set_bit());
+  }
+  NVIC::pend(Interrupt::TIM14);
 }
 
 // Define an interupt handler, i.e. function to call when interrupt occurs. Here if our external
 // interrupt trips when the timer timed out
 #[interrupt]
 fn TIM14() {
-  static mut INT: Option<Timer<TIM14>> = None;
-  static mut NIXIEDISPLAY: Option<NixieClock> = None;
-  static mut CHARGESTATUS: Option<gpioa::PA1<Input<Floating>>> = None;
-  static mut COUNTER: u8 = 0;
+  static mut SMULTIPLEX: Option<Multiplex> = None;
+  static mut SCHARGER: Charger = Charger::new();
+  // Ticks since the motion sensor was last looked at
+  static mut COUNTER: u32 = 0;
+  // The next compare match lights the tube rather than darkening it, and where the tube goes dark below full brightness
+  static mut LIGHT_PENDING: bool = false;
+  static mut DARK_AT: Option<u32> = None;
+  // STOP modes seen so far, the RTC has to resync after each
+  static mut STOPS: u32 = 0;
+  // RTC timestamp of the reset, moved along when the time is set
+  static mut BOOT: Option<u32> = None;
+  static mut BATTERYFILTER: Filter = Filter::new();
+  static mut BATTERYMONITOR: Monitor = Monitor::new();
+  static mut SHAKE: Shake = Shake::new();
+  // Self test step last reported over USB
+  static mut REPORTED: Option<u32> = None;
+
+  let Multiplex { timer: int, nixie_clock, charge_status, adc, battery_voltage, calibration, settings_store } =
+    SMULTIPLEX.get_or_insert_with(|| {
+      cortex_m::interrupt::free(|cs| {
+        MULTIPLEX.borrow(cs).replace(None).unwrap()
+      })
+    });
 
-  static mut BATTERYVOLTAGE: Option<gpioa::PA0<Analog>> = None;
-  static mut SADC: Option<Adc<>> = None;
+  // The update starts a multiplex period with the tubes dark, compare matches on channel 1 light the next tube after
+  // the blanking dead time and darken it once its on time is over
+  let tim = unsafe { &*TIM14::ptr() };
+  if tim.sr.read().cc1if().bit_is_set() {
+    // The flags clear by writing 0, leave the update flag alone
+    tim.sr.write(|w| unsafe { w.bits(!0x2) });
+    if tim.sr.read().uif().bit_is_clear() {
+      match (*LIGHT_PENDING, *DARK_AT) {
+        (true, Some(dark_at)) => {
+          nixie_clock.light();
+          tim.ccr1.write(|w| unsafe { w.bits(dark_at) });
+        }
+        (true, None) => {
+          nixie_clock.light();
+          tim.dier.modify(|_, w| w.cc1ie().clear_bit());
+        }
+        (false, _) => {
+          nixie_clock.blank();
+          tim.dier.modify(|_, w| w.cc1ie().clear_bit());
+        }
+      }
+      *LIGHT_PENDING = false;
+      return;
+    }
+  }
 
-  let int = INT.get_or_insert_with(|| {
-    cortex_m::interrupt::free(|cs| {
-      GINT.borrow(cs).replace(None).unwrap()
-    })
-  });
+  let mut settings = cortex_m::interrupt::free(|cs| SETTINGS.borrow(cs).borrow().unwrap());
 
-  let nixie_clock = NIXIEDISPLAY.get_or_insert_with(|| {
-    cortex_m::interrupt::free(|cs| {
-      NIXIE_DISPLAY.borrow(cs).replace(None).unwrap()
-    })
+  let (mode, stops) = cortex_m::interrupt::free(|cs| {
+    let power = POWER.borrow(cs).borrow();
+    (power.mode(), power.stops())
   });
+  if stops != *STOPS {
+    *STOPS = stops;
+    nixie_clock.time_source().resync();
+  }
 
-  let charge_status = CHARGESTATUS.get_or_insert_with(|| {
-    cortex_m::interrupt::free(|cs| {
-      CHARGE_STATUS.borrow(cs).replace(None).unwrap()
-    })
-  });
+  nixie_clock.tick();
+  let period = tim.arr.read().bits() + 1;
+  let dead_time = (settings.blanking_us as u32 * SYSCLK_MHZ / (tim.psc.read().bits() + 1)).min(period / 2);
+  let on_time = nixie_clock.on_time() as u32;
+  let dark_at = dead_time + (period - dead_time) * on_time / BRIGHTNESS_LEVELS as u32;
+  *DARK_AT = if on_time < BRIGHTNESS_LEVELS as u32 { Some(dark_at) } else { None };
+  *LIGHT_PENDING = dead_time > 0;
+  tim.ccr1.write(|w| unsafe { w.bits(if *LIGHT_PENDING { dead_time } else { dark_at }) });
+  tim.dier.modify(|_, w| w.cc1ie().bit(nixie_clock.is_hv_on() && (*LIGHT_PENDING || DARK_AT.is_some())));
+  let before = nixie_clock.now().timestamp();
+  let boot = BOOT.get_or_insert(before);
+
+  let sync = if TIMESTAMP_SYNC.load(Ordering::Relaxed) {
+    TIMESTAMP_SYNC.store(false, Ordering::Relaxed);
+    Some(DateTime::from_timestamp(TIMESTAMP.load(Ordering::Relaxed)))
+  } else if TIME_SYNC.load(Ordering::Relaxed) {
+    TIME_SYNC.store(false, Ordering::Relaxed);
+    Time::new(HOURS.load(Ordering::Relaxed), MINUTES.load(Ordering::Relaxed), SECONDS.load(Ordering::Relaxed))
+      .map(|time| nixie_clock.now().nearest_with_time(time))
+  } else {
+    None
+  };
+
+  if let Some(actual) = sync {
+    // Set the clock first, the second starts now and writing the flash below can take a while
+    let clock = nixie_clock.now();
+    nixie_clock.set_date_time(actual);
+    if let Some(trim_ppm) = calibration.time_set(clock.timestamp(), actual.timestamp()) {
+      nixie_clock.time_source().set_trim(trim_ppm);
+      settings.trim_ppm = trim_ppm;
+      cortex_m::interrupt::free(|cs| *SETTINGS.borrow(cs).borrow_mut() = Some(settings));
+      settings.save(settings_store).ok();
+    }
+    save_calibration(nixie_clock.time_source(), calibration);
+  } else if TIME_SET.load(Ordering::Relaxed) {
+    TIME_SET.store(false, Ordering::Relaxed);
+    nixie_clock.set_time(HOURS.load(Ordering::Relaxed), MINUTES.load(Ordering::Relaxed));
+    calibration.time_changed();
+    save_calibration(nixie_clock.time_source(), calibration);
+  } else {
+    let time = nixie_clock.now().time;
+    HOURS.store(time.hours(), Ordering::Relaxed);
+    MINUTES.store(time.minutes(), Ordering::Relaxed);
+    SECONDS.store(time.seconds(), Ordering::Relaxed);
+  }
 
-  let adc = SADC.get_or_insert_with(|| {
-    cortex_m::interrupt::free(|cs| {
-      ADC.borrow(cs).replace(None).unwrap()
-    })
-  });
+  if SETTINGS_CHANGED.load(Ordering::Relaxed) {
+    SETTINGS_CHANGED.store(false, Ordering::Relaxed);
+    settings.save(settings_store).ok();
+    apply_settings(nixie_clock, &settings);
+    if settings.trim_ppm != calibration.trim_ppm() {
+      *calibration = Calibration::new(settings.trim_ppm, calibration.last_set());
+      nixie_clock.time_source().set_trim(calibration.trim_ppm());
+    }
+  }
 
+  if DATE_SET.load(Ordering::Relaxed) {
+    DATE_SET.store(false, Ordering::Relaxed);
+    if let Some(date) = Date::new(YEAR.load(Ordering::Relaxed), MONTH.load(Ordering::Relaxed), DAY.load(Ordering::Relaxed)) {
+      nixie_clock.set_date(date);
+      calibration.time_changed();
+      save_calibration(nixie_clock.time_source(), calibration);
+    }
+  } else {
+    let date = nixie_clock.get_date();
+    YEAR.store(date.year(), Ordering::Relaxed);
+    MONTH.store(date.month(), Ordering::Relaxed);
+    DAY.store(date.day(), Ordering::Relaxed);
+  }
 
-  let battery_voltage = BATTERYVOLTAGE.get_or_insert_with(|| {
-    cortex_m::interrupt::free(|cs| {
-      BATTERY_VOLTAGE.borrow(cs).replace(None).unwrap()
-    })
-  });
+  let now = nixie_clock.now().timestamp();
+  if sync.is_some() || now < before || now > before + 1 {
+    // The time was set, keep the uptime going
+    *boot = boot.wrapping_add(now.wrapping_sub(before));
+  }
+  UPTIME.store(now.wrapping_sub(*boot), Ordering::Relaxed);
+  SINCE_SET.store(calibration.elapsed(now), Ordering::Relaxed);
 
-  nixie_clock.tick();
-  if TIME_SET.load(Ordering::Relaxed) {
-    TIME_SET.store(false, Ordering::Relaxed);
-    nixie_clock.set_time(HOURS.load(Ordering::Relaxed), MINUTES.load(Ordering::Relaxed))
-  } else {
-    let (hours, minutes) = nixie_clock.get_time();
-    HOURS.store(hours, Ordering::Relaxed);
-    MINUTES.store(minutes, Ordering::Relaxed);
+  if SHOW_DATE.load(Ordering::Relaxed) {
+    SHOW_DATE.store(false, Ordering::Relaxed);
+    nixie_clock.show_date();
+  }
+  if let Some(text) = cortex_m::interrupt::free(|cs| MESSAGE.borrow(cs).take()) {
+    nixie_clock.show_message(text);
   }
 
+  let uptime = UPTIME.load(Ordering::Relaxed);
+  let power_change = SCHARGER.update(charge_status.read(), uptime, BATTERY_MV.load(Ordering::Relaxed));
+  let exercise = EXERCISE.load(Ordering::Relaxed);
+  EXERCISE.store(false, Ordering::Relaxed);
+  if exercise || (power_change == Some(Power::Charging) && settings.exercise_on_charge) {
+    nixie_clock.exercise();
+  } else if power_change == Some(Power::Charging) {
+    nixie_clock.show_charging();
+  }
+  if exercise || power_change == Some(Power::Charging) {
+    nixie_clock.wear().save(settings_store).ok();
+  }
+  if SELF_TEST.load(Ordering::Relaxed) {
+    SELF_TEST.store(false, Ordering::Relaxed);
+    nixie_clock.self_test();
+  }
+  let step = nixie_clock.self_test_step();
+  if step != *REPORTED {
+    *REPORTED = step;
+    cortex_m::interrupt::free(|cs| self_test::report(USB_SERIAL.borrow(cs).borrow_mut().output(), step));
+    NVIC::pend(Interrupt::USB);
+  }
+  cortex_m::interrupt::free(|cs| {
+    CHARGER.borrow(cs).set((SCHARGER.power(), SCHARGER.session()));
+    let mut energy = ENERGY.borrow(cs).borrow_mut();
+    if power_change == Some(Power::Battery) {
+      energy.restart(uptime);
+    }
+    energy.tick(nixie_clock.is_display_on(), nixie_clock.is_hv_on(), USB_CONNECTED.load(Ordering::Relaxed),
+      SCHARGER.power() != Power::Battery);
+  });
+
   if nixie_clock.is_display_on() == false {
-    let mut battery_charge: u16 = adc.read(battery_voltage).unwrap();
-    if battery_charge < 2100 {battery_charge = 2100};
-    battery_charge = battery_charge - 2100; // Voltage is halved as the input is 3.3V max. 3.6 to 4.2V becomes 1.8 to 2.1. Remove offset.
-    battery_charge = battery_charge / 4; // 0 to 300mV is around 0 to 400.
-    if battery_charge >= 100 {battery_charge = 100};
-    nixie_clock.set_charge_level(battery_charge as u8);
-    BATTERY_STATUS.store(battery_charge as u8, Ordering::Relaxed);
-    BATTERY_CHARGE_DONE.store(charge_status.is_high().unwrap(), Ordering::Relaxed);
+    // Voltage is halved as the input is 3.3V max, VREFINT right after it tells what VDDA was
+    let reading: u16 = adc.read(battery_voltage).unwrap();
+    let vrefint: u16 = adc.read(&mut VRef).unwrap();
+    let millivolts = BATTERYFILTER.update(battery::millivolts(reading, vrefint, unsafe { *VREFINT_CAL }));
+    let battery_charge = battery::LIPO.scaled(settings.battery_empty_mv, settings.battery_full_mv).percent(millivolts);
+    let state = BATTERYMONITOR.update(battery_charge, SCHARGER.power(), settings.battery_low, settings.battery_critical);
+    nixie_clock.set_charge_level(battery_charge);
+    nixie_clock.set_battery_state(state);
+    BATTERY_STATUS.store(battery_charge, Ordering::Relaxed);
+    BATTERY_MV.store(millivolts, Ordering::Relaxed);
   }
 
 
-  *COUNTER = *COUNTER + 1;
-  if *COUNTER > 50 {
+  *COUNTER += 1;
+  if *COUNTER >= TICKS_PER_SECOND.load(Ordering::Relaxed) / MOTION_CHECKS_PER_SECOND || mode != Mode::Running {
     *COUNTER = 0;
+    cortex_m::interrupt::free(|cs| WEAR.borrow(cs).set(nixie_clock.wear()));
+
+    let moved = MOVEMENT_DETECTED.load(Ordering::Relaxed);
+    MOVEMENT_DETECTED.store(false, Ordering::Relaxed);
+    // Shaking the watch for a while starts the self test, movement during it leaves it running
+    if SHAKE.update(moved) && step.is_none() {
+      nixie_clock.self_test();
+    } else if moved && step.is_none() {
+      WAKES.store(WAKES.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+      nixie_clock.wake_up();
+    }
+  }
 
-
-    if MOVEMENT_DETECTED.load(Ordering::Relaxed) {
-      MOVEMENT_DETECTED.store(false, Ordering::Relaxed);
-      if BATTERY_STATUS.load(Ordering::Relaxed) > 10 {
-        nixie_clock.show_time_and_charge();
-      } else {
-        nixie_clock.show_empty();
-      }
+  // Only keep the multiplex timer running while it has something to do, other interrupts pend TIM14 to wake it
+  // While on USB power the charger is watched for the end of the charge or unplugging
+  let activity = Activity {
+    display_on: nixie_clock.is_display_on(),
+    usb_connected: USB_CONNECTED.load(Ordering::Relaxed),
+    charger_busy: SCHARGER.power() != Power::Battery || SCHARGER.is_settling(),
+  };
+  match cortex_m::interrupt::free(|cs| POWER.borrow(cs).borrow_mut().update(activity)) {
+    Some(Mode::Running) => {
+      int.start(Hertz(TICKS_PER_SECOND.load(Ordering::Relaxed)));
+      int.listen(Event::TimeOut);
     }
+    Some(_) => {
+      int.unlisten(Event::TimeOut);
+      // The HAL has no way to stop a timer
+      unsafe { (*TIM14::ptr()).cr1.modify(|_, w| w.cen().clear_bit()) };
+    }
+    None => {}
   }
 
   int.wait().ok();
 }
 
+/// Hands the clock the settings it takes while running, the multiplex rate only counts from a reset.
+fn apply_settings(nixie_clock: &mut NixieClock<OPIN, Rtc>, settings: &Settings) {
+  nixie_clock.set_display_duration(settings.display_duration as u32);
+  nixie_clock.set_wake_sequence(settings.sequence);
+  nixie_clock.set_scroll_step(settings.scroll_step_ms as u32);
+  nixie_clock.set_brightness(settings.brightness, settings.brightness_low);
+  nixie_clock.set_blanking(settings.blanking_us > 0);
+  nixie_clock.set_exercise_duration(settings.exercise_s as u32);
+  nixie_clock.set_twelve_hour(settings.am_pm());
+}
+
+fn save_calibration(rtc: &mut Rtc, calibration: &Calibration) {
+  rtc.write_backup(BACKUP_LAST_SET, calibration.last_set());
+}
+
 #[interrupt]
 fn USB() {
   cortex_m::interrupt::free(|cs| {
-    if let (&mut Some(ref mut usb_serial), ) = (
-      USB_SERIAL.borrow(cs).borrow_mut().deref_mut(),
-    ) {
-      if usb_serial.handle(HOURS.load(Ordering::Relaxed), MINUTES.load(Ordering::Relaxed),
-                                      BATTERY_STATUS.load(Ordering::Relaxed), BATTERY_CHARGE_DONE.load(Ordering::Relaxed)) {
-        let (hours, minutes) = usb_serial.get_time();
-        HOURS.store(hours, Ordering::Relaxed);
-        MINUTES.store(minutes, Ordering::Relaxed);
-        TIME_SET.store(true, Ordering::Relaxed);
+    let mut usb_serial = USB_SERIAL.borrow(cs).borrow_mut();
+    {
+      let mut settings = SETTINGS.borrow(cs).borrow_mut();
+      let charger = CHARGER.borrow(cs).get();
+      let uptime = UPTIME.load(Ordering::Relaxed);
+      let energy = ENERGY.borrow(cs).borrow().usage(uptime, TICKS_PER_SECOND.load(Ordering::Relaxed),
+        &settings.unwrap_or_default().currents());
+      let status = Status {
+        time: Time::new(HOURS.load(Ordering::Relaxed), MINUTES.load(Ordering::Relaxed), SECONDS.load(Ordering::Relaxed)).unwrap_or_default(),
+        date: Date::new(YEAR.load(Ordering::Relaxed), MONTH.load(Ordering::Relaxed), DAY.load(Ordering::Relaxed)).unwrap_or_default(),
+        battery_status: BATTERY_STATUS.load(Ordering::Relaxed),
+        battery_mv: BATTERY_MV.load(Ordering::Relaxed),
+        power: charger.0,
+        charge_session: charger.1,
+        since_set: SINCE_SET.load(Ordering::Relaxed),
+        uptime,
+        wakes: WAKES.load(Ordering::Relaxed),
+        energy,
+        wear: WEAR.borrow(cs).get(),
+        tx_dropped: usb_serial.tx_dropped(),
+        board_revision: firmware::board_revision(),
+        image_crc: IMAGE_CRC.load(Ordering::Relaxed),
+        settings: settings.unwrap_or_default(),
+        firmware_version: env!("CARGO_PKG_VERSION"),
+      };
+      usb_serial.handle(&status, |request| match request {
+        Request::SetTime(hours, minutes) => {
+          HOURS.store(hours, Ordering::Relaxed);
+          MINUTES.store(minutes, Ordering::Relaxed);
+          TIME_SET.store(true, Ordering::Relaxed);
+          NVIC::pend(Interrupt::TIM14);
+        }
+        Request::SyncTime(time) => {
+          HOURS.store(time.hours(), Ordering::Relaxed);
+          MINUTES.store(time.minutes(), Ordering::Relaxed);
+          SECONDS.store(time.seconds(), Ordering::Relaxed);
+          TIME_SYNC.store(true, Ordering::Relaxed);
+          // Set the time right away rather than on the next tick
+          NVIC::pend(Interrupt::TIM14);
+        }
+        Request::SyncDateTime(now) => {
+          TIMESTAMP.store(now.timestamp(), Ordering::Relaxed);
+          TIMESTAMP_SYNC.store(true, Ordering::Relaxed);
+          NVIC::pend(Interrupt::TIM14);
+        }
+        Request::SetDate(date) => {
+          YEAR.store(date.year(), Ordering::Relaxed);
+          MONTH.store(date.month(), Ordering::Relaxed);
+          DAY.store(date.day(), Ordering::Relaxed);
+          DATE_SET.store(true, Ordering::Relaxed);
+        }
+        Request::ShowDate => SHOW_DATE.store(true, Ordering::Relaxed),
+        Request::Exercise => {
+          EXERCISE.store(true, Ordering::Relaxed);
+          NVIC::pend(Interrupt::TIM14);
+        }
+        Request::SelfTest => {
+          SELF_TEST.store(true, Ordering::Relaxed);
+          NVIC::pend(Interrupt::TIM14);
+        }
+        Request::ShowMessage(text) => {
+          MESSAGE.borrow(cs).set(Some(text));
+          NVIC::pend(Interrupt::TIM14);
+        }
+        Request::Set(key, value) => {
+          if let Some(settings) = settings.as_mut() {
+            settings.set(key, value);
+            SETTINGS_CHANGED.store(true, Ordering::Relaxed);
+          }
+        }
+        Request::Bootloader => ENTER_BOOTLOADER.store(true, Ordering::Relaxed),
+      });
+
+      let connected = usb_serial.is_connected();
+      if connected && !USB_CONNECTED.load(Ordering::Relaxed) {
+        NVIC::pend(Interrupt::TIM14);
       }
+      USB_CONNECTED.store(connected, Ordering::Relaxed);
+      POWER.borrow(cs).borrow_mut().set_usb_connected(connected);
     }
   });
 }
@@ -198,16 +526,24 @@ fn main() -> ! {
 
       let rcc = p.RCC;
       rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+      let rtc_clock = Rtc::enable_clock(&rcc, &p.PWR);
 
       let mut rcc = rcc
         .configure()
         .hse(12.mhz(), stm32f0xx_hal::rcc::HSEBypassMode::NotBypassed)
         .enable_crs(p.CRS)
-        .sysclk(12.mhz())
-        .pclk(12.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .pclk(SYSCLK_MHZ.mhz())
         .usbsrc(stm32f0xx_hal::rcc::USBClockSource::HSI48)
         .freeze(&mut p.FLASH);
 
+      let settings_store = SettingsStore::new(SettingsFlash::new(p.FLASH));
+      let settings = Settings::load(&settings_store);
+      TICKS_PER_SECOND.store(settings.multiplex_rate as u32, Ordering::Relaxed);
+      HOURS.store(settings.start_time.hours(), Ordering::Relaxed);
+      MINUTES.store(settings.start_time.minutes(), Ordering::Relaxed);
+      IMAGE_CRC.store(firmware::crc(), Ordering::Relaxed);
+
 
       let gpioa = p.GPIOA.split(&mut rcc);
       let gpiob = p.GPIOB.split(&mut rcc);
@@ -220,6 +556,15 @@ fn main() -> ! {
       exti.imr.modify(|_, w| w.mr4().set_bit());
       exti.rtsr.modify(|_, w| w.tr4().set_bit());
 
+      // The charger pulls its STAT pin PA1 low once it gets USB power and starts charging
+      syscfg.exticr1.modify(|_, w| unsafe { w.exti1().bits(0) });
+      exti.imr.modify(|_, w| w.mr1().set_bit());
+      exti.ftsr.modify(|_, w| w.tr1().set_bit());
+
+      // The RTC alarm is on EXTI line 17, which is what wakes the core from STOP mode
+      exti.imr.modify(|_, w| w.mr17().set_bit());
+      exti.rtsr.modify(|_, w| w.tr17().set_bit());
+
       let mut nixie1 = gpioa.pa8.into_push_pull_output(cs);
       let mut nixie2 = gpioa.pa9.into_push_pull_output(cs);
 
@@ -234,7 +579,7 @@ fn main() -> ! {
       
       let mut hv_enable = gpioa.pa2.into_push_pull_output(cs);
 
-      let charge_status = gpioa.pa1.into_floating_input(cs);
+      let charge_status = StatPin::new(gpioa.pa1.into_pull_up_input(cs));
 
       //initial states
       nixie_dp.set_low().ok();
@@ -259,11 +604,20 @@ fn main() -> ! {
         nixie_dp.downgrade(),
         hv_enable.downgrade(),
       );
-      let nixie_clock = NixieClock::new(
+      let start = DateTime::new(Date::default(), settings.start_time);
+      let mut rtc = Rtc::new(p.RTC, rtc_clock, start);
+      let calibration = Calibration::new(settings.trim_ppm, rtc.read_backup(BACKUP_LAST_SET));
+      rtc.set_trim(calibration.trim_ppm());
+      rtc.enable_minute_alarm();
+      // Written once before interrupts run, so no critical section is needed
+      static mut NIXIE_CLOCK: MaybeUninit<NixieClock<OPIN, Rtc>> = MaybeUninit::uninit();
+      let nixie_clock = unsafe { NIXIE_CLOCK.write(NixieClock::new(
         nixie_display,
-        200
-      );
-      *NIXIE_DISPLAY.borrow(cs).borrow_mut() = Some(nixie_clock);
+        rtc,
+        settings.multiplex_rate as u32
+      )) };
+      apply_settings(nixie_clock, &settings);
+      nixie_clock.set_wear(Wear::load(&settings_store));
 
       //setup i2c for the gyro
       let sda = gpiob.pb7.into_alternate_af1(cs);
@@ -282,24 +636,28 @@ fn main() -> ! {
       mpu.write_bit(0x6C, 2, true).unwrap();
       *MPU.borrow(cs).borrow_mut() = Some(mpu);
 
-      // Set up a timer for 200Hz interrupts
-      let mut timer = Timer::tim14(p.TIM14, Hertz(200), &mut rcc);
+      // Set up a timer for the multiplex interrupts, 200Hz by default
+      let mut timer = Timer::tim14(p.TIM14, Hertz(settings.multiplex_rate as u32), &mut rcc);
       // Generate an interrupt when the timer expires
       timer.listen(Event::TimeOut);
 
       // Init the ADC
-      let adc = Adc::new(p.ADC, &mut rcc);
+      let mut adc = Adc::new(p.ADC, &mut rcc);
+      VRef::new().enable(&mut adc);
       let battery_voltage = gpioa.pa0.into_analog(cs);
-      *ADC.borrow(cs).borrow_mut() = Some(adc);
-      *BATTERY_VOLTAGE.borrow(cs).borrow_mut() = Some(battery_voltage);
 
-      // Move the timer into our global storage
-      *GINT.borrow(cs).borrow_mut() = Some(timer);
+      // Move the timer and what it drives into our global storage
+      *MULTIPLEX.borrow(cs).borrow_mut() = Some(Multiplex {
+        timer,
+        nixie_clock,
+        charge_status,
+        adc,
+        battery_voltage,
+        calibration,
+        settings_store,
+      });
 
-      *EINT.borrow(cs).borrow_mut() = Some(exti);
-
-      // Charge pin move
-      *CHARGE_STATUS.borrow(cs).borrow_mut() = Some(charge_status);
+      *SETTINGS.borrow(cs).borrow_mut() = Some(settings);
 
 
       // Enable TIM14 IRQ, set prio 1 and clear any pending IRQs
@@ -308,13 +666,19 @@ fn main() -> ! {
         nvic.set_priority(Interrupt::TIM14, 16);
         nvic.set_priority(Interrupt::USB, 32);
         nvic.set_priority(Interrupt::EXTI4_15, 1);
+        nvic.set_priority(Interrupt::EXTI0_1, 1);
+        nvic.set_priority(Interrupt::RTC, 1);
         cortex_m::peripheral::NVIC::unmask(Interrupt::TIM14);
         cortex_m::peripheral::NVIC::unmask(Interrupt::USB);
-        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI4_15)
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI4_15);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI0_1);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::RTC)
       }
       cortex_m::peripheral::NVIC::unpend(Interrupt::TIM14);
       cortex_m::peripheral::NVIC::unpend(Interrupt::USB);
       cortex_m::peripheral::NVIC::unpend(Interrupt::EXTI4_15);
+      cortex_m::peripheral::NVIC::unpend(Interrupt::EXTI0_1);
+      cortex_m::peripheral::NVIC::unpend(Interrupt::RTC);
 
       let usb = Peripheral {
         usb: p.USB,
@@ -322,14 +686,29 @@ fn main() -> ! {
         pin_dp: gpioa.pa12,
       };
 
-      let mut usb_serial = UsbSerial::new();
-      usb_serial.init(usb);
-      *USB_SERIAL.borrow(cs).borrow_mut() = Some(usb_serial);
+      USB_SERIAL.borrow(cs).borrow_mut().init(usb);
 
     });
   }  
 
   loop {
-    wfi();
+    // With interrupts disabled the core still wakes up, but the handler only runs once the clocks are back
+    let stopped = cortex_m::interrupt::free(|cs| {
+      let stop = POWER.borrow(cs).borrow_mut().stop();
+      if stop {
+        power::stop();
+        POWER.borrow(cs).borrow_mut().wake();
+        ENERGY.borrow(cs).borrow_mut().wake();
+      }
+      stop
+    });
+    if !stopped {
+      wfi();
+    }
+    if ENTER_BOOTLOADER.load(Ordering::Relaxed) {
+      // Give the host a few USB frames to read the OK, at the 12MHz system clock
+      cortex_m::asm::delay(12_000 * 20);
+      firmware::enter_dfu();
+    }
   }
 }
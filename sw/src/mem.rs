@@ -0,0 +1,43 @@
+//! `memcpy`, `memmove` and `memset` in place of the ones of `compiler_builtins`, see
+//! `nixiewatch_core::mem` for why and why they are correct.
+//!
+//! On thumbv6m LLVM only calls the `__aeabi_` names, and the linker takes the ones here over those of
+//! `compiler_builtins`, so they are the only copies in the image. Unlike C's `memset`, `__aeabi_memset`
+//! takes the length before the value.
+
+use nixiewatch_core::mem::{copy, fill};
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memmove(dest: *mut u8, src: *const u8, n: usize) {
+    copy(dest, src, n);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memcpy(dest: *mut u8, src: *const u8, n: usize) {
+    copy(dest, src, n);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memcpy4(dest: *mut u8, src: *const u8, n: usize) {
+    copy(dest, src, n);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memset(dest: *mut u8, n: usize, c: i32) {
+    fill(dest, n, c as u8);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memset4(dest: *mut u8, n: usize, c: i32) {
+    fill(dest, n, c as u8);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memclr(dest: *mut u8, n: usize) {
+    fill(dest, n, 0);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __aeabi_memclr4(dest: *mut u8, n: usize) {
+    fill(dest, n, 0);
+}
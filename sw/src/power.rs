@@ -0,0 +1,28 @@
+//! STOP mode, see `nixiewatch_core::power` for when the watch enters it.
+
+use cortex_m::{asm, peripheral::SCB};
+use stm32f0xx_hal::pac::{PWR, RCC};
+
+/// SLEEPDEEP in the system control register turns the next WFI into STOP mode
+const SCB_SCR_SLEEPDEEP: u32 = 1 << 2;
+
+/// Stops all clocks but the RTC until an EXTI line fires, then brings back the clocks `main` configured.
+/// Has to be called with interrupts disabled, otherwise the handler of the interrupt that woke
+/// the core would run on the 8MHz HSI.
+pub fn stop() {
+    let (pwr, rcc, scb) = unsafe { (&*PWR::ptr(), &*RCC::ptr(), &*SCB::PTR) };
+    // STOP rather than STANDBY, with the regulator in low power mode
+    pwr.cr.modify(|_, w| w.pdds().clear_bit().lpds().set_bit());
+    unsafe { scb.scr.modify(|scr| scr | SCB_SCR_SLEEPDEEP) };
+    asm::wfi();
+    unsafe { scb.scr.modify(|scr| scr & !SCB_SCR_SLEEPDEEP) };
+
+    // The core wakes up on the HSI, with the HSE and the other oscillators off
+    rcc.cr.modify(|_, w| w.hseon().set_bit());
+    while rcc.cr.read().hserdy().bit_is_clear() {}
+    rcc.cfgr.modify(|_, w| w.sw().hse());
+    while !rcc.cfgr.read().sws().is_hse() {}
+    // HSI48 clocks the USB, HSI14 the ADC
+    rcc.cr2.modify(|_, w| w.hsi48on().set_bit().hsi14on().set_bit());
+    while rcc.cr2.read().hsi48rdy().bit_is_clear() || rcc.cr2.read().hsi14rdy().bit_is_clear() {}
+}
@@ -0,0 +1,200 @@
+//! The STM32F042 hardware RTC as time source for the clock.
+//!
+//! The RTC runs from a 32.768kHz crystal on the LSE pins (PC14/PC15) when one is fitted and falls
+//! back to the internal ~40kHz LSI otherwise, which keeps time but is only accurate to a few percent.
+//! Both keep running in STOP mode and the RTC lives in the backup domain, so the time survives a reset.
+//! The RTC only stores a two digit year, so dates are limited to 2000 up to 2099.
+
+use stm32f0xx_hal::pac::{PWR, RCC, RTC};
+
+use nixiewatch_core::calendar::{Date, DateTime, Time};
+use nixiewatch_core::time_source::TimeSource;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum RtcClock {
+    Lse,
+    Lsi
+}
+
+/// Busy loop iterations to wait for the LSE to start, a bit over its 2s worst case start up at 12MHz.
+const LSE_TIMEOUT: u32 = 3_000_000;
+
+/// Backup register holding `INIT_MARKER` once `init` has set up the prescalers and the calendar.
+const BACKUP_INIT: usize = 4;
+const INIT_MARKER: u32 = 0x4E49_5845;
+
+pub struct Rtc {
+    rtc: RTC,
+}
+
+fn to_bcd(value: u8) -> u32 {
+    (((value / 10) << 4) | (value % 10)) as u32
+}
+
+fn from_bcd(value: u32) -> u8 {
+    ((value >> 4) * 10 + (value & 0xF)) as u8
+}
+
+impl Rtc {
+    /// Enables the backup domain and starts the RTC clock, returning which oscillator it runs from.
+    /// Needs the raw RCC registers, so it has to be called before the RCC is configured.
+    pub fn enable_clock(rcc: &RCC, pwr: &PWR) -> RtcClock {
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+        pwr.cr.modify(|_, w| w.dbp().set_bit());
+
+        if rcc.bdcr.read().rtcen().bit_is_set() {
+            // Still running from before the reset. The LSI is not part of the backup domain though.
+            if rcc.bdcr.read().rtcsel().is_lse() {
+                return RtcClock::Lse;
+            }
+            Self::enable_lsi(rcc);
+            return RtcClock::Lsi;
+        }
+
+        rcc.bdcr.modify(|_, w| w.lseon().set_bit());
+        let mut timeout = LSE_TIMEOUT;
+        while rcc.bdcr.read().lserdy().bit_is_clear() && timeout > 0 {
+            timeout -= 1;
+        }
+
+        let clock = if rcc.bdcr.read().lserdy().bit_is_set() {
+            rcc.bdcr.modify(|_, w| w.rtcsel().lse());
+            RtcClock::Lse
+        } else {
+            rcc.bdcr.modify(|_, w| w.lseon().clear_bit());
+            Self::enable_lsi(rcc);
+            rcc.bdcr.modify(|_, w| w.rtcsel().lsi());
+            RtcClock::Lsi
+        };
+        rcc.bdcr.modify(|_, w| w.rtcen().set_bit());
+        clock
+    }
+
+    fn enable_lsi(rcc: &RCC) {
+        rcc.csr.modify(|_, w| w.lsion().set_bit());
+        while rcc.csr.read().lsirdy().bit_is_clear() {}
+    }
+
+    /// Takes over the RTC, starting it at `start` unless it kept running through a reset.
+    pub fn new(rtc: RTC, clock: RtcClock, start: DateTime) -> Self {
+        let mut rtc = Rtc { rtc };
+        // ISR.INITS can't tell, it stays clear while the year is 00 (RM0091, RTC_ISR). The backup registers
+        // are only cleared by a backup domain reset, the same one that stops the RTC (RM0091, RTC backup
+        // registers), so a marker in one of them survives exactly as long as the calendar does.
+        if rtc.read_backup(BACKUP_INIT) != INIT_MARKER {
+            rtc.init(clock, start);
+        }
+        rtc
+    }
+
+    fn init(&mut self, clock: RtcClock, start: DateTime) {
+        // Asynchronous and synchronous prescaler that divide the clock down to 1Hz
+        let (prediv_a, prediv_s) = match clock {
+            RtcClock::Lse => (127, 255),
+            RtcClock::Lsi => (99, 399),
+        };
+        self.enter_init_mode();
+        self.rtc.prer.write(|w| unsafe { w.prediv_s().bits(prediv_s) });
+        self.rtc.prer.modify(|_, w| unsafe { w.prediv_a().bits(prediv_a) });
+        self.write_date_time(start);
+        self.exit_init_mode();
+        self.write_backup(BACKUP_INIT, INIT_MARKER);
+    }
+
+    fn enter_init_mode(&mut self) {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        self.rtc.isr.modify(|_, w| w.init().set_bit());
+        while self.rtc.isr.read().initf().bit_is_clear() {}
+    }
+
+    /// RSF is write protected as well, so it is cleared and waited for before locking the registers again.
+    /// Reads before that could still return the shadow registers from before the init mode.
+    fn exit_init_mode(&mut self) {
+        self.rtc.isr.modify(|_, w| w.init().clear_bit().rsf().clear_bit());
+        while self.rtc.isr.read().rsf().bit_is_clear() {}
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+    }
+
+    /// Raises alarm A at the start of every minute. Through EXTI line 17 it wakes the watch from
+    /// STOP mode, to keep an eye on the battery.
+    pub fn enable_minute_alarm(&mut self) {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        self.rtc.cr.modify(|_, w| w.alrae().clear_bit());
+        while self.rtc.isr.read().alrawf().bit_is_clear() {}
+        // Only the seconds have to match, at 00
+        self.rtc.alrmar.write(|w| w.msk4().set_bit().msk3().set_bit().msk2().set_bit().msk1().clear_bit());
+        self.rtc.cr.modify(|_, w| w.alrae().set_bit().alraie().set_bit());
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+    }
+
+    /// The shadow registers are stale after STOP mode, `now` waits until they are refreshed.
+    pub fn resync(&mut self) {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        self.rtc.isr.modify(|_, w| w.rsf().clear_bit());
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+    }
+
+    /// One of the five 32 bit backup registers, these keep their value through a reset. The last one
+    /// belongs to the RTC itself.
+    pub fn read_backup(&self, index: usize) -> u32 {
+        self.rtc.bkpr[index].read().bits()
+    }
+
+    pub fn write_backup(&mut self, index: usize, value: u32) {
+        self.rtc.bkpr[index].write(|w| unsafe { w.bits(value) });
+    }
+
+    fn write_date_time(&mut self, now: DateTime) {
+        let time = (to_bcd(now.time.hours()) << 16) | (to_bcd(now.time.minutes()) << 8) | to_bcd(now.time.seconds());
+        let date = (to_bcd((now.date.year() % 100) as u8) << 16)
+            | ((now.date.weekday().number() as u32) << 13)
+            | (to_bcd(now.date.month()) << 8)
+            | to_bcd(now.date.day());
+        self.rtc.tr.write(|w| unsafe { w.bits(time) });
+        self.rtc.dr.write(|w| unsafe { w.bits(date) });
+    }
+}
+
+impl TimeSource for Rtc {
+    fn now(&mut self) -> DateTime {
+        // The shadow registers are refreshed every two RTC clock cycles, wait for that after a wake up.
+        while self.rtc.isr.read().rsf().bit_is_clear() {}
+        // Reading TR locks the shadow DR until it has been read as well
+        let time = self.rtc.tr.read().bits();
+        let date = self.rtc.dr.read().bits();
+
+        let time = Time::new(from_bcd((time >> 16) & 0x3F), from_bcd((time >> 8) & 0x7F), from_bcd(time & 0x7F));
+        let date = Date::new(2000 + from_bcd((date >> 16) & 0xFF) as u16, from_bcd((date >> 8) & 0x1F), from_bcd(date & 0x3F));
+        DateTime::new(date.unwrap_or_default(), time.unwrap_or_default())
+    }
+
+    /// Leaving the init mode restarts the prescalers as well, so the second starts
+    /// four RTC clock cycles after this returns.
+    fn set(&mut self, now: DateTime) {
+        if now.date.year() < 2000 || now.date.year() > 2099 {
+            return;
+        }
+        self.enter_init_mode();
+        self.write_date_time(now);
+        self.exit_init_mode();
+    }
+
+    /// Uses the smooth calibration: CALP inserts a pulse every 2^11 clocks, adding 488.5ppm,
+    /// and every step of CALM masks one clock out of 2^20, removing 0.954ppm.
+    fn set_trim(&mut self, ppm: i32) {
+        let steps = ((ppm.abs() * 1_048_576 + 500_000) / 1_000_000) as u16;
+        let (calp, calm) = if ppm > 0 {
+            (true, 512 - steps.min(512))
+        } else {
+            (false, steps.min(511))
+        };
+        while self.rtc.isr.read().recalpf().bit_is_set() {}
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        self.rtc.calr.write(|w| unsafe { w.calp().bit(calp).calm().bits(calm) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+    }
+}
@@ -1,30 +1,37 @@
 extern crate embedded_hal as hal;
 use crate::hal::usb::{UsbBus};
 
+use core::mem::MaybeUninit;
+
 use usb_device::{prelude::*};
 use usbd_serial::{SerialPort, USB_CLASS_CDC};
 
-use numtoa::NumToA;
+use nixiewatch_core::command::{handle_line, LineBuffer, Output, Request, Status, TX_BUFFER_SIZE};
+use nixiewatch_core::ring_buffer::RingBuffer;
 
 static mut USB_BUS: Option<usb_device::bus::UsbBusAllocator<stm32f0xx_hal::usb::UsbBus<stm32f0xx_hal::usb::Peripheral>>> = None;
 
+type Bus = stm32f0xx_hal::usb::UsbBus<stm32f0xx_hal::usb::Peripheral>;
+
 pub struct UsbSerial {
-    hours: u8,
-    minutes: u8,
-    serial: Option<usbd_serial::SerialPort<'static, stm32f0xx_hal::usb::UsbBus<stm32f0xx_hal::usb::Peripheral>>>,
-    device: Option<UsbDevice<'static, stm32f0xx_hal::usb::UsbBus<stm32f0xx_hal::usb::Peripheral>>>
+    /// Only there once `ready` is set by `init`. Not an `Option`, its `None` is not all zeros,
+    /// which would move the whole static from `.bss` to `.data` and cost its size in flash.
+    usb: MaybeUninit<(UsbDevice<'static, Bus>, SerialPort<'static, Bus>)>,
+    ready: bool,
+    line: LineBuffer,
+    /// Replies waiting for the host to read them, so a host that stops reading can't stall the watch
+    tx: RingBuffer<TX_BUFFER_SIZE>
 }
 
 impl UsbSerial {
-    pub fn new() -> Self {
-        let usb_serial = UsbSerial {
-            hours: 0,
-            minutes: 0,
-            serial: None,
-            device: None
-
-        };
-        usb_serial
+    /// Const so the static holding it doesn't need an `Option`, `init` sets up the USB peripheral later.
+    pub const fn new() -> Self {
+        UsbSerial {
+            usb: MaybeUninit::uninit(),
+            ready: false,
+            line: LineBuffer::new(),
+            tx: RingBuffer::new()
+        }
     }
 
     pub fn init(&mut self, usb: stm32f0xx_hal::usb::Peripheral ) {
@@ -33,7 +40,7 @@ impl UsbSerial {
     
             USB_BUS = Some(usb_bus);
       
-            self.serial = Some(SerialPort::new(USB_BUS.as_ref().unwrap()));
+            let serial = SerialPort::new(USB_BUS.as_ref().unwrap());
       
             let usb_dev = UsbDeviceBuilder::new(USB_BUS.as_ref().unwrap(), UsbVidPid(0x16c0, 0x27dd))
                 .manufacturer("FopsCorp")
@@ -42,109 +49,56 @@ impl UsbSerial {
                 .device_class(USB_CLASS_CDC)
                 .build();
       
-            self.device = Some(usb_dev);
+            self.usb.write((usb_dev, serial));
+            self.ready = true;
           }
     }
 
-    fn print_time(&mut self, hours: u8, minutes: u8) {
-        let serial = self.serial.as_mut().unwrap();
-        
-        let mut write_offset = 0;
-        let mut send_buffer: [u8; 6] = [b'0'; 6];
-        hours.numtoa_str(10, &mut send_buffer[0..2]);
-        minutes.numtoa_str(10, &mut send_buffer[3..5]);
-        send_buffer[2] = b':';
-        send_buffer[5] = b'\n';
-
-        while write_offset < send_buffer.len() {
-            match serial.write(&send_buffer[write_offset..send_buffer.len()]) {
-                Ok(len) if len > 0 => {
-                    write_offset += len;
-                }
-                _ => {}
-            }
+    /// Handles whatever the host sent, calling `on_request` for every command the clock has to act on.
+    pub fn handle<F: FnMut(Request)>(&mut self, status: &Status, mut on_request: F) {
+        if !self.ready {
+            return;
         }
-    }
-
-    fn print_battery_status(&mut self, battery_status: u8, charge_status: bool) {
-        let serial = self.serial.as_mut().unwrap();
+        let (usb_dev, serial) = unsafe { self.usb.assume_init_mut() };
 
-        let mut write_offset = 0;
-        let mut send_buffer: [u8; 4] = [b'0'; 4];
-        battery_status.numtoa_str(10, &mut send_buffer[0..3]);
-        send_buffer[3] = b'\n';
+        let mut receive_buffer: [u8; 64]  = [0u8; 64];
 
-        while write_offset < send_buffer.len() {
-            match serial.write(&send_buffer[write_offset..send_buffer.len()]) {
-                Ok(len) if len > 0 => {
-                    write_offset += len;
-                }
-                _ => {}
+        if usb_dev.poll(&mut [serial]) {
+            match serial.read(&mut receive_buffer[..]) {
+                Ok(count) if count > 0 => {
+                    for &byte in &receive_buffer[..count] {
+                        if let Some(line) = self.line.push(byte) {
+                            if let Some(request) = handle_line(line, status, &mut self.tx) {
+                                on_request(request);
+                            }
+                        }
+                    }
+                },
+                Err(UsbError::WouldBlock) => {}// No data received
+                _ => {}// An error occurred
             }
         }
 
-        let send_buffer = if charge_status { "Charged!\n" } else { "Charging\n"};
-        write_offset = 0;
-        while write_offset < send_buffer.len() {
-            match serial.write(&send_buffer.as_bytes()[write_offset..send_buffer.len()]) {
-                Ok(len) if len > 0 => {
-                    write_offset += len;
-                }
-                _ => {}
+        // Hand over whatever the serial port takes now, the rest goes on the next interrupt
+        while !self.tx.is_empty() {
+            match serial.write(self.tx.front()) {
+                Ok(len) if len > 0 => self.tx.consume(len),
+                _ => break
             }
         }
     }
 
-    pub fn handle(&mut self, hours: u8, minutes: u8, battery_status: u8, charge_status: bool) -> bool {
-        let usb_dev = self.device.as_mut().unwrap();
-        let serial = self.serial.as_mut().unwrap();
-
-        let mut receive_buffer: [u8; 64]  = [0u8; 64];
-        let mut time_set = false;
-
-        if !usb_dev.poll(&mut [serial]) {
-            return false;
-        }
-
-        match serial.read(&mut receive_buffer[..]) {
-            Ok(count) if count > 0 => {
-            if receive_buffer.iter().find(| &&x| x == '?' as u8) != None {
-                self.print_time(hours, minutes);
-                self.print_battery_status(battery_status, charge_status);
-            }
-            else if receive_buffer.iter().find(| &&x| x == ':' as u8) != None {
-                if count >= 5 {
-                    self.hours = (receive_buffer[0] - 48) * 10 + receive_buffer[1] - 48;
-                    self.minutes = (receive_buffer[3] - 48) * 10 + receive_buffer[4] - 48;
-                    if self.hours < 24 && self.minutes < 60 {
-                        time_set = true;
-                        self.print_time(self.hours, self.minutes);
-                    }
-                }
-            }
-            },
-            Err(UsbError::WouldBlock) => {}// No data received
-            _ => {}// An error occurred
-        }
-        time_set
+    /// Where to queue lines the clock sends on its own, they go out on the next interrupt.
+    pub fn output(&mut self) -> &mut impl Output {
+        &mut self.tx
     }
 
-    pub fn print(&mut self, string: [u8; 64], length: usize) {
-        let serial = self.serial.as_mut().unwrap();
-        
-        let mut write_offset = 0;
-
-        while write_offset < length {
-            match serial.write(&string[write_offset..length]) {
-                Ok(len) if len > 0 => {
-                    write_offset += len;
-                }
-                _ => {}
-            }
-        }
+    /// Reply bytes dropped because the host didn't read them in time.
+    pub fn tx_dropped(&self) -> u32 {
+        self.tx.dropped()
     }
 
-    pub fn get_time(&mut self) -> (u8, u8) {
-        (self.hours, self.minutes)
+    pub fn is_connected(&self) -> bool {
+        self.ready && unsafe { self.usb.assume_init_ref() }.0.state() == UsbDeviceState::Configured
     }
 }